@@ -0,0 +1,351 @@
+//! Input/output seams of the proxy: where physical events come from, where
+//! virtual events go, and what time it is. The evdev implementations drive
+//! real hardware; the in-memory ones let the proxy logic run in tests and
+//! session replays.
+
+use evdev::uinput::VirtualDevice;
+use evdev::{Device, InputEvent};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Which physical device an event came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// The grabbed mouse, cloned by the virtual device
+    Mouse,
+    /// The optional secondary device (usually a keyboard) that supplies trigger keys
+    TriggerDevice,
+}
+
+/// Source of physical input events
+pub trait EventSource {
+    /// Return the events available right now, or an empty batch if there are none.
+    /// Must not block.
+    fn fetch(&mut self) -> io::Result<Vec<(Origin, InputEvent)>>;
+
+    /// Block until input may be available, `deadline` is reached, or `timeout` has
+    /// passed, whichever comes first. Spurious early returns are allowed.
+    fn wait(&mut self, deadline: Option<Instant>, timeout: Duration) -> io::Result<()>;
+}
+
+/// Destination for the events the proxy produces
+pub trait EventSink {
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()>;
+}
+
+pub trait Clock {
+    fn now(&self) -> Instant;
+
+    /// Wall-clock time, the base of the kernel's input event timestamps
+    fn wall_time(&self) -> SystemTime;
+}
+
+/// Reads from the grabbed event nodes of a mouse, and optionally a trigger
+/// device, opened in non-blocking mode. Waiting is done with epoll on the
+/// device fds plus a timerfd armed for the engine's next deadline, so the
+/// thread sleeps until there is work to do.
+pub struct EvdevSource {
+    devices: Vec<Device>,
+    trigger_device: Option<Device>,
+    epoll: OwnedFd,
+    timer: OwnedFd,
+    /// Readiness buffer for epoll_wait, one slot per registered fd
+    ready: Vec<libc::epoll_event>,
+}
+
+impl EvdevSource {
+    /// Read from all `devices`, which are merged into one stream of mouse events
+    pub fn new(devices: Vec<Device>) -> io::Result<Self> {
+        let epoll = owned_fd(unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) })?;
+        let timer = owned_fd(unsafe {
+            libc::timerfd_create(libc::CLOCK_MONOTONIC, libc::TFD_NONBLOCK | libc::TFD_CLOEXEC)
+        })?;
+
+        for device in &devices {
+            epoll_add(&epoll, device.as_raw_fd())?;
+        }
+        epoll_add(&epoll, timer.as_raw_fd())?;
+
+        Ok(Self {
+            ready: vec![libc::epoll_event { events: 0, u64: 0 }; devices.len() + 1],
+            devices,
+            trigger_device: None,
+            epoll,
+            timer,
+        })
+    }
+
+    /// Also read trigger keys from `device`
+    pub fn with_trigger_device(mut self, device: Device) -> io::Result<Self> {
+        epoll_add(&self.epoll, device.as_raw_fd())?;
+        self.trigger_device = Some(device);
+        self.ready.push(libc::epoll_event { events: 0, u64: 0 });
+        Ok(self)
+    }
+
+    pub fn devices_mut(&mut self) -> &mut [Device] {
+        &mut self.devices
+    }
+
+    pub fn trigger_device_mut(&mut self) -> Option<&mut Device> {
+        self.trigger_device.as_mut()
+    }
+
+    /// Arm the timer to fire once after `after`, or disarm it with `None`
+    fn arm_timer(&self, after: Option<Duration>) -> io::Result<()> {
+        let value = match after {
+            // An all-zero it_value would disarm the timer instead of firing immediately
+            Some(after) => libc::timespec {
+                tv_sec: after.as_secs() as libc::time_t,
+                tv_nsec: after.subsec_nanos().max(1) as libc::c_long,
+            },
+            None => libc::timespec { tv_sec: 0, tv_nsec: 0 },
+        };
+        let spec = libc::itimerspec {
+            it_interval: libc::timespec { tv_sec: 0, tv_nsec: 0 },
+            it_value: value,
+        };
+        let ret = unsafe { libc::timerfd_settime(self.timer.as_raw_fd(), 0, &spec, std::ptr::null_mut()) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Consume a pending expiration so the timer stops reporting readable
+    fn drain_timer(&self) {
+        let mut expirations = 0u64;
+        unsafe {
+            libc::read(
+                self.timer.as_raw_fd(),
+                &mut expirations as *mut u64 as *mut libc::c_void,
+                std::mem::size_of::<u64>(),
+            );
+        }
+    }
+}
+
+impl EventSource for EvdevSource {
+    fn fetch(&mut self) -> io::Result<Vec<(Origin, InputEvent)>> {
+        let mut batch = Vec::new();
+        for device in &mut self.devices {
+            fetch_from(device, Origin::Mouse, &mut batch)?;
+        }
+        if let Some(device) = &mut self.trigger_device {
+            fetch_from(device, Origin::TriggerDevice, &mut batch)?;
+        }
+        Ok(batch)
+    }
+
+    fn wait(&mut self, deadline: Option<Instant>, timeout: Duration) -> io::Result<()> {
+        // epoll_wait only has millisecond resolution, so precise deadlines go through the timerfd
+        let until_deadline = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+        if until_deadline.is_some_and(|d| d.is_zero()) {
+            return Ok(());
+        }
+        self.arm_timer(until_deadline.filter(|d| *d < timeout))?;
+
+        let timeout_ms = timeout.as_millis().clamp(1, i32::MAX as u128) as i32;
+        let ready = unsafe {
+            libc::epoll_wait(self.epoll.as_raw_fd(), self.ready.as_mut_ptr(), self.ready.len() as i32, timeout_ms)
+        };
+        if ready < 0 {
+            let err = io::Error::last_os_error();
+            return if err.kind() == io::ErrorKind::Interrupted { Ok(()) } else { Err(err) };
+        }
+
+        let timer_fd = self.timer.as_raw_fd() as u64;
+        if self.ready[..ready as usize].iter().any(|e| e.u64 == timer_fd) {
+            self.drain_timer();
+        }
+        Ok(())
+    }
+}
+
+fn fetch_from(device: &mut Device, origin: Origin, batch: &mut Vec<(Origin, InputEvent)>) -> io::Result<()> {
+    match device.fetch_events() {
+        Ok(events) => {
+            batch.extend(events.map(|event| (origin, event)));
+            Ok(())
+        }
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+fn owned_fd(fd: RawFd) -> io::Result<OwnedFd> {
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Register `fd` for readability, tagging events with the fd itself
+fn epoll_add(epoll: &OwnedFd, fd: RawFd) -> io::Result<()> {
+    let mut event = libc::epoll_event {
+        events: libc::EPOLLIN as u32,
+        u64: fd as u64,
+    };
+    let ret = unsafe { libc::epoll_ctl(epoll.as_raw_fd(), libc::EPOLL_CTL_ADD, fd, &mut event) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+impl EventSink for VirtualDevice {
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        VirtualDevice::emit(self, events)
+    }
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wall_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+pub use memory::{ManualClock, MemorySink};
+#[cfg(test)]
+pub use memory::ScriptedSource;
+
+mod memory {
+    use super::*;
+    use std::cell::Cell;
+    #[cfg(test)]
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    /// Clock that only moves when told to; clones share the same time
+    #[derive(Clone)]
+    pub struct ManualClock {
+        start: Instant,
+        now: Rc<Cell<Instant>>,
+    }
+
+    impl ManualClock {
+        pub fn new() -> Self {
+            let start = Instant::now();
+            Self {
+                start,
+                now: Rc::new(Cell::new(start)),
+            }
+        }
+
+        pub fn advance(&self, by: Duration) {
+            self.now.set(self.now.get() + by);
+        }
+
+        /// Time since the clock was created
+        pub fn elapsed(&self) -> Duration {
+            self.now.get() - self.start
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            self.now.get()
+        }
+
+        /// Starts at the Unix epoch
+        fn wall_time(&self) -> SystemTime {
+            SystemTime::UNIX_EPOCH + self.elapsed()
+        }
+    }
+
+    /// Replays events at fixed offsets from the clock's start, stamping each
+    /// with its scheduled time the way the kernel stamps real events
+    #[cfg(test)]
+    pub struct ScriptedSource {
+        clock: ManualClock,
+        script: VecDeque<(Duration, Origin, InputEvent)>,
+    }
+
+    #[cfg(test)]
+    impl ScriptedSource {
+        /// Script of mouse events
+        pub fn new(clock: ManualClock, script: Vec<(Duration, InputEvent)>) -> Self {
+            let mut source = Self {
+                clock,
+                script: VecDeque::new(),
+            };
+            for (at, event) in script {
+                source.add(at, Origin::Mouse, event);
+            }
+            source
+        }
+
+        /// Schedule one more event, keeping the script in time order
+        pub fn add(&mut self, at: Duration, origin: Origin, event: InputEvent) {
+            let idx = self.script.partition_point(|(scheduled, _, _)| *scheduled <= at);
+            self.script.insert(idx, (at, origin, event));
+        }
+    }
+
+    #[cfg(test)]
+    impl EventSource for ScriptedSource {
+        fn fetch(&mut self) -> io::Result<Vec<(Origin, InputEvent)>> {
+            let now = self.clock.elapsed();
+            let mut batch = Vec::new();
+            while self.script.front().is_some_and(|(at, _, _)| *at <= now) {
+                batch.extend(self.script.pop_front().map(|(at, origin, event)| (origin, stamp(event, at))));
+            }
+            Ok(batch)
+        }
+
+        /// Jump the clock straight to the next scripted event, deadline or timeout
+        fn wait(&mut self, deadline: Option<Instant>, timeout: Duration) -> io::Result<()> {
+            let now = self.clock.now();
+            let next_event = self.script.front().map(|(at, _, _)| self.clock.start + *at);
+            let target = [next_event, deadline, Some(now + timeout)]
+                .into_iter()
+                .flatten()
+                .min()
+                .unwrap_or(now);
+            if target > now {
+                self.clock.advance(target - now);
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    fn stamp(event: InputEvent, at: Duration) -> InputEvent {
+        let mut raw: libc::input_event = event.into();
+        raw.time = libc::timeval {
+            tv_sec: at.as_secs() as libc::time_t,
+            tv_usec: at.subsec_micros() as libc::suseconds_t,
+        };
+        raw.into()
+    }
+
+    /// Records everything emitted, stamped with the clock's offset
+    pub struct MemorySink {
+        clock: ManualClock,
+        pub emitted: Vec<(Duration, InputEvent)>,
+    }
+
+    impl MemorySink {
+        pub fn new(clock: ManualClock) -> Self {
+            Self {
+                clock,
+                emitted: Vec::new(),
+            }
+        }
+    }
+
+    impl EventSink for MemorySink {
+        fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+            let at = self.clock.elapsed();
+            self.emitted.extend(events.iter().map(|event| (at, *event)));
+            Ok(())
+        }
+    }
+}
@@ -1,7 +1,11 @@
 //! Diagnostic tool to see raw input events from all mouse devices
 //! Run with: cargo run --bin diagnose
 
+#[path = "../inspect.rs"]
+mod inspect;
+
 use evdev::{Device, EventType};
+use inspect::{DeviceDetails, DeviceKind};
 use std::os::fd::AsRawFd;
 use std::time::Duration;
 
@@ -14,12 +18,12 @@ fn main() {
     
     let mut mice: Vec<(String, String)> = Vec::new();
     
-    for (path, device) in evdev::enumerate() {
-        let name = device.name().unwrap_or("Unknown");
+    for (path, mut device) in evdev::enumerate() {
+        let name = device.name().unwrap_or("Unknown").to_string();
         let id = device.input_id();
         
         // Check if it looks like a mouse
-        let has_buttons = device.supported_keys().map_or(false, |keys| {
+        let has_buttons = device.supported_keys().is_some_and(|keys| {
             keys.contains(evdev::KeyCode::BTN_LEFT) || 
             keys.contains(evdev::KeyCode::BTN_SIDE) ||
             keys.contains(evdev::KeyCode::BTN_EXTRA)
@@ -29,9 +33,24 @@ fn main() {
         
         println!("{}: {} ({:04x}:{:04x}){}",
             path.display(), name, id.vendor(), id.product(), marker);
+        if let Some(phys) = device.physical_path() {
+            println!("    Physical path: {}", phys);
+        }
+        if let Some(uniq) = device.unique_name().filter(|uniq| !uniq.is_empty()) {
+            println!("    Unique id: {}", uniq);
+        }
+        let details = DeviceDetails::inspect(&path, &mut device);
+        for line in details.lines() {
+            println!("    {}", line);
+        }
         
-        if has_buttons || name.to_lowercase().contains("mouse") || name.to_lowercase().contains("razer") {
-            mice.push((path.to_string_lossy().to_string(), name.to_string()));
+        // Side buttons sometimes arrive on a node that is not classified as the mouse
+        let mouse_like = details.kind == DeviceKind::Mouse
+            || has_buttons
+            || name.to_lowercase().contains("mouse")
+            || name.to_lowercase().contains("razer");
+        if mouse_like && details.kind != DeviceKind::Virtual {
+            mice.push((path.to_string_lossy().to_string(), name));
         }
     }
     
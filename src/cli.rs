@@ -0,0 +1,287 @@
+use crate::ipc::Request;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: libinput-helper [OPTIONS]
+       libinput-helper ctl <start|stop|status|reload|stats|devices [--all]> [--socket <PATH>]
+       libinput-helper replay <PATH> [--virtual]
+
+Options:
+  --headless          Run the proxy without opening the GUI window
+  --daemon            Run in the background, controlled over a Unix socket
+  --config <PATH>     Load settings from PATH instead of the saved config (headless/daemon only)
+  --profile <NAME>    Use the named profile instead of the active one (also with 'ctl reload')
+  --socket <PATH>     Control socket to listen on or connect to
+  --stats-file <PATH> Write proxy statistics as JSON to PATH on exit (headless only)
+  --record-session <PATH>
+                      Record the session to PATH for 'replay' (headless only)
+  --virtual           Also play a replay through a fresh virtual device in real time
+  -h, --help          Print this help text";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Gui,
+    Headless,
+    Daemon,
+    /// Send a single request to a running daemon
+    Ctl(Request),
+    /// Replay a recorded session and check it reproduces the recorded output
+    Replay { path: PathBuf, virtual_device: bool },
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CliOptions {
+    pub command: Command,
+    /// Config file to load instead of ~/.config/ferrisfire/config.json
+    pub config_path: Option<PathBuf>,
+    /// Profile to use instead of the store's active one
+    pub profile: Option<String>,
+    /// Control socket path (defaults to ipc::default_socket_path())
+    pub socket_path: Option<PathBuf>,
+    /// Where a headless run writes its statistics on exit
+    pub stats_path: Option<PathBuf>,
+    /// Where a headless run records the session
+    pub session_path: Option<PathBuf>,
+}
+
+fn parse_ctl_request(action: &str, all: bool, profile: Option<String>) -> Result<Request, String> {
+    if all && action != "devices" {
+        return Err("--all is only supported with 'ctl devices'".to_string());
+    }
+    if profile.is_some() && action != "reload" {
+        return Err("--profile is only supported with 'ctl reload'".to_string());
+    }
+    match action {
+        "start" => Ok(Request::Start { config: None }),
+        "stop" => Ok(Request::Stop),
+        "status" => Ok(Request::Status),
+        "reload" => Ok(Request::Reload { config: None, profile }),
+        "stats" => Ok(Request::Stats),
+        "devices" => Ok(Request::ListDevices { all }),
+        other => Err(format!("Unknown ctl command: {}", other)),
+    }
+}
+
+/// Parse command-line arguments (excluding the program name)
+pub fn parse_args<I>(args: I) -> Result<CliOptions, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut headless = false;
+    let mut daemon = false;
+    let mut ctl_action: Option<String> = None;
+    let mut all_devices = false;
+    let mut config_path = None;
+    let mut profile = None;
+    let mut socket_path = None;
+    let mut stats_path = None;
+    let mut session_path = None;
+    let mut replay_path: Option<PathBuf> = None;
+    let mut virtual_device = false;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--headless" => headless = true,
+            "--daemon" => daemon = true,
+            "--all" => all_devices = true,
+            "--virtual" => virtual_device = true,
+            "--config" => {
+                let value = args.next().ok_or("--config requires a path")?;
+                config_path = Some(PathBuf::from(value));
+            }
+            "--profile" => {
+                let value = args.next().ok_or("--profile requires a name")?;
+                profile = Some(value);
+            }
+            "--socket" => {
+                let value = args.next().ok_or("--socket requires a path")?;
+                socket_path = Some(PathBuf::from(value));
+            }
+            "--stats-file" => {
+                let value = args.next().ok_or("--stats-file requires a path")?;
+                stats_path = Some(PathBuf::from(value));
+            }
+            "--record-session" => {
+                let value = args.next().ok_or("--record-session requires a path")?;
+                session_path = Some(PathBuf::from(value));
+            }
+            "replay" if replay_path.is_none() => {
+                let value = args.next().ok_or("replay requires a path")?;
+                replay_path = Some(PathBuf::from(value));
+            }
+            "ctl" if ctl_action.is_none() => {
+                let action = args.next().ok_or("ctl requires a command")?;
+                ctl_action = Some(action);
+            }
+            "-h" | "--help" => {
+                return Ok(CliOptions {
+                    command: Command::Help,
+                    config_path: None,
+                    profile: None,
+                    socket_path: None,
+                    stats_path: None,
+                    session_path: None,
+                });
+            }
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+
+    let command = match (headless, daemon, ctl_action, replay_path) {
+        (false, false, None, None) => Command::Gui,
+        (true, false, None, None) => Command::Headless,
+        (false, true, None, None) => Command::Daemon,
+        (false, false, Some(action), None) => {
+            Command::Ctl(parse_ctl_request(&action, all_devices, profile.take())?)
+        }
+        (false, false, None, Some(path)) => Command::Replay { path, virtual_device },
+        _ => return Err("--headless, --daemon, ctl and replay are mutually exclusive".to_string()),
+    };
+
+    if all_devices && !matches!(command, Command::Ctl(_)) {
+        return Err("--all is only supported with 'ctl devices'".to_string());
+    }
+    if config_path.is_some() && !matches!(command, Command::Headless | Command::Daemon) {
+        return Err("--config is only supported together with --headless or --daemon".to_string());
+    }
+    if socket_path.is_some() && !matches!(command, Command::Daemon | Command::Ctl(_)) {
+        return Err("--socket is only supported together with --daemon or ctl".to_string());
+    }
+    if stats_path.is_some() && command != Command::Headless {
+        return Err("--stats-file is only supported together with --headless".to_string());
+    }
+    if session_path.is_some() && command != Command::Headless {
+        return Err("--record-session is only supported together with --headless".to_string());
+    }
+    if virtual_device && !matches!(command, Command::Replay { .. }) {
+        return Err("--virtual is only supported with replay".to_string());
+    }
+    if profile.is_some() && matches!(command, Command::Replay { .. }) {
+        return Err("--profile is not supported with replay; the recording has its own config".to_string());
+    }
+
+    Ok(CliOptions {
+        command,
+        config_path,
+        profile,
+        socket_path,
+        stats_path,
+        session_path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliOptions, String> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_no_args_runs_gui() {
+        let options = parse(&[]).unwrap();
+        assert_eq!(options.command, Command::Gui);
+        assert_eq!(options.config_path, None);
+    }
+
+    #[test]
+    fn test_headless_with_config() {
+        let options = parse(&["--headless", "--config", "/tmp/ff.json"]).unwrap();
+        assert_eq!(options.command, Command::Headless);
+        assert_eq!(options.config_path, Some(PathBuf::from("/tmp/ff.json")));
+    }
+
+    #[test]
+    fn test_config_without_path_fails() {
+        assert_eq!(parse(&["--headless", "--config"]).unwrap_err(), "--config requires a path");
+    }
+
+    #[test]
+    fn test_config_requires_headless() {
+        assert!(parse(&["--config", "/tmp/ff.json"]).is_err());
+    }
+
+    #[test]
+    fn test_unknown_argument_fails() {
+        assert_eq!(parse(&["--bogus"]).unwrap_err(), "Unknown argument: --bogus");
+    }
+
+    #[test]
+    fn test_help() {
+        assert_eq!(parse(&["--headless", "-h"]).unwrap().command, Command::Help);
+    }
+
+    #[test]
+    fn test_daemon_with_socket() {
+        let options = parse(&["--daemon", "--socket", "/tmp/ff.sock"]).unwrap();
+        assert_eq!(options.command, Command::Daemon);
+        assert_eq!(options.socket_path, Some(PathBuf::from("/tmp/ff.sock")));
+    }
+
+    #[test]
+    fn test_ctl_commands() {
+        assert_eq!(parse(&["ctl", "stop"]).unwrap().command, Command::Ctl(Request::Stop));
+        assert_eq!(
+            parse(&["ctl", "devices", "--all"]).unwrap().command,
+            Command::Ctl(Request::ListDevices { all: true })
+        );
+        assert_eq!(parse(&["ctl", "stats"]).unwrap().command, Command::Ctl(Request::Stats));
+        assert_eq!(parse(&["ctl", "explode"]).unwrap_err(), "Unknown ctl command: explode");
+        assert!(parse(&["ctl", "status", "--all"]).is_err());
+    }
+
+    #[test]
+    fn test_profile_selection() {
+        let options = parse(&["--headless", "--profile", "apex"]).unwrap();
+        assert_eq!(options.profile.as_deref(), Some("apex"));
+
+        let options = parse(&["--profile", "apex"]).unwrap();
+        assert_eq!(options.command, Command::Gui);
+
+        let options = parse(&["ctl", "reload", "--profile", "apex"]).unwrap();
+        assert_eq!(
+            options.command,
+            Command::Ctl(Request::Reload { config: None, profile: Some("apex".to_string()) })
+        );
+        assert_eq!(options.profile, None);
+
+        assert!(parse(&["ctl", "stop", "--profile", "apex"]).is_err());
+        assert_eq!(parse(&["--profile"]).unwrap_err(), "--profile requires a name");
+    }
+
+    #[test]
+    fn test_stats_file_requires_headless() {
+        let options = parse(&["--headless", "--stats-file", "/tmp/stats.json"]).unwrap();
+        assert_eq!(options.stats_path, Some(PathBuf::from("/tmp/stats.json")));
+        assert!(parse(&["--daemon", "--stats-file", "/tmp/stats.json"]).is_err());
+    }
+
+    #[test]
+    fn test_session_recording_and_replay() {
+        let options = parse(&["--headless", "--record-session", "/tmp/run.ffs"]).unwrap();
+        assert_eq!(options.session_path, Some(PathBuf::from("/tmp/run.ffs")));
+        assert!(parse(&["--record-session", "/tmp/run.ffs"]).is_err());
+
+        assert_eq!(
+            parse(&["replay", "/tmp/run.ffs"]).unwrap().command,
+            Command::Replay { path: PathBuf::from("/tmp/run.ffs"), virtual_device: false }
+        );
+        assert_eq!(
+            parse(&["replay", "/tmp/run.ffs", "--virtual"]).unwrap().command,
+            Command::Replay { path: PathBuf::from("/tmp/run.ffs"), virtual_device: true }
+        );
+        assert_eq!(parse(&["replay"]).unwrap_err(), "replay requires a path");
+        assert_eq!(parse(&["--headless", "--virtual"]).unwrap_err(), "--virtual is only supported with replay");
+        assert!(parse(&["replay", "/tmp/run.ffs", "--config", "/tmp/ff.json"]).is_err());
+    }
+
+    #[test]
+    fn test_modes_are_exclusive() {
+        assert!(parse(&["--headless", "--daemon"]).is_err());
+        assert!(parse(&["--daemon", "ctl", "status"]).is_err());
+        assert!(parse(&["--headless", "replay", "/tmp/run.ffs"]).is_err());
+    }
+}
@@ -1,6 +1,10 @@
+use crate::device::DeviceIdentity;
+use crate::humanize::TimingDistribution;
+use crate::macros::MacroStep;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::path::Path;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TriggerButton {
@@ -27,7 +31,7 @@ pub enum TriggerButton {
 }
 
 impl TriggerButton {
-    pub fn to_key_code(&self) -> evdev::KeyCode {
+    pub fn to_key_code(self) -> evdev::KeyCode {
         match self {
             TriggerButton::Mouse3 => evdev::KeyCode::BTN_MIDDLE,
             TriggerButton::Mouse4 => evdev::KeyCode::BTN_SIDE,
@@ -103,22 +107,283 @@ impl TriggerButton {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Common outputs offered in the GUI, with friendly names
+pub const OUTPUT_PRESETS: &[(evdev::KeyCode, &str)] = &[
+    (evdev::KeyCode::BTN_LEFT, "Left Click"),
+    (evdev::KeyCode::BTN_RIGHT, "Right Click"),
+    (evdev::KeyCode::BTN_MIDDLE, "Middle Click"),
+];
+
+/// Keys and buttons offered as remap targets; the config file accepts any code
+pub const REMAP_TARGETS: &[(evdev::KeyCode, &str)] = &[
+    (evdev::KeyCode::BTN_LEFT, "Left Click"),
+    (evdev::KeyCode::BTN_RIGHT, "Right Click"),
+    (evdev::KeyCode::BTN_MIDDLE, "Middle Click"),
+    (evdev::KeyCode::BTN_SIDE, "Mouse4"),
+    (evdev::KeyCode::BTN_EXTRA, "Mouse5"),
+    (evdev::KeyCode::KEY_E, "E"),
+    (evdev::KeyCode::KEY_F, "F"),
+    (evdev::KeyCode::KEY_Q, "Q"),
+    (evdev::KeyCode::KEY_R, "R"),
+    (evdev::KeyCode::KEY_SPACE, "Space"),
+    (evdev::KeyCode::KEY_LEFTSHIFT, "Left Shift"),
+    (evdev::KeyCode::KEY_LEFTCTRL, "Left Ctrl"),
+];
+
+/// One physical button passed on to the virtual device as another key or button
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Remap {
+    pub from: u16,
+    pub to: u16,
+}
+
+/// Human-readable name of a key or button code
+pub fn key_name(code: u16) -> String {
+    OUTPUT_PRESETS
+        .iter()
+        .find(|(key, _)| key.0 == code)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| format!("{:?}", evdev::KeyCode(code)))
+}
+
+/// How a trigger press starts and stops rapid-fire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ActivationMode {
+    /// Fire while the trigger is held
+    #[default]
+    Hold,
+    /// Press once to start firing, press again to stop
+    Toggle,
+    /// Fire a fixed number of clicks per press
+    Clicks,
+}
+
+impl ActivationMode {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            ActivationMode::Hold => "Hold",
+            ActivationMode::Toggle => "Toggle",
+            ActivationMode::Clicks => "Fixed clicks",
+        }
+    }
+
+    pub fn all() -> &'static [ActivationMode] {
+        &[ActivationMode::Hold, ActivationMode::Toggle, ActivationMode::Clicks]
+    }
+}
+
+/// Smart ADS preset: left click fires while right click (aim) is held
+pub const SMART_ADS_FIRE: evdev::KeyCode = evdev::KeyCode::BTN_LEFT;
+pub const SMART_ADS_AIM: evdev::KeyCode = evdev::KeyCode::BTN_RIGHT;
+
+/// One trigger and what it fires: the output keys and the timing used for them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Binding {
+    pub trigger_button: TriggerButton,
+    /// Custom key code recorded from the device (overrides trigger_button if set)
+    #[serde(default)]
+    pub custom_trigger_code: Option<u16>,
+    /// Keys that must be held for the trigger to fire; they pass through unchanged
+    #[serde(default)]
+    pub chord: Vec<u16>,
+    #[serde(default)]
+    pub activation: ActivationMode,
+    #[serde(default = "default_activation_clicks")]
+    pub activation_clicks: u64,
+    #[serde(default = "default_output_keys")]
+    pub output_keys: Vec<u16>,
+    pub click_delay_min_ms: u64,
+    pub click_delay_max_ms: u64,
+    pub travel_time_min_ms: u64,
+    pub travel_time_max_ms: u64,
+    #[serde(default)]
+    pub use_gaussian: bool,
+    #[serde(default)]
+    pub click_distribution: Option<TimingDistribution>,
+    #[serde(default)]
+    pub travel_distribution: Option<TimingDistribution>,
+    #[serde(default)]
+    pub simulate_fatigue: bool,
+    #[serde(default = "default_fatigue_max_percent")]
+    pub fatigue_max_percent: u64,
+    #[serde(default)]
+    pub travel_jitter: bool,
+    #[serde(default)]
+    pub burst_mode: bool,
+    #[serde(default = "default_burst_count")]
+    pub burst_count: u64,
+    #[serde(default = "default_burst_pause_ms")]
+    pub burst_pause_ms: u64,
+    /// Sequence played instead of rapid-fire clicks (empty = rapid-fire)
+    #[serde(default)]
+    pub macro_steps: Vec<MacroStep>,
+    /// Play the macro again for as long as the trigger is active, instead of once per activation
+    #[serde(default)]
+    pub macro_repeat: bool,
+}
+
+impl Binding {
+    /// Get the effective trigger key code (custom if set, otherwise from trigger_button)
+    pub fn effective_trigger_code(&self) -> evdev::KeyCode {
+        if let Some(code) = self.custom_trigger_code {
+            evdev::KeyCode(code)
+        } else {
+            self.trigger_button.to_key_code()
+        }
+    }
+
+    pub fn output_key_codes(&self) -> Vec<evdev::KeyCode> {
+        self.output_keys.iter().map(|code| evdev::KeyCode(*code)).collect()
+    }
+
+    pub fn chord_key_codes(&self) -> Vec<evdev::KeyCode> {
+        self.chord.iter().map(|code| evdev::KeyCode(*code)).collect()
+    }
+
+    /// Distribution of click intervals; `use_gaussian` decides while none is chosen
+    pub fn click_timing(&self) -> &TimingDistribution {
+        self.click_distribution.as_ref().unwrap_or(TimingDistribution::legacy(self.use_gaussian))
+    }
+
+    /// Distribution of travel times; `use_gaussian` decides while none is chosen
+    pub fn travel_timing(&self) -> &TimingDistribution {
+        self.travel_distribution.as_ref().unwrap_or(TimingDistribution::legacy(self.use_gaussian))
+    }
+
+    /// Whether the binding plays a macro rather than rapid-fire clicks
+    pub fn is_macro(&self) -> bool {
+        !self.macro_steps.is_empty()
+    }
+
+    /// Keys and buttons the macro presses
+    pub fn macro_key_codes(&self) -> Vec<evdev::KeyCode> {
+        self.macro_steps.iter().filter_map(MacroStep::key).map(evdev::KeyCode).collect()
+    }
+
+    /// Trigger and chord as typed in the GUI, e.g. "Right Click + Left Click"
+    pub fn trigger_description(&self) -> String {
+        let mut keys: Vec<String> = self.chord.iter().map(|code| key_name(*code)).collect();
+        keys.push(key_name(self.effective_trigger_code().0));
+        keys.join(" + ")
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.click_delay_min_ms > self.click_delay_max_ms {
+            return Err("Min delay cannot be greater than max delay".to_string());
+        }
+        if self.travel_time_min_ms > self.travel_time_max_ms {
+            return Err("Min travel time cannot be greater than max travel time".to_string());
+        }
+        if self.click_delay_min_ms < 10 {
+            return Err("Min delay must be at least 10ms".to_string());
+        }
+        self.click_timing().validate().map_err(|e| format!("Click delay distribution: {}", e))?;
+        self.travel_timing().validate().map_err(|e| format!("Travel time distribution: {}", e))?;
+        if self.output_keys.is_empty() && !self.is_macro() {
+            return Err("Select at least one output button".to_string());
+        }
+        if self.macro_steps.iter().any(|step| matches!(step, MacroStep::Click { count: 0, .. })) {
+            return Err("Macro clicks must be at least 1".to_string());
+        }
+        if self.activation == ActivationMode::Clicks && self.activation_clicks == 0 {
+            return Err("Clicks per press must be at least 1".to_string());
+        }
+        if self.chord.contains(&self.effective_trigger_code().0) {
+            return Err("The trigger cannot also be part of its chord".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Default for Binding {
+    fn default() -> Self {
+        Config::default().primary_binding()
+    }
+}
+
+/// Scheduling policy for the proxy thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RealtimePolicy {
+    /// Regular time-sharing scheduling
+    #[default]
+    Normal,
+    Fifo,
+    RoundRobin,
+}
+
+impl RealtimePolicy {
+    /// The libc policy constant, or `None` to leave the thread alone
+    pub fn to_libc(self) -> Option<libc::c_int> {
+        match self {
+            RealtimePolicy::Normal => None,
+            RealtimePolicy::Fifo => Some(libc::SCHED_FIFO),
+            RealtimePolicy::RoundRobin => Some(libc::SCHED_RR),
+        }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            RealtimePolicy::Normal => "Normal",
+            RealtimePolicy::Fifo => "SCHED_FIFO",
+            RealtimePolicy::RoundRobin => "SCHED_RR",
+        }
+    }
+
+    pub fn all() -> &'static [RealtimePolicy] {
+        &[RealtimePolicy::Normal, RealtimePolicy::Fifo, RealtimePolicy::RoundRobin]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Config {
     pub device_path: String,
+    /// Further event nodes of the same mouse (e.g. a separate interface for side
+    /// buttons), grabbed and merged into the one virtual device
+    #[serde(default)]
+    pub extra_device_paths: Vec<String>,
+    /// Second device, usually a keyboard, whose keys can also be triggers (empty = none)
+    #[serde(default)]
+    pub trigger_device_path: String,
+    /// Grab the trigger device so trigger keys do not reach other applications;
+    /// its other keys are passed on through a virtual clone
+    #[serde(default)]
+    pub grab_trigger_device: bool,
+    /// Stable identity of each device path above, so the devices are found
+    /// again when their event numbers change across reboots and replugs
+    #[serde(default)]
+    pub device_identities: BTreeMap<String, DeviceIdentity>,
     pub trigger_button: TriggerButton,
     /// Custom key code recorded from the device (overrides trigger_button if set)
     #[serde(default)]
     pub custom_trigger_code: Option<u16>,
+    /// Keys that must be held for the trigger to fire (e.g. a modifier); they pass through unchanged
+    #[serde(default)]
+    pub chord: Vec<u16>,
+    /// Whether the trigger fires while held, toggles firing, or fires a fixed number of clicks
+    #[serde(default)]
+    pub activation: ActivationMode,
+    /// Clicks fired per trigger press in `ActivationMode::Clicks`
+    #[serde(default = "default_activation_clicks")]
+    pub activation_clicks: u64,
     pub click_delay_min_ms: u64,
     pub click_delay_max_ms: u64,
     pub travel_time_min_ms: u64,
     pub travel_time_max_ms: u64,
+    /// Keys or buttons pressed together on every rapid-fire click
+    #[serde(default = "default_output_keys")]
+    pub output_keys: Vec<u16>,
     
     // Humanization features
     /// Use Gaussian distribution instead of uniform random for timing
+    /// (only where no distribution is chosen below)
     #[serde(default)]
     pub use_gaussian: bool,
+    /// Distribution click delays are drawn from, within the min-max range
+    #[serde(default)]
+    pub click_distribution: Option<TimingDistribution>,
+    /// Distribution travel times are drawn from, within the min-max range
+    #[serde(default)]
+    pub travel_distribution: Option<TimingDistribution>,
     /// Simulate fatigue - gradually slow down over time then recover
     #[serde(default)]
     pub simulate_fatigue: bool,
@@ -137,88 +402,260 @@ pub struct Config {
     /// Pause between bursts in milliseconds
     #[serde(default = "default_burst_pause_ms")]
     pub burst_pause_ms: u64,
-    /// Smart ADS trigger - rapid-fire only when aiming (RMB) and firing (LMB)
+    /// Sequence the trigger above plays instead of rapid-fire clicks (empty = rapid-fire)
+    #[serde(default)]
+    pub macro_steps: Vec<MacroStep>,
+    /// Play that macro again for as long as the trigger is active
+    #[serde(default)]
+    pub macro_repeat: bool,
+    /// Smart ADS trigger - rapid-fire only when aiming (RMB) and firing (LMB).
+    /// A preset chord that replaces the trigger and chord above
     #[serde(default)]
     pub smart_ads_trigger: bool,
+    /// Fixed RNG seed for humanized timing (random per session if unset)
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Further triggers that fire alongside the one configured above
+    #[serde(default)]
+    pub extra_bindings: Vec<Binding>,
+    /// Mouse buttons passed on as other keys or buttons. Triggers see the
+    /// physical button; only what would be forwarded is remapped.
+    #[serde(default)]
+    pub remap: Vec<Remap>,
+
+    // Proxy thread tuning, applied when the proxy starts
+    /// Real-time scheduling policy (needs CAP_SYS_NICE or RLIMIT_RTPRIO)
+    #[serde(default)]
+    pub realtime_policy: RealtimePolicy,
+    /// Real-time priority, 1-99 (only used with a real-time policy)
+    #[serde(default = "default_realtime_priority")]
+    pub realtime_priority: u8,
+    /// Lock process memory with mlockall to avoid page faults on the input path
+    #[serde(default)]
+    pub lock_memory: bool,
+    /// CPUs the proxy thread may run on (empty = no pinning)
+    #[serde(default)]
+    pub cpu_affinity: Vec<usize>,
 }
 
 fn default_fatigue_max_percent() -> u64 { 30 }
 fn default_burst_count() -> u64 { 4 }
+fn default_activation_clicks() -> u64 { 3 }
 fn default_burst_pause_ms() -> u64 { 100 }
+fn default_realtime_priority() -> u8 { 50 }
+fn default_output_keys() -> Vec<u16> { vec![evdev::KeyCode::BTN_LEFT.0] }
 
 impl Config {
-    /// Get the effective trigger key code (custom if set, otherwise from trigger_button)
-    pub fn effective_trigger_code(&self) -> evdev::KeyCode {
-        if let Some(code) = self.custom_trigger_code {
-            evdev::KeyCode(code)
+    /// The binding made of the top-level trigger, output and timing settings
+    pub fn primary_binding(&self) -> Binding {
+        let (custom_trigger_code, chord) = if self.smart_ads_trigger {
+            (Some(SMART_ADS_FIRE.0), vec![SMART_ADS_AIM.0])
         } else {
-            self.trigger_button.to_key_code()
+            (self.custom_trigger_code, self.chord.clone())
+        };
+        Binding {
+            trigger_button: self.trigger_button,
+            custom_trigger_code,
+            chord,
+            activation: self.activation,
+            activation_clicks: self.activation_clicks,
+            output_keys: self.output_keys.clone(),
+            click_delay_min_ms: self.click_delay_min_ms,
+            click_delay_max_ms: self.click_delay_max_ms,
+            travel_time_min_ms: self.travel_time_min_ms,
+            travel_time_max_ms: self.travel_time_max_ms,
+            use_gaussian: self.use_gaussian,
+            click_distribution: self.click_distribution.clone(),
+            travel_distribution: self.travel_distribution.clone(),
+            simulate_fatigue: self.simulate_fatigue,
+            fatigue_max_percent: self.fatigue_max_percent,
+            travel_jitter: self.travel_jitter,
+            burst_mode: self.burst_mode,
+            burst_count: self.burst_count,
+            burst_pause_ms: self.burst_pause_ms,
+            macro_steps: self.macro_steps.clone(),
+            macro_repeat: self.macro_repeat,
+        }
+    }
+
+    /// Every binding the proxy runs, the primary one first
+    pub fn bindings(&self) -> Vec<Binding> {
+        let mut bindings = vec![self.primary_binding()];
+        bindings.extend(self.extra_bindings.iter().cloned());
+        bindings
+    }
+
+    /// Keys the virtual device must be able to emit: output and macro keys
+    /// of all bindings and remap targets, each listed once
+    pub fn all_output_keys(&self) -> Vec<evdev::KeyCode> {
+        let mut keys: Vec<evdev::KeyCode> = self
+            .bindings()
+            .iter()
+            .flat_map(|b| b.output_key_codes().into_iter().chain(b.macro_key_codes()))
+            .chain(self.remap.iter().map(|remap| evdev::KeyCode(remap.to)))
+            .collect();
+        keys.sort_by_key(|key| key.0);
+        keys.dedup();
+        keys
+    }
+
+    /// What the button `code` is passed on as
+    pub fn remapped(&self, code: u16) -> u16 {
+        self.remap.iter().find(|remap| remap.from == code).map_or(code, |remap| remap.to)
+    }
+
+    /// Every event node of the proxied mouse, the main one first
+    pub fn mouse_device_paths(&self) -> Vec<&String> {
+        std::iter::once(&self.device_path).chain(&self.extra_device_paths).collect()
+    }
+
+    /// Every device path the config uses: the mouse nodes and the trigger device, if any
+    pub fn configured_device_paths(&self) -> Vec<&String> {
+        let mut paths = self.mouse_device_paths();
+        if !self.trigger_device_path.is_empty() {
+            paths.push(&self.trigger_device_path);
+        }
+        paths
+    }
+
+    /// Record which device `path` is, forgetting devices no longer configured
+    pub fn remember_device(&mut self, path: &str, identity: DeviceIdentity) {
+        self.device_identities.insert(path.to_string(), identity);
+        let configured: Vec<String> = self.configured_device_paths().into_iter().cloned().collect();
+        self.device_identities.retain(|path, _| configured.contains(path));
+    }
+
+    /// Rename device paths (old to new) everywhere at once, so two devices
+    /// can swap event numbers
+    pub fn move_devices(&mut self, moves: &HashMap<String, String>) {
+        let moved = |path: &mut String| {
+            if let Some(new) = moves.get(path.as_str()) {
+                *path = new.clone();
+            }
+        };
+        moved(&mut self.device_path);
+        self.extra_device_paths.iter_mut().for_each(moved);
+        moved(&mut self.trigger_device_path);
+        self.device_identities = std::mem::take(&mut self.device_identities)
+            .into_iter()
+            .map(|(path, identity)| (moves.get(&path).cloned().unwrap_or(path), identity))
+            .collect();
+    }
+
+    /// Use `other`'s mouse, for profiles saved without a device of their own
+    pub fn inherit_device(&mut self, other: &Config) {
+        self.device_path = other.device_path.clone();
+        if let Some(identity) = other.device_identities.get(&other.device_path) {
+            self.device_identities.insert(self.device_path.clone(), identity.clone());
         }
     }
+
+    /// Whether switching a running proxy from `self` to `other` needs a new
+    /// grab and virtual device rather than a live update
+    pub fn needs_restart(&self, other: &Config) -> bool {
+        self.device_path != other.device_path
+            || self.extra_device_paths != other.extra_device_paths
+            || self.trigger_device_path != other.trigger_device_path
+            || self.grab_trigger_device != other.grab_trigger_device
+            || self.all_output_keys() != other.all_output_keys()
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             device_path: String::new(),
+            extra_device_paths: Vec::new(),
+            trigger_device_path: String::new(),
+            grab_trigger_device: false,
+            device_identities: BTreeMap::new(),
             trigger_button: TriggerButton::Mouse4,
             custom_trigger_code: None,
+            chord: Vec::new(),
+            activation: ActivationMode::Hold,
+            activation_clicks: default_activation_clicks(),
             click_delay_min_ms: 45,
             click_delay_max_ms: 80,
             travel_time_min_ms: 10,
             travel_time_max_ms: 25,
+            output_keys: default_output_keys(),
             use_gaussian: false,
+            click_distribution: None,
+            travel_distribution: None,
             simulate_fatigue: false,
             fatigue_max_percent: default_fatigue_max_percent(),
             travel_jitter: false,
             burst_mode: false,
             burst_count: default_burst_count(),
             burst_pause_ms: default_burst_pause_ms(),
+            macro_steps: Vec::new(),
+            macro_repeat: false,
             smart_ads_trigger: false,
+            seed: None,
+            extra_bindings: Vec::new(),
+            remap: Vec::new(),
+            realtime_policy: RealtimePolicy::Normal,
+            realtime_priority: default_realtime_priority(),
+            lock_memory: false,
+            cpu_affinity: Vec::new(),
         }
     }
 }
 
 impl Config {
-    fn config_path() -> PathBuf {
-        let mut path = dirs_next::config_dir().unwrap_or_else(|| PathBuf::from("."));
-        path.push("ferrisfire");
-        fs::create_dir_all(&path).ok();
-        path.push("config.json");
-        path
-    }
-
-    pub fn load() -> Self {
-        let path = Self::config_path();
-        if path.exists() {
-            if let Ok(contents) = fs::read_to_string(&path) {
-                if let Ok(config) = serde_json::from_str(&contents) {
-                    return config;
-                }
-            }
-        }
-        Self::default()
+    /// Load a config from an explicit path, reporting why it could not be used
+    pub fn load_from(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
     }
 
-    pub fn save(&self) {
-        let path = Self::config_path();
-        if let Ok(json) = serde_json::to_string_pretty(self) {
-            fs::write(path, json).ok();
-        }
+    /// Write this config as a standalone file (see `ProfileStore` for the saved settings)
+    pub fn save_to(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("Failed to encode config: {}", e))?;
+        fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
     }
 
     pub fn validate(&self) -> Result<(), String> {
         if self.device_path.is_empty() {
             return Err("No device selected".to_string());
         }
-        if self.click_delay_min_ms > self.click_delay_max_ms {
-            return Err("Min delay cannot be greater than max delay".to_string());
+        if !self.trigger_device_path.is_empty() && self.mouse_device_paths().contains(&&self.trigger_device_path) {
+            return Err("The trigger device must be different from the mouse".to_string());
         }
-        if self.travel_time_min_ms > self.travel_time_max_ms {
-            return Err("Min travel time cannot be greater than max travel time".to_string());
+        let mut paths: Vec<&String> = self.mouse_device_paths();
+        paths.sort_unstable();
+        if paths.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err("Each mouse event node can only be selected once".to_string());
         }
-        if self.click_delay_min_ms < 10 {
-            return Err("Min delay must be at least 10ms".to_string());
+        self.primary_binding().validate()?;
+        for (idx, binding) in self.extra_bindings.iter().enumerate() {
+            binding.validate().map_err(|e| format!("Binding {}: {}", idx + 2, e))?;
+        }
+        let mut triggers: Vec<(u16, Vec<u16>)> = self
+            .bindings()
+            .into_iter()
+            .map(|mut b| {
+                b.chord.sort_unstable();
+                b.chord.dedup();
+                (b.effective_trigger_code().0, b.chord)
+            })
+            .collect();
+        triggers.sort_unstable();
+        if triggers.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err("Each binding needs its own trigger".to_string());
+        }
+        let mut remapped: Vec<u16> = self.remap.iter().map(|remap| remap.from).collect();
+        remapped.sort_unstable();
+        if remapped.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err("Each button can only be remapped once".to_string());
+        }
+        if self.remap.iter().any(|remap| remap.from == remap.to) {
+            return Err("A button cannot be remapped to itself".to_string());
+        }
+        if self.realtime_policy != RealtimePolicy::Normal && !(1..=99).contains(&self.realtime_priority) {
+            return Err("Real-time priority must be between 1 and 99".to_string());
         }
         Ok(())
     }
@@ -364,6 +801,345 @@ mod tests {
         assert!(config.validate().is_ok());
     }
 
+    #[test]
+    fn test_bindings_start_with_primary() {
+        let config = Config {
+            extra_bindings: vec![Binding {
+                trigger_button: TriggerButton::Mouse5,
+                output_keys: vec![evdev::KeyCode::BTN_RIGHT.0],
+                click_delay_min_ms: 120,
+                click_delay_max_ms: 120,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let bindings = config.bindings();
+        assert_eq!(bindings.len(), 2);
+        assert_eq!(bindings[0].effective_trigger_code(), evdev::KeyCode::BTN_SIDE);
+        assert_eq!(bindings[1].effective_trigger_code(), evdev::KeyCode::BTN_EXTRA);
+        assert_eq!(
+            config.all_output_keys(),
+            vec![evdev::KeyCode::BTN_LEFT, evdev::KeyCode::BTN_RIGHT]
+        );
+    }
+
+    #[test]
+    fn test_validate_extra_bindings() {
+        let mut config = Config {
+            device_path: "/dev/input/event5".to_string(),
+            extra_bindings: vec![Binding {
+                trigger_button: TriggerButton::Mouse5,
+                click_delay_min_ms: 5,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(config.validate().unwrap_err(), "Binding 2: Min delay must be at least 10ms");
+
+        config.extra_bindings[0].click_delay_min_ms = 45;
+        assert!(config.validate().is_ok());
+
+        config.extra_bindings[0].trigger_button = TriggerButton::Mouse4;
+        assert_eq!(config.validate().unwrap_err(), "Each binding needs its own trigger");
+
+        // The same trigger is fine under a different chord
+        config.extra_bindings[0].chord = vec![evdev::KeyCode::KEY_LEFTSHIFT.0];
+        assert!(config.validate().is_ok());
+
+        config.extra_bindings[0].chord = vec![evdev::KeyCode::BTN_SIDE.0];
+        assert_eq!(config.validate().unwrap_err(), "Binding 2: The trigger cannot also be part of its chord");
+    }
+
+    #[test]
+    fn test_remap() {
+        let mut config = Config {
+            device_path: "/dev/input/event5".to_string(),
+            remap: vec![Remap {
+                from: evdev::KeyCode::BTN_EXTRA.0,
+                to: evdev::KeyCode::KEY_E.0,
+            }],
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.remapped(evdev::KeyCode::BTN_EXTRA.0), evdev::KeyCode::KEY_E.0);
+        assert_eq!(config.remapped(evdev::KeyCode::BTN_SIDE.0), evdev::KeyCode::BTN_SIDE.0);
+        // The virtual device needs the target key, so adding a remap re-creates it
+        assert!(config.all_output_keys().contains(&evdev::KeyCode::KEY_E));
+        assert!(config.needs_restart(&Config {
+            remap: Vec::new(),
+            ..config.clone()
+        }));
+
+        config.remap.push(Remap {
+            from: evdev::KeyCode::BTN_EXTRA.0,
+            to: evdev::KeyCode::BTN_MIDDLE.0,
+        });
+        assert_eq!(config.validate().unwrap_err(), "Each button can only be remapped once");
+        config.remap[1].from = evdev::KeyCode::BTN_MIDDLE.0;
+        assert_eq!(config.validate().unwrap_err(), "A button cannot be remapped to itself");
+    }
+
+    #[test]
+    fn test_macro_bindings() {
+        let mut config = Config {
+            device_path: "/dev/input/event5".to_string(),
+            extra_bindings: vec![Binding {
+                trigger_button: TriggerButton::Mouse5,
+                output_keys: Vec::new(),
+                macro_steps: vec![
+                    MacroStep::Click {
+                        key: evdev::KeyCode::KEY_R.0,
+                        count: 1,
+                    },
+                    MacroStep::Wait { ms: 30 },
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        // A macro needs no rapid-fire output, but the virtual device must be able to emit its keys
+        assert!(config.validate().is_ok());
+        assert!(config.all_output_keys().contains(&evdev::KeyCode::KEY_R));
+
+        config.extra_bindings[0].macro_steps[0] = MacroStep::Click {
+            key: evdev::KeyCode::KEY_R.0,
+            count: 0,
+        };
+        assert_eq!(config.validate().unwrap_err(), "Binding 2: Macro clicks must be at least 1");
+    }
+
+    #[test]
+    fn test_primary_binding_plays_macro() {
+        let mut config = Config {
+            device_path: "/dev/input/event5".to_string(),
+            macro_steps: vec![MacroStep::Click {
+                key: evdev::KeyCode::KEY_R.0,
+                count: 0,
+            }],
+            macro_repeat: true,
+            ..Default::default()
+        };
+        assert_eq!(config.validate().unwrap_err(), "Macro clicks must be at least 1");
+
+        config.macro_steps[0] = MacroStep::Click {
+            key: evdev::KeyCode::KEY_R.0,
+            count: 2,
+        };
+        let primary = config.primary_binding();
+        assert!(primary.is_macro() && primary.macro_repeat);
+        assert_eq!(primary.macro_steps, config.macro_steps);
+        assert!(config.all_output_keys().contains(&evdev::KeyCode::KEY_R));
+    }
+
+    #[test]
+    fn test_timing_distributions() {
+        let mut config = Config {
+            device_path: "/dev/input/event5".to_string(),
+            use_gaussian: true,
+            travel_distribution: Some(TimingDistribution::Triangular { mode_ms: 12.0 }),
+            ..Default::default()
+        };
+        // Without a chosen distribution the old switch still decides
+        let primary = config.primary_binding();
+        assert_eq!(primary.click_timing(), &TimingDistribution::RANGE_GAUSSIAN);
+        assert_eq!(primary.travel_timing(), &TimingDistribution::Triangular { mode_ms: 12.0 });
+
+        config.click_distribution = Some(TimingDistribution::LogNormal { median_ms: -5.0, sigma: 0.2 });
+        assert_eq!(config.validate().unwrap_err(), "Click delay distribution: Median must be above 0 ms");
+
+        let binding: Binding = serde_json::from_str(
+            r#"{"trigger_button":"Mouse5","click_delay_min_ms":50,"click_delay_max_ms":60,"travel_time_min_ms":10,
+                "travel_time_max_ms":20,"click_distribution":{"kind":"gamma","shape":9.0,"mean_ms":55.0}}"#,
+        )
+        .unwrap();
+        assert_eq!(binding.click_timing(), &TimingDistribution::Gamma { shape: 9.0, mean_ms: 55.0 });
+        assert_eq!(binding.travel_timing(), &TimingDistribution::Uniform);
+    }
+
+    #[test]
+    fn test_smart_ads_is_a_chord_preset() {
+        let config = Config {
+            smart_ads_trigger: true,
+            chord: vec![evdev::KeyCode::KEY_LEFTSHIFT.0],
+            ..Default::default()
+        };
+        let primary = config.primary_binding();
+        assert_eq!(primary.effective_trigger_code(), SMART_ADS_FIRE);
+        assert_eq!(primary.chord, vec![SMART_ADS_AIM.0]);
+        assert_eq!(primary.trigger_description(), "Right Click + Left Click");
+    }
+
+    #[test]
+    fn test_validate_requires_output() {
+        let config = Config {
+            device_path: "/dev/input/event5".to_string(),
+            output_keys: Vec::new(),
+            ..Default::default()
+        };
+        assert_eq!(config.validate().unwrap_err(), "Select at least one output button");
+    }
+
+    #[test]
+    fn test_validate_activation_clicks() {
+        let config = Config {
+            device_path: "/dev/input/event5".to_string(),
+            activation: ActivationMode::Clicks,
+            activation_clicks: 0,
+            ..Default::default()
+        };
+        assert_eq!(config.validate().unwrap_err(), "Clicks per press must be at least 1");
+        // Older configs without the keys load as hold-to-fire
+        let binding: Binding = serde_json::from_str(
+            r#"{"trigger_button":"Mouse5","click_delay_min_ms":50,"click_delay_max_ms":60,"travel_time_min_ms":10,"travel_time_max_ms":20}"#,
+        )
+        .unwrap();
+        assert_eq!(binding.activation, ActivationMode::Hold);
+        assert_eq!(binding.activation_clicks, 3);
+    }
+
+    #[test]
+    fn test_key_names() {
+        assert_eq!(key_name(evdev::KeyCode::BTN_RIGHT.0), "Right Click");
+        assert_eq!(key_name(evdev::KeyCode::KEY_E.0), "KEY_E");
+    }
+
+    #[test]
+    fn test_output_change_needs_restart() {
+        let config = Config::default();
+        let faster = Config {
+            click_delay_min_ms: 20,
+            ..Config::default()
+        };
+        let right_click = Config {
+            output_keys: vec![evdev::KeyCode::BTN_RIGHT.0],
+            ..Config::default()
+        };
+        assert!(!config.needs_restart(&faster));
+        assert!(config.needs_restart(&right_click));
+    }
+
+    #[test]
+    fn test_trigger_device() {
+        let mut config = Config {
+            device_path: "/dev/input/event5".to_string(),
+            trigger_device_path: "/dev/input/event3".to_string(),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert!(config.needs_restart(&Config::default()));
+
+        config.trigger_device_path = config.device_path.clone();
+        assert_eq!(config.validate().unwrap_err(), "The trigger device must be different from the mouse");
+    }
+
+    fn test_identity(phys: &str) -> DeviceIdentity {
+        DeviceIdentity {
+            name: "G502".to_string(),
+            vendor_id: 0x046d,
+            product_id: 0xc08b,
+            phys: Some(phys.to_string()),
+            uniq: None,
+        }
+    }
+
+    #[test]
+    fn test_device_identities_follow_configured_paths() {
+        let mut config = Config {
+            device_path: "/dev/input/event5".to_string(),
+            trigger_device_path: "/dev/input/event3".to_string(),
+            ..Default::default()
+        };
+        config.remember_device("/dev/input/event5", test_identity("usb-1/input0"));
+        config.remember_device("/dev/input/event3", test_identity("usb-2/input0"));
+        assert_eq!(config.device_identities.len(), 2);
+
+        // Identities of devices no longer configured are dropped
+        config.trigger_device_path.clear();
+        config.remember_device("/dev/input/event5", test_identity("usb-1/input0"));
+        assert_eq!(config.device_identities.keys().collect::<Vec<_>>(), vec!["/dev/input/event5"]);
+
+        config.move_devices(&HashMap::from([("/dev/input/event5".to_string(), "/dev/input/event9".to_string())]));
+        assert_eq!(config.device_path, "/dev/input/event9");
+        assert_eq!(
+            config.device_identities.get("/dev/input/event9"),
+            Some(&test_identity("usb-1/input0"))
+        );
+
+        let mut profile = Config::default();
+        profile.inherit_device(&config);
+        assert_eq!(profile.device_path, "/dev/input/event9");
+        assert_eq!(profile.device_identities, config.device_identities);
+    }
+
+    #[test]
+    fn test_extra_device_paths() {
+        let mut config = Config {
+            device_path: "/dev/input/event5".to_string(),
+            extra_device_paths: vec!["/dev/input/event6".to_string()],
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.mouse_device_paths(), vec!["/dev/input/event5", "/dev/input/event6"]);
+        assert!(config.needs_restart(&Config {
+            extra_device_paths: Vec::new(),
+            ..config.clone()
+        }));
+
+        config.extra_device_paths.push("/dev/input/event5".to_string());
+        assert_eq!(config.validate().unwrap_err(), "Each mouse event node can only be selected once");
+
+        config.extra_device_paths = vec!["/dev/input/event6".to_string()];
+        config.trigger_device_path = "/dev/input/event6".to_string();
+        assert_eq!(config.validate().unwrap_err(), "The trigger device must be different from the mouse");
+    }
+
+    #[test]
+    fn test_validate_realtime_priority_range() {
+        let config = Config {
+            device_path: "/dev/input/event5".to_string(),
+            realtime_policy: RealtimePolicy::Fifo,
+            realtime_priority: 0,
+            ..Default::default()
+        };
+        assert_eq!(config.validate().unwrap_err(), "Real-time priority must be between 1 and 99");
+
+        // Ignored while the policy is Normal
+        let config = Config {
+            realtime_policy: RealtimePolicy::Normal,
+            ..config
+        };
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_load_from_missing_file_fails() {
+        let result = Config::load_from(Path::new("/nonexistent/ferrisfire.json"));
+        assert!(result.unwrap_err().starts_with("Failed to read"));
+    }
+
+    #[test]
+    fn test_load_from_partial_file_uses_defaults() {
+        let path = std::env::temp_dir().join(format!("ferrisfire-load-{}.json", std::process::id()));
+        fs::write(&path, r#"{
+            "device_path": "/dev/input/event7",
+            "trigger_button": "Mouse5",
+            "click_delay_min_ms": 30,
+            "click_delay_max_ms": 60,
+            "travel_time_min_ms": 15,
+            "travel_time_max_ms": 30
+        }"#).unwrap();
+        let config = Config::load_from(&path);
+        fs::remove_file(&path).ok();
+
+        let config = config.unwrap();
+        assert_eq!(config.device_path, "/dev/input/event7");
+        assert_eq!(config.burst_count, 4);
+        assert!(!config.smart_ads_trigger);
+        assert_eq!(config.output_keys, vec![evdev::KeyCode::BTN_LEFT.0]);
+        assert_eq!(config.realtime_policy, RealtimePolicy::Normal);
+        assert!(config.cpu_affinity.is_empty());
+    }
+
     #[test]
     fn test_trigger_button_equality() {
         assert_eq!(TriggerButton::Mouse4, TriggerButton::Mouse4);
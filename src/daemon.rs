@@ -0,0 +1,463 @@
+//! Long-running background process that owns the proxy and accepts
+//! commands over a Unix domain socket (see `ipc` for the protocol).
+
+use crate::config::Config;
+use crate::device::{
+    connected_devices, enumerate_all_input_devices, enumerate_mice, resolve_config_devices_among, DeviceIdentity,
+};
+use crate::focus::{self, AutoSwitcher, FocusWatcher};
+use crate::headless::{install_signal_handlers, shutdown_requested};
+use crate::ipc::{self, DaemonStatus, Request, Response};
+use crate::profile;
+use crate::proxy::spawn_proxy;
+use crate::stats::{self, SharedStats};
+use std::fs;
+use std::io::{BufReader, ErrorKind};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+pub struct Daemon {
+    config: Config,
+    /// File that `reload` re-reads when no inline config is sent (None = saved GUI profiles)
+    config_path: Option<PathBuf>,
+    /// Profile that `reload` picks from that file (None = the store's active profile)
+    profile: Option<String>,
+    stop_signal: Arc<AtomicBool>,
+    proxy_handle: Option<JoinHandle<Result<(), String>>>,
+    /// Live config channel into the running proxy
+    config_updates: Option<Sender<Config>>,
+    last_error: Option<String>,
+    /// Real-time tuning the running proxy could not apply
+    tuning_warning: Option<String>,
+    /// Statistics of the current or most recent proxy run
+    stats: SharedStats,
+    /// Focus-based profile switching, when enabled in the profile store
+    auto_switch: Option<(FocusWatcher, AutoSwitcher)>,
+    /// Lists the connected devices that saved device identities are matched against
+    connected_devices: fn() -> Vec<(String, DeviceIdentity)>,
+}
+
+impl Daemon {
+    pub fn new(config: Config, config_path: Option<PathBuf>, profile: Option<String>) -> Self {
+        Self {
+            config,
+            config_path,
+            profile,
+            stop_signal: Arc::new(AtomicBool::new(false)),
+            proxy_handle: None,
+            config_updates: None,
+            last_error: None,
+            tuning_warning: None,
+            stats: SharedStats::default(),
+            auto_switch: None,
+            connected_devices,
+        }
+    }
+
+    /// Start following the focused application if the profile store asks for it
+    fn start_auto_switch(&mut self) {
+        let store = match profile::load_store(self.config_path.as_deref()) {
+            Ok(store) => store,
+            Err(e) => {
+                log::warn!("Auto profile switching disabled: {}", e);
+                return;
+            }
+        };
+        if !store.auto_switch.enabled || store.auto_switch.rules.is_empty() {
+            return;
+        }
+        let Some(provider) = focus::detect_provider() else {
+            log::warn!("Auto profile switching disabled: no supported active-window provider in this session");
+            return;
+        };
+
+        let fallback = self.profile.clone().unwrap_or(store.active);
+        let switcher = AutoSwitcher::new(store.auto_switch.rules, fallback);
+        self.auto_switch = Some((FocusWatcher::spawn(provider, focus::POLL_INTERVAL), switcher));
+    }
+
+    fn poll_auto_switch(&mut self) {
+        let Some((watcher, switcher)) = &mut self.auto_switch else {
+            return;
+        };
+        let Some(process) = watcher.latest() else {
+            return;
+        };
+        let Some(name) = switcher.update(process.as_ref()) else {
+            return;
+        };
+
+        let result = profile::load_config(self.config_path.as_deref(), Some(&name)).and_then(|mut config| {
+            // Profiles without a device of their own keep using the current one
+            if config.device_path.is_empty() {
+                config.inherit_device(&self.config);
+            }
+            self.reload(Some(config), None)
+        });
+        match result {
+            Ok(()) => log::info!("Focus changed, switched to profile '{}'", name),
+            Err(e) => log::warn!("Failed to switch to profile '{}': {}", name, e),
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.proxy_handle.is_some()
+    }
+
+    fn start(&mut self) -> Result<(), String> {
+        if self.is_running() {
+            return Err("Proxy is already running".to_string());
+        }
+        resolve_config_devices_among(&mut self.config, &(self.connected_devices)())?;
+        self.config.validate()?;
+
+        self.stop_signal.store(false, Ordering::SeqCst);
+        self.last_error = None;
+        let (config_updates, updates) = mpsc::channel();
+        self.config_updates = Some(config_updates);
+        self.stats = SharedStats::default();
+        let (handle, tuning_warning) = spawn_proxy(
+            self.config.clone(),
+            Arc::clone(&self.stop_signal),
+            updates,
+            Arc::clone(&self.stats),
+            None,
+        );
+        self.proxy_handle = Some(handle);
+        self.tuning_warning = tuning_warning;
+        log::info!("Proxy started on {}", self.config.device_path);
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), String> {
+        let Some(handle) = self.proxy_handle.take() else {
+            return Err("Proxy is not running".to_string());
+        };
+        self.config_updates = None;
+        self.tuning_warning = None;
+        self.stop_signal.store(true, Ordering::SeqCst);
+        self.record_exit(handle.join());
+        match &self.last_error {
+            Some(e) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+
+    /// Collect a proxy thread that exited on its own (e.g. device error)
+    fn reap(&mut self) {
+        if self.proxy_handle.as_ref().is_some_and(|h| h.is_finished())
+            && let Some(handle) = self.proxy_handle.take()
+        {
+            self.config_updates = None;
+            self.tuning_warning = None;
+            self.record_exit(handle.join());
+            if let Some(e) = &self.last_error {
+                log::error!("Proxy exited: {}", e);
+            }
+        }
+    }
+
+    fn record_exit(&mut self, result: thread::Result<Result<(), String>>) {
+        self.last_error = match result {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(e),
+            Err(_) => Some("Proxy thread panicked".to_string()),
+        };
+    }
+
+    fn reload(&mut self, config: Option<Config>, profile: Option<String>) -> Result<(), String> {
+        if let Some(name) = &profile {
+            if let Some((_, switcher)) = &mut self.auto_switch {
+                switcher.set_fallback(name.clone());
+            }
+            self.profile = profile;
+        }
+        let mut config = match config {
+            Some(config) => config,
+            None => profile::load_config(self.config_path.as_deref(), self.profile.as_deref())?,
+        };
+        resolve_config_devices_among(&mut config, &(self.connected_devices)())?;
+        config.validate()?;
+
+        // Same device and outputs: update the running proxy in place instead of re-grabbing
+        if !config.needs_restart(&self.config)
+            && let Some(sender) = &self.config_updates
+            && sender.send(config.clone()).is_ok()
+        {
+            self.config = config;
+            return Ok(());
+        }
+
+        let was_running = self.is_running();
+        if was_running {
+            self.stop()?;
+        }
+        self.config = config;
+        if was_running {
+            self.start()?;
+        }
+        Ok(())
+    }
+
+    fn status(&self) -> DaemonStatus {
+        DaemonStatus {
+            running: self.is_running(),
+            config: self.config.clone(),
+            last_error: self.last_error.clone(),
+            tuning_warning: self.tuning_warning.clone(),
+        }
+    }
+
+    pub fn handle_request(&mut self, request: Request) -> Response {
+        self.reap();
+
+        let result = match request {
+            Request::Start { config } => {
+                if let Some(config) = config {
+                    if self.is_running() {
+                        return Response::error("Proxy is already running");
+                    }
+                    self.config = *config;
+                }
+                self.start()
+            }
+            Request::Stop => self.stop(),
+            Request::Status => return Response::Status(self.status()),
+            Request::Reload { config, profile } => self.reload(config.map(|config| *config), profile),
+            Request::ListDevices { all } => {
+                let devices = if all { enumerate_all_input_devices() } else { enumerate_mice() };
+                return Response::Devices { devices };
+            }
+            Request::Stats => return Response::Stats { stats: stats::snapshot(&self.stats) },
+        };
+
+        match result {
+            Ok(()) => Response::Ok,
+            Err(e) => Response::error(e),
+        }
+    }
+
+    fn shutdown(&mut self) {
+        if self.is_running()
+            && let Err(e) = self.stop()
+        {
+            log::warn!("Proxy stopped with error: {}", e);
+        }
+    }
+}
+
+/// A request read off a control connection, with the channel its response goes back on
+type PendingRequest = (Request, Sender<Response>);
+
+/// Talk to one client on its own thread, so a client that never sends its
+/// request cannot hold up the daemon; only the request itself goes to the main loop
+fn spawn_connection(stream: UnixStream, requests: Sender<PendingRequest>) {
+    thread::spawn(move || {
+        if let Err(e) = serve_connection(stream, &requests) {
+            log::warn!("Control connection failed: {}", e);
+        }
+    });
+}
+
+fn serve_connection(stream: UnixStream, requests: &Sender<PendingRequest>) -> Result<(), String> {
+    stream.set_nonblocking(false).ok();
+    stream.set_read_timeout(Some(Duration::from_secs(2))).ok();
+    stream.set_write_timeout(Some(Duration::from_secs(2))).ok();
+    let mut writer = stream
+        .try_clone()
+        .map_err(|e| format!("Failed to clone client stream: {}", e))?;
+
+    let response = match ipc::read_message::<Request>(&mut BufReader::new(stream)) {
+        Ok(Some(request)) => {
+            log::debug!("Control request: {:?}", request);
+            let (reply, response) = mpsc::channel();
+            requests
+                .send((request, reply))
+                .map_err(|_| "Daemon is shutting down".to_string())?;
+            response.recv().map_err(|_| "Daemon is shutting down".to_string())?
+        }
+        Ok(None) => return Ok(()),
+        Err(e) => Response::error(e),
+    };
+    ipc::write_message(&mut writer, &response)
+}
+
+/// Create `dir` for our socket, or check that the existing one belongs to us
+/// alone, so nobody else can plant a socket there or reach ours
+fn ensure_private_dir(dir: &Path) -> Result<(), String> {
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(format!("Failed to create {}: {}", dir.display(), e)),
+    }
+    let metadata = fs::symlink_metadata(dir).map_err(|e| format!("Failed to inspect {}: {}", dir.display(), e))?;
+    if !metadata.is_dir() || metadata.uid() != unsafe { libc::getuid() } || metadata.mode() & 0o077 != 0 {
+        return Err(format!("{} must be a directory owned by you that only you can access", dir.display()));
+    }
+    Ok(())
+}
+
+fn bind_socket(socket_path: &Path) -> Result<UnixListener, String> {
+    if let Some(dir) = socket_path.parent()
+        && dir == ipc::fallback_socket_dir()
+    {
+        ensure_private_dir(dir)?;
+    }
+    if socket_path.exists() {
+        if UnixStream::connect(socket_path).is_ok() {
+            return Err(format!("Another daemon is already listening on {}", socket_path.display()));
+        }
+        // Left over from a daemon that did not exit cleanly
+        fs::remove_file(socket_path)
+            .map_err(|e| format!("Failed to remove stale socket {}: {}", socket_path.display(), e))?;
+    }
+
+    // Create the socket as 0600 from the start instead of fixing it up after bind
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(socket_path);
+    unsafe { libc::umask(umask) };
+    let listener = listener.map_err(|e| format!("Failed to bind {}: {}", socket_path.display(), e))?;
+    fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600))
+        .map_err(|e| format!("Failed to restrict {} to the owner: {}", socket_path.display(), e))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| format!("Failed to configure socket: {}", e))?;
+    Ok(listener)
+}
+
+/// Serve control requests until SIGINT/SIGTERM, then stop the proxy and remove the socket
+pub fn run(mut daemon: Daemon, socket_path: &Path) -> Result<(), String> {
+    install_signal_handlers()?;
+    let listener = bind_socket(socket_path)?;
+
+    log::info!("Daemon listening on {}", socket_path.display());
+    daemon.start_auto_switch();
+
+    let (requests, pending) = mpsc::channel();
+    while !shutdown_requested() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                spawn_connection(stream, requests.clone());
+                continue;
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => log::warn!("Failed to accept control connection: {}", e),
+        }
+        daemon.reap();
+        daemon.poll_auto_switch();
+        // Waiting on the request channel doubles as the pause between accepts
+        if let Ok((request, reply)) = pending.recv_timeout(Duration::from_millis(50)) {
+            reply.send(daemon.handle_request(request)).ok();
+        }
+    }
+
+    log::info!("Shutdown signal received, stopping daemon");
+    daemon.shutdown();
+    fs::remove_file(socket_path).ok();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socket_is_private() {
+        let dir = std::env::temp_dir().join(format!("ferrisfire-socket-{}", std::process::id()));
+        ensure_private_dir(&dir).unwrap();
+        let socket_path = dir.join("ferrisfire.sock");
+        let listener = bind_socket(&socket_path);
+        let mode = fs::metadata(&socket_path).map(|metadata| metadata.mode() & 0o777);
+        let dir_mode = fs::metadata(&dir).map(|metadata| metadata.mode() & 0o777);
+
+        // A directory others can get into is refused
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        let shared = ensure_private_dir(&dir);
+        drop(listener);
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(mode.unwrap(), 0o600);
+        assert_eq!(dir_mode.unwrap(), 0o700);
+        assert!(shared.is_err());
+    }
+
+    #[test]
+    fn test_silent_client_does_not_block_others() {
+        let mut daemon = Daemon::new(Config::default(), None, None);
+        let (requests, pending) = mpsc::channel();
+        let (_silent, silent_server) = UnixStream::pair().unwrap();
+        spawn_connection(silent_server, requests.clone());
+
+        let (mut client, server) = UnixStream::pair().unwrap();
+        spawn_connection(server, requests);
+        ipc::write_message(&mut client, &Request::Status).unwrap();
+        let (request, reply) = pending.recv_timeout(Duration::from_secs(1)).unwrap();
+        reply.send(daemon.handle_request(request)).unwrap();
+
+        let response = ipc::read_message::<Response>(&mut BufReader::new(client)).unwrap();
+        assert!(matches!(response, Some(Response::Status(_))));
+    }
+
+    #[test]
+    fn test_status_of_idle_daemon() {
+        let mut daemon = Daemon::new(Config::default(), None, None);
+        match daemon.handle_request(Request::Status) {
+            Response::Status(status) => {
+                assert!(!status.running);
+                assert!(status.last_error.is_none());
+            }
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_start_rejects_invalid_config() {
+        let mut daemon = Daemon::new(Config::default(), None, None);
+        match daemon.handle_request(Request::Start { config: None }) {
+            Response::Error { message } => assert_eq!(message, "No device selected"),
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_stop_when_idle_is_an_error() {
+        let mut daemon = Daemon::new(Config::default(), None, None);
+        assert!(matches!(daemon.handle_request(Request::Stop), Response::Error { .. }));
+    }
+
+    #[test]
+    fn test_stats_before_first_run_are_empty() {
+        let mut daemon = Daemon::new(Config::default(), None, None);
+        match daemon.handle_request(Request::Stats) {
+            Response::Stats { stats } => {
+                assert_eq!(stats.clicks, 0);
+                assert_eq!(stats.forward_latency.count, 0);
+            }
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reload_replaces_config_while_idle() {
+        let mut daemon = Daemon::new(Config::default(), None, None);
+        // Whatever is plugged into this machine must not leak into the config
+        daemon.connected_devices = Vec::new;
+        let config = Config {
+            device_path: "/dev/input/event5".to_string(),
+            click_delay_min_ms: 60,
+            click_delay_max_ms: 90,
+            ..Default::default()
+        };
+        assert!(matches!(
+            daemon.handle_request(Request::Reload { config: Some(Box::new(config.clone())), profile: None }),
+            Response::Ok
+        ));
+        assert_eq!(daemon.status().config, config);
+    }
+}
@@ -1,102 +1,105 @@
 use evdev::{
-    uinput::VirtualDevice, AttributeSet, Device, EventType, InputId, KeyCode, RelativeAxisCode,
+    uinput::VirtualDevice, AttributeSet, Device, EventType, InputEvent, InputId, KeyCode, RelativeAxisCode,
 };
+use crate::config::Config;
+use crate::inspect::{classify, DeviceDetails, DeviceKind};
+use crate::macros::{MacroRecording, MacroStep};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::io;
-use std::os::fd::AsRawFd;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceInfo {
     pub path: String,
     pub name: String,
     pub vendor_id: u16,
     pub product_id: u16,
+    #[serde(default)]
+    pub phys: Option<String>,
+    #[serde(default)]
+    pub uniq: Option<String>,
+    #[serde(default)]
+    pub details: DeviceDetails,
 }
 
 impl DeviceInfo {
+    fn new(path: &Path, device: &mut Device) -> Self {
+        let identity = DeviceIdentity::of(device);
+        Self {
+            details: DeviceDetails::inspect(path, device),
+            path: path.to_string_lossy().to_string(),
+            name: identity.name,
+            vendor_id: identity.vendor_id,
+            product_id: identity.product_id,
+            phys: identity.phys,
+            uniq: identity.uniq,
+        }
+    }
+
     pub fn display_name(&self) -> String {
         format!("{} ({:04x}:{:04x})", self.name, self.vendor_id, self.product_id)
     }
-}
 
-pub fn enumerate_mice() -> Vec<DeviceInfo> {
-    let mut devices = Vec::new();
+    /// Everything known about the device, one detail per line
+    pub fn description(&self) -> String {
+        let mut lines = vec![format!("{} - {}", self.display_name(), self.path)];
+        lines.extend(self.phys.iter().map(|phys| format!("Physical path: {}", phys)));
+        lines.extend(self.uniq.iter().map(|uniq| format!("Unique id: {}", uniq)));
+        lines.extend(self.details.lines());
+        lines.join("\n")
+    }
 
-    // Try evdev enumerate first
-    for entry in evdev::enumerate() {
-        let (path, device) = entry;
-        if is_mouse(&device) {
-            let id = device.input_id();
-            devices.push(DeviceInfo {
-                path: path.to_string_lossy().to_string(),
-                name: device.name().unwrap_or("Unknown Device").to_string(),
-                vendor_id: id.vendor(),
-                product_id: id.product(),
-            });
+    pub fn identity(&self) -> DeviceIdentity {
+        DeviceIdentity {
+            name: self.name.clone(),
+            vendor_id: self.vendor_id,
+            product_id: self.product_id,
+            phys: self.phys.clone(),
+            uniq: self.uniq.clone(),
         }
     }
+}
 
-    // If evdev enumerate returned nothing, try manual scan
-    if devices.is_empty() {
-        log::warn!("evdev::enumerate() returned no mice, trying manual scan");
-        devices = manual_scan_input_devices(true);
+/// Devices that are most likely mice, likely gaming mice first
+pub fn enumerate_mice() -> Vec<DeviceInfo> {
+    let devices = enumerate_all_input_devices();
+    if devices.iter().any(|device| device.details.kind == DeviceKind::Mouse) {
+        return devices.into_iter().filter(|device| device.details.kind == DeviceKind::Mouse).collect();
     }
 
+    // Nothing looks like a mouse; better to offer anything that points than nothing
+    log::warn!("No device classified as a mouse, listing all pointing devices");
     devices
+        .into_iter()
+        .filter(|device| matches!(device.details.kind, DeviceKind::PointingStick | DeviceKind::Touchpad))
+        .collect()
 }
 
-fn is_mouse(device: &Device) -> bool {
-    // Check for mouse-like buttons
-    let has_mouse_buttons = device.supported_keys().map_or(false, |keys| {
-        keys.contains(KeyCode::BTN_LEFT)
-            || keys.contains(KeyCode::BTN_RIGHT)
-            || keys.contains(KeyCode::BTN_MIDDLE)
-    });
-
-    // Check for relative axes (movement)
-    let has_relative = device.supported_relative_axes().map_or(false, |axes| {
-        axes.contains(RelativeAxisCode::REL_X) || axes.contains(RelativeAxisCode::REL_Y)
-    });
-
-    // Accept if it has mouse buttons OR relative axes (more permissive)
-    has_mouse_buttons || has_relative
-}
-
+/// Every input device except virtual ones, likely gaming mice first
 pub fn enumerate_all_input_devices() -> Vec<DeviceInfo> {
-    let mut devices = Vec::new();
-
-    for entry in evdev::enumerate() {
-        let (path, device) = entry;
-        let id = device.input_id();
-        let name = device.name().unwrap_or("Unknown Device").to_string();
-        
-        // Skip virtual/uinput devices we might have created
-        if name.to_lowercase().contains("virtual") {
-            continue;
-        }
-
-        devices.push(DeviceInfo {
-            path: path.to_string_lossy().to_string(),
-            name,
-            vendor_id: id.vendor(),
-            product_id: id.product(),
-        });
-    }
+    let mut devices: Vec<DeviceInfo> = evdev::enumerate()
+        .map(|(path, mut device)| DeviceInfo::new(&path, &mut device))
+        .collect();
 
     // If evdev enumerate returned nothing, try manual scan
     if devices.is_empty() {
         log::warn!("evdev::enumerate() returned no devices, trying manual scan");
-        devices = manual_scan_input_devices(false);
+        devices = manual_scan_input_devices();
     }
 
+    // Skip virtual/uinput devices, including the ones we create
+    devices.retain(|device| device.details.kind != DeviceKind::Virtual);
+    devices.sort_by(|a, b| b.details.score.cmp(&a.details.score).then_with(|| a.path.cmp(&b.path)));
     devices
 }
 
-fn manual_scan_input_devices(mice_only: bool) -> Vec<DeviceInfo> {
+fn manual_scan_input_devices() -> Vec<DeviceInfo> {
     let mut devices = Vec::new();
     let input_dir = Path::new("/dev/input");
 
@@ -111,25 +114,7 @@ fn manual_scan_input_devices(mice_only: bool) -> Vec<DeviceInfo> {
             }
 
             match Device::open(&path) {
-                Ok(device) => {
-                    if mice_only && !is_mouse(&device) {
-                        continue;
-                    }
-
-                    let id = device.input_id();
-                    let name = device.name().unwrap_or("Unknown Device").to_string();
-                    
-                    if name.to_lowercase().contains("virtual") {
-                        continue;
-                    }
-
-                    devices.push(DeviceInfo {
-                        path: path.to_string_lossy().to_string(),
-                        name,
-                        vendor_id: id.vendor(),
-                        product_id: id.product(),
-                    });
-                }
+                Ok(mut device) => devices.push(DeviceInfo::new(&path, &mut device)),
                 Err(e) => {
                     log::debug!("Cannot open {}: {} (permission denied?)", path.display(), e);
                 }
@@ -139,8 +124,6 @@ fn manual_scan_input_devices(mice_only: bool) -> Vec<DeviceInfo> {
         log::error!("Cannot read /dev/input directory");
     }
 
-    // Sort by path for consistent ordering
-    devices.sort_by(|a, b| a.path.cmp(&b.path));
     devices
 }
 
@@ -148,52 +131,308 @@ pub fn open_device(path: &str) -> io::Result<Device> {
     Device::open(path)
 }
 
-/// Record a button press from the device and return its key code.
+/// What identifies a physical device across replugs, when its
+/// `/dev/input/eventN` path may change
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceIdentity {
+    pub name: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// Physical location, e.g. "usb-0000:00:14.0-2/input0"
+    #[serde(default)]
+    pub phys: Option<String>,
+    /// Serial number or other unique id, when the device reports one
+    #[serde(default)]
+    pub uniq: Option<String>,
+}
+
+impl DeviceIdentity {
+    pub fn of(device: &Device) -> Self {
+        let id = device.input_id();
+        Self {
+            name: device.name().unwrap_or("Unknown Device").to_string(),
+            vendor_id: id.vendor(),
+            product_id: id.product(),
+            phys: device.physical_path().map(str::to_string),
+            uniq: device.unique_name().filter(|uniq| !uniq.is_empty()).map(str::to_string),
+        }
+    }
+
+    /// Same model of device, wherever it is plugged in
+    fn same_model(&self, other: &DeviceIdentity) -> bool {
+        self.name == other.name && self.vendor_id == other.vendor_id && self.product_id == other.product_id
+    }
+
+    /// Same serial number, when there is one to compare
+    fn same_uniq(&self, other: &DeviceIdentity) -> bool {
+        self.uniq.is_some() && self.uniq == other.uniq
+    }
+
+    /// Same interface of a multi-node device (the "input1" in "usb-.../input1"),
+    /// which stays the same when only the port changes
+    fn same_interface(&self, other: &DeviceIdentity) -> bool {
+        let interface = |identity: &DeviceIdentity| {
+            identity.phys.as_deref().and_then(|phys| phys.rsplit_once('/')).map(|(_, interface)| interface.to_string())
+        };
+        interface(self).is_some() && interface(self) == interface(other)
+    }
+}
+
+/// How a saved identity matches the connected devices
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceMatch {
+    Found(String),
+    /// More than one device fits equally well
+    Ambiguous(Vec<String>),
+    Missing,
+}
+
+/// How closely `candidate` fits `identity`: 0 for an exact match, then the same
+/// model with the same serial number, on the same interface, or anywhere.
+/// None if it is another model.
+fn match_tier(identity: &DeviceIdentity, candidate: &DeviceIdentity) -> Option<usize> {
+    if candidate == identity {
+        Some(0)
+    } else if !candidate.same_model(identity) {
+        None
+    } else if candidate.same_uniq(identity) {
+        Some(1)
+    } else if candidate.same_interface(identity) {
+        Some(2)
+    } else {
+        Some(3)
+    }
+}
+
+/// Find `identity` among `candidates`. The closest fit decides (see `match_tier`).
+pub fn match_identity(identity: &DeviceIdentity, candidates: &[(String, DeviceIdentity)]) -> DeviceMatch {
+    let tiers: Vec<(String, usize)> = candidates
+        .iter()
+        .filter_map(|(path, candidate)| match_tier(identity, candidate).map(|tier| (path.clone(), tier)))
+        .collect();
+    let Some(best) = tiers.iter().map(|(_, tier)| *tier).min() else {
+        return DeviceMatch::Missing;
+    };
+    let mut paths: Vec<String> = tiers.into_iter().filter(|(_, tier)| *tier == best).map(|(path, _)| path).collect();
+    match paths.len() {
+        1 => DeviceMatch::Found(paths.remove(0)),
+        _ => DeviceMatch::Ambiguous(paths),
+    }
+}
+
+/// Find all of `identities` among `candidates` together, giving each one its
+/// own node. The nodes of a multi-node mouse can each fit every identity of
+/// that mouse, which only makes them ambiguous one at a time. Picks the split
+/// with the closest fits overall; None when the identities cannot all get a
+/// node, or when equally close splits would use different nodes.
+pub fn match_identities(
+    identities: &[&DeviceIdentity],
+    candidates: &[(String, DeviceIdentity)],
+) -> Option<Vec<String>> {
+    let tiers: Vec<Vec<Option<usize>>> = identities
+        .iter()
+        .map(|identity| candidates.iter().map(|(_, candidate)| match_tier(identity, candidate)).collect())
+        .collect();
+    let mut splits = Vec::new();
+    collect_splits(&tiers, &mut Vec::new(), 0, &mut splits);
+
+    let best = splits.iter().map(|(tier, _)| *tier).min()?;
+    let mut best_splits = splits.into_iter().filter(|(tier, _)| *tier == best).map(|(_, split)| split);
+    let split = best_splits.next()?;
+    let nodes = |split: &[usize]| {
+        let mut nodes = split.to_vec();
+        nodes.sort_unstable();
+        nodes
+    };
+    if best_splits.any(|other| nodes(&other) != nodes(&split)) {
+        return None;
+    }
+    Some(split.into_iter().map(|index| candidates[index].0.clone()).collect())
+}
+
+/// Every way to give each identity its own candidate, as candidate indices
+/// with the sum of their tiers
+fn collect_splits(
+    tiers: &[Vec<Option<usize>>],
+    taken: &mut Vec<usize>,
+    sum: usize,
+    splits: &mut Vec<(usize, Vec<usize>)>,
+) {
+    let Some(fits) = tiers.get(taken.len()) else {
+        splits.push((sum, taken.clone()));
+        return;
+    };
+    for (index, tier) in fits.iter().enumerate() {
+        if let Some(tier) = tier
+            && !taken.contains(&index)
+        {
+            taken.push(index);
+            collect_splits(tiers, taken, sum + tier, splits);
+            taken.pop();
+        }
+    }
+}
+
+/// Path and identity of every event node that can be opened, except virtual
+/// ones: our own virtual mouse carries the identity of the real one
+pub fn connected_devices() -> Vec<(String, DeviceIdentity)> {
+    evdev::enumerate()
+        .filter(|(path, device)| classify(path, device).0 != DeviceKind::Virtual)
+        .map(|(path, device)| (path.to_string_lossy().to_string(), DeviceIdentity::of(&device)))
+        .collect()
+}
+
+/// Look for the devices with `identities` among the event nodes that can be opened
+pub fn find_devices(identities: &[&DeviceIdentity]) -> Option<Vec<String>> {
+    match_identities(identities, &connected_devices())
+}
+
+/// A saved device that several connected devices could be
+#[derive(Debug, Clone, PartialEq)]
+pub struct AmbiguousDevice {
+    /// Path the device had when it was saved
+    pub path: String,
+    pub identity: DeviceIdentity,
+    pub candidates: Vec<String>,
+}
+
+/// Point the config's device paths at wherever their saved identities are
+/// connected now. Devices saved without an identity (older configs) have it
+/// filled in from `connected`. Returns the devices that need the user to pick
+/// between several candidates; their paths are left unchanged.
+pub fn resolve_devices(config: &mut Config, connected: &[(String, DeviceIdentity)]) -> Vec<AmbiguousDevice> {
+    let mut moves = HashMap::new();
+    let mut ambiguous = Vec::new();
+    for path in config.configured_device_paths() {
+        let Some(identity) = config.device_identities.get(path) else {
+            continue;
+        };
+        match match_identity(identity, connected) {
+            DeviceMatch::Found(found) if found == *path => {}
+            DeviceMatch::Found(found) => {
+                log::info!("{} moved from {} to {}", identity.name, path, found);
+                moves.insert(path.clone(), found);
+            }
+            // The saved path is still one of them, most likely the right one
+            DeviceMatch::Ambiguous(candidates) if candidates.contains(path) => {}
+            DeviceMatch::Ambiguous(candidates) => ambiguous.push(AmbiguousDevice {
+                path: path.clone(),
+                identity: identity.clone(),
+                candidates,
+            }),
+            DeviceMatch::Missing => log::warn!("{} ({}) is not connected", identity.name, path),
+        }
+    }
+    config.move_devices(&moves);
+    for (path, identity) in connected {
+        if config.configured_device_paths().contains(&path) && !config.device_identities.contains_key(path) {
+            config.device_identities.insert(path.clone(), identity.clone());
+        }
+    }
+    ambiguous
+}
+
+/// Resolve `config`'s devices with nobody to ask, failing when a saved device
+/// could be any of several connected ones
+pub fn resolve_config_devices(config: &mut Config) -> Result<(), String> {
+    resolve_config_devices_among(config, &connected_devices())
+}
+
+/// `resolve_config_devices` against a given list of connected devices
+pub fn resolve_config_devices_among(config: &mut Config, connected: &[(String, DeviceIdentity)]) -> Result<(), String> {
+    match resolve_devices(config, connected).first() {
+        Some(device) => Err(format!(
+            "Several connected devices match {} ({}), select one of them",
+            device.identity.name,
+            device.candidates.join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Notices event nodes appearing in /dev/input through inotify
+pub struct InputDirWatcher {
+    fd: OwnedFd,
+}
+
+impl InputDirWatcher {
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        // udev sets permissions after creating the node, so watch attribute changes too
+        let ret = unsafe {
+            libc::inotify_add_watch(fd.as_raw_fd(), c"/dev/input".as_ptr(), libc::IN_CREATE | libc::IN_ATTRIB)
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { fd })
+    }
+
+    /// Block until something changed in /dev/input or `timeout` passed
+    pub fn wait(&self, timeout: Duration) -> io::Result<()> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout_ms = timeout.as_millis().clamp(1, i32::MAX as u128) as i32;
+        let ret = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            return if err.kind() == io::ErrorKind::Interrupted { Ok(()) } else { Err(err) };
+        }
+        // Drain the queued notifications; only the wakeup matters
+        let mut buf = [0u8; 4096];
+        while unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len()) } > 0 {}
+        Ok(())
+    }
+}
+
+/// Record a button press from any of the devices and return its key code.
 /// Returns None if cancelled or timed out.
 pub fn record_button_press(
-    device_path: &str,
+    device_paths: &[String],
     cancel: Arc<AtomicBool>,
     timeout: Duration,
 ) -> Option<(u16, String)> {
-    let mut device = match Device::open(device_path) {
-        Ok(d) => d,
+    let mut devices = match open_for_recording(device_paths) {
+        Ok(devices) => devices,
         Err(e) => {
             log::error!("Failed to open device for recording: {}", e);
             return None;
         }
     };
 
-    // Set non-blocking mode
-    let fd = device.as_raw_fd();
-    unsafe {
-        let flags = libc::fcntl(fd, libc::F_GETFL);
-        libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
-    }
-
     let start = Instant::now();
     
-    log::info!("Recording button press from {}...", device_path);
+    log::info!("Recording button press from {}...", device_paths.join(", "));
 
     while !cancel.load(Ordering::Relaxed) && start.elapsed() < timeout {
-        match device.fetch_events() {
-            Ok(events) => {
-                for event in events {
-                    // Only capture key press events (value == 1)
-                    if event.event_type() == EventType::KEY && event.value() == 1 {
-                        let code = event.code();
-                        let key_code = KeyCode(code);
-                        let name = format!("{:?}", key_code);
-                        log::info!("Recorded button: {} (code {})", name, code);
-                        return Some((code, name));
+        for device in &mut devices {
+            match device.fetch_events() {
+                Ok(events) => {
+                    for event in events {
+                        // Only capture key press events (value == 1)
+                        if event.event_type() == EventType::KEY && event.value() == 1 {
+                            let code = event.code();
+                            let key_code = KeyCode(code);
+                            let name = format!("{:?}", key_code);
+                            log::info!("Recorded button: {} (code {})", name, code);
+                            return Some((code, name));
+                        }
                     }
                 }
-            }
-            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                // No events available
-            }
-            Err(e) => {
-                log::error!("Error reading events during recording: {}", e);
-                return None;
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    // No events available
+                }
+                Err(e) => {
+                    log::error!("Error reading events during recording: {}", e);
+                    return None;
+                }
             }
         }
         std::thread::sleep(Duration::from_millis(10));
@@ -203,7 +442,85 @@ pub fn record_button_press(
     None
 }
 
-pub fn create_virtual_clone(physical: &Device) -> io::Result<VirtualDevice> {
+/// Open devices in non-blocking mode, without grabbing them
+fn open_for_recording(device_paths: &[String]) -> io::Result<Vec<Device>> {
+    let mut devices = Vec::new();
+    for device_path in device_paths {
+        let device = Device::open(device_path)?;
+
+        // Set non-blocking mode
+        let fd = device.as_raw_fd();
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+        }
+        devices.push(device);
+    }
+    Ok(devices)
+}
+
+/// Record key presses and motion from the devices as a macro until
+/// `stop_key` is pressed on any of them or `limit` passes. Waits come from
+/// the kernel timestamps and start with the first recorded event.
+pub fn record_macro(
+    device_paths: &[String],
+    stop_key: KeyCode,
+    cancel: Arc<AtomicBool>,
+    limit: Duration,
+) -> Result<Vec<MacroStep>, String> {
+    let mut devices =
+        open_for_recording(device_paths).map_err(|e| format!("Failed to open device for recording: {}", e))?;
+    let mut recording = MacroRecording::new();
+    let mut first_event = SystemTime::UNIX_EPOCH;
+    let start = Instant::now();
+
+    log::info!("Recording macro from {} until {:?}...", device_paths.join(", "), stop_key);
+
+    'record: while start.elapsed() < limit {
+        if cancel.load(Ordering::Relaxed) {
+            return Err("Recording cancelled".to_string());
+        }
+        for device in &mut devices {
+            match device.fetch_events() {
+                Ok(events) => {
+                    for event in events {
+                        if event.event_type() == EventType::KEY && event.code() == stop_key.0 {
+                            if event.value() == 1 {
+                                break 'record;
+                            }
+                            continue;
+                        }
+                        if recording.is_empty() {
+                            first_event = event.timestamp();
+                        }
+                        let at = event.timestamp().duration_since(first_event).unwrap_or(Duration::ZERO);
+                        recording.push(at, &event);
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    // No events available
+                }
+                Err(e) => return Err(format!("Error reading events during recording: {}", e)),
+            }
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    if recording.is_empty() {
+        return Err("Nothing was recorded".to_string());
+    }
+    let steps = recording.into_steps();
+    log::info!("Recorded a macro of {} steps", steps.len());
+    Ok(steps)
+}
+
+/// Create a virtual device with the identity of the first of `nodes` and the
+/// capabilities of all of them, plus `extra_keys` so the proxy can emit
+/// outputs the physical device does not have
+pub fn create_virtual_clone(nodes: &[Device], extra_keys: &[KeyCode]) -> io::Result<VirtualDevice> {
+    let physical = nodes
+        .first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No device to clone"))?;
     let id = physical.input_id();
     let name = physical.name().unwrap_or("Mouse");
 
@@ -211,29 +528,90 @@ pub fn create_virtual_clone(physical: &Device) -> io::Result<VirtualDevice> {
         .name(name.as_bytes())
         .input_id(InputId::new(id.bus_type(), id.vendor(), id.product(), id.version()));
 
-    if let Some(keys) = physical.supported_keys() {
-        builder = builder.with_keys(&keys)?;
-    } else {
-        let mut keys = AttributeSet::<KeyCode>::new();
+    let mut keys = AttributeSet::<KeyCode>::new();
+    for supported in nodes.iter().filter_map(|node| node.supported_keys()) {
+        for key in supported.iter() {
+            keys.insert(key);
+        }
+    }
+    if keys.iter().next().is_none() {
         keys.insert(KeyCode::BTN_LEFT);
         keys.insert(KeyCode::BTN_RIGHT);
         keys.insert(KeyCode::BTN_MIDDLE);
         keys.insert(KeyCode::BTN_SIDE);
         keys.insert(KeyCode::BTN_EXTRA);
-        builder = builder.with_keys(&keys)?;
     }
+    for key in extra_keys {
+        keys.insert(*key);
+    }
+    builder = builder.with_keys(&keys)?;
 
-    if let Some(rel_axes) = physical.supported_relative_axes() {
-        builder = builder.with_relative_axes(&rel_axes)?;
-    } else {
-        let mut axes = AttributeSet::<RelativeAxisCode>::new();
-        axes.insert(RelativeAxisCode::REL_X);
-        axes.insert(RelativeAxisCode::REL_Y);
-        axes.insert(RelativeAxisCode::REL_WHEEL);
-        axes.insert(RelativeAxisCode::REL_HWHEEL);
-        builder = builder.with_relative_axes(&axes)?;
+    let mut rel_axes = AttributeSet::<RelativeAxisCode>::new();
+    for supported in nodes.iter().filter_map(|node| node.supported_relative_axes()) {
+        for axis in supported.iter() {
+            rel_axes.insert(axis);
+        }
+    }
+    if rel_axes.iter().next().is_none() {
+        rel_axes.insert(RelativeAxisCode::REL_X);
+        rel_axes.insert(RelativeAxisCode::REL_Y);
+        rel_axes.insert(RelativeAxisCode::REL_WHEEL);
+        rel_axes.insert(RelativeAxisCode::REL_HWHEEL);
     }
+    builder = builder.with_relative_axes(&rel_axes)?;
+
+    builder.build()
+}
 
+/// Create a virtual device with a grabbed trigger device's identity and keys,
+/// to pass on the keys that are not triggers
+pub fn create_virtual_key_clone(physical: &Device) -> io::Result<VirtualDevice> {
+    let id = physical.input_id();
+    let name = physical.name().unwrap_or("Keyboard");
+
+    let mut builder = VirtualDevice::builder()?
+        .name(name.as_bytes())
+        .input_id(InputId::new(id.bus_type(), id.vendor(), id.product(), id.version()));
+    if let Some(keys) = physical.supported_keys() {
+        builder = builder.with_keys(keys)?;
+    }
+    builder.build()
+}
+
+/// Create a virtual device able to emit `events` and `extra_keys`, for playing
+/// back a recorded session without the devices it was recorded on. A `pointer`
+/// always gets a left button and X/Y motion so it is picked up as a mouse.
+pub fn create_replay_device(
+    name: &str,
+    events: &[InputEvent],
+    extra_keys: &[KeyCode],
+    pointer: bool,
+) -> io::Result<VirtualDevice> {
+    let mut keys = AttributeSet::<KeyCode>::new();
+    let mut rel_axes = AttributeSet::<RelativeAxisCode>::new();
+    for event in events {
+        match event.event_type() {
+            EventType::KEY => keys.insert(KeyCode(event.code())),
+            EventType::RELATIVE => rel_axes.insert(RelativeAxisCode(event.code())),
+            _ => {}
+        }
+    }
+    for key in extra_keys {
+        keys.insert(*key);
+    }
+    if pointer {
+        keys.insert(KeyCode::BTN_LEFT);
+        rel_axes.insert(RelativeAxisCode::REL_X);
+        rel_axes.insert(RelativeAxisCode::REL_Y);
+    }
+
+    let mut builder = VirtualDevice::builder()?.name(name.as_bytes());
+    if keys.iter().next().is_some() {
+        builder = builder.with_keys(&keys)?;
+    }
+    if rel_axes.iter().next().is_some() {
+        builder = builder.with_relative_axes(&rel_axes)?;
+    }
     builder.build()
 }
 
@@ -248,6 +626,9 @@ mod tests {
             name: "Logitech G502".to_string(),
             vendor_id: 0x046d,
             product_id: 0xc08b,
+            phys: None,
+            uniq: None,
+            details: DeviceDetails::default(),
         };
         assert_eq!(info.display_name(), "Logitech G502 (046d:c08b)");
     }
@@ -259,10 +640,38 @@ mod tests {
             name: "Generic Mouse".to_string(),
             vendor_id: 0x0001,
             product_id: 0x0002,
+            phys: None,
+            uniq: None,
+            details: DeviceDetails::default(),
         };
         assert_eq!(info.display_name(), "Generic Mouse (0001:0002)");
     }
 
+    #[test]
+    fn test_device_info_description() {
+        let info = DeviceInfo {
+            path: "/dev/input/event5".to_string(),
+            name: "Logitech G502".to_string(),
+            vendor_id: 0x046d,
+            product_id: 0xc08b,
+            phys: Some("usb-0000:00:14.0-2/input1".to_string()),
+            uniq: None,
+            details: DeviceDetails {
+                bus: "USB".to_string(),
+                capabilities: "12 keys".to_string(),
+                ..Default::default()
+            },
+        };
+        assert_eq!(
+            info.description(),
+            "Logitech G502 (046d:c08b) - /dev/input/event5\n\
+             Physical path: usb-0000:00:14.0-2/input1\n\
+             Kind: Other (score 0)\n\
+             Bus: USB (version 0000)\n\
+             Reports: 12 keys"
+        );
+    }
+
     #[test]
     fn test_device_info_clone() {
         let info = DeviceInfo {
@@ -270,6 +679,9 @@ mod tests {
             name: "Test Mouse".to_string(),
             vendor_id: 0x1234,
             product_id: 0x5678,
+            phys: None,
+            uniq: None,
+            details: DeviceDetails::default(),
         };
         let cloned = info.clone();
         assert_eq!(cloned.path, info.path);
@@ -292,6 +704,175 @@ mod tests {
         assert!(result.is_err());
     }
 
+    fn identity(name: &str, phys: &str) -> DeviceIdentity {
+        DeviceIdentity {
+            name: name.to_string(),
+            vendor_id: 0x046d,
+            product_id: 0xc08b,
+            phys: Some(phys.to_string()),
+            uniq: None,
+        }
+    }
+
+    fn node(path: &str, identity: DeviceIdentity) -> (String, DeviceIdentity) {
+        (path.to_string(), identity)
+    }
+
+    fn found(path: &str) -> DeviceMatch {
+        DeviceMatch::Found(path.to_string())
+    }
+
+    #[test]
+    fn test_match_prefers_exact_identity() {
+        let mouse = identity("G502", "usb-1/input0");
+        let candidates = vec![
+            node("/dev/input/event7", identity("G502", "usb-2/input0")),
+            node("/dev/input/event8", mouse.clone()),
+        ];
+        assert_eq!(match_identity(&mouse, &candidates), found("/dev/input/event8"));
+    }
+
+    #[test]
+    fn test_match_follows_port_change_only_when_unambiguous() {
+        let mouse = identity("G502", "usb-1/input0");
+        let mut candidates = vec![
+            node("/dev/input/event7", identity("G502", "usb-3/input0")),
+            node("/dev/input/event9", identity("G502 Keyboard", "usb-3/input1")),
+        ];
+        assert_eq!(match_identity(&mouse, &candidates), found("/dev/input/event7"));
+
+        candidates.push(node("/dev/input/event10", identity("G502", "usb-4/input0")));
+        assert_eq!(
+            match_identity(&mouse, &candidates),
+            DeviceMatch::Ambiguous(vec!["/dev/input/event7".to_string(), "/dev/input/event10".to_string()])
+        );
+        assert_eq!(match_identity(&identity("G703", "usb-1/input0"), &candidates), DeviceMatch::Missing);
+    }
+
+    #[test]
+    fn test_match_tells_identical_mice_apart_by_serial() {
+        let serial = |uniq: &str, phys: &str| DeviceIdentity {
+            uniq: Some(uniq.to_string()),
+            ..identity("G502", phys)
+        };
+        let candidates = vec![
+            node("/dev/input/event7", serial("AAAA", "usb-3/input0")),
+            node("/dev/input/event8", serial("BBBB", "usb-4/input0")),
+        ];
+        assert_eq!(match_identity(&serial("BBBB", "usb-1/input0"), &candidates), found("/dev/input/event8"));
+    }
+
+    #[test]
+    fn test_match_keeps_node_of_multi_node_mouse_on_new_port() {
+        let candidates = vec![
+            node("/dev/input/event7", identity("G502", "usb-3/input0")),
+            node("/dev/input/event8", identity("G502", "usb-3/input1")),
+        ];
+        assert_eq!(match_identity(&identity("G502", "usb-1/input1"), &candidates), found("/dev/input/event8"));
+    }
+
+    #[test]
+    fn test_match_identities_splits_nodes_of_one_mouse() {
+        // Both nodes report the same name and location, so each alone is ambiguous
+        let node_id = identity("G502", "usb-1/input0");
+        let candidates = vec![
+            node("/dev/input/event7", identity("G502", "usb-3/input0")),
+            node("/dev/input/event8", identity("G502", "usb-3/input0")),
+        ];
+        assert!(matches!(match_identity(&node_id, &candidates), DeviceMatch::Ambiguous(_)));
+        assert_eq!(
+            match_identities(&[&node_id, &node_id], &candidates),
+            Some(vec!["/dev/input/event7".to_string(), "/dev/input/event8".to_string()])
+        );
+        assert_eq!(match_identities(&[&node_id, &node_id, &node_id], &candidates), None);
+    }
+
+    #[test]
+    fn test_match_identities_prefers_same_interface() {
+        let (pointer, buttons) = (identity("G502", "usb-1/input0"), identity("G502", "usb-1/input1"));
+        let candidates = vec![
+            node("/dev/input/event7", identity("G502", "usb-3/input1")),
+            node("/dev/input/event8", identity("G502", "usb-3/input0")),
+        ];
+        assert_eq!(
+            match_identities(&[&pointer, &buttons], &candidates),
+            Some(vec!["/dev/input/event8".to_string(), "/dev/input/event7".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_match_identities_refuses_to_guess_between_mice() {
+        let mouse = identity("G502", "usb-1/input0");
+        let candidates = vec![
+            node("/dev/input/event7", identity("G502", "usb-3/input0")),
+            node("/dev/input/event10", identity("G502", "usb-4/input0")),
+        ];
+        assert_eq!(match_identities(&[&mouse], &candidates), None);
+    }
+
+    #[test]
+    fn test_resolve_follows_swapped_event_numbers() {
+        let mouse = identity("G502", "usb-1/input0");
+        let side_buttons = identity("G502", "usb-1/input1");
+        let mut config = Config {
+            device_path: "/dev/input/event5".to_string(),
+            extra_device_paths: vec!["/dev/input/event6".to_string()],
+            ..Default::default()
+        };
+        config.remember_device("/dev/input/event5", mouse.clone());
+        config.remember_device("/dev/input/event6", side_buttons.clone());
+
+        let connected = vec![
+            node("/dev/input/event5", side_buttons.clone()),
+            node("/dev/input/event6", mouse.clone()),
+        ];
+        assert!(resolve_devices(&mut config, &connected).is_empty());
+        assert_eq!(config.device_path, "/dev/input/event6");
+        assert_eq!(config.extra_device_paths, vec!["/dev/input/event5"]);
+        assert_eq!(config.device_identities.get("/dev/input/event6"), Some(&mouse));
+        assert_eq!(config.device_identities.get("/dev/input/event5"), Some(&side_buttons));
+    }
+
+    #[test]
+    fn test_resolve_asks_when_several_devices_match() {
+        let mut config = Config {
+            device_path: "/dev/input/event5".to_string(),
+            ..Default::default()
+        };
+        config.remember_device("/dev/input/event5", identity("G502", "usb-1/input0"));
+        let connected = vec![
+            node("/dev/input/event7", identity("G502", "usb-3/input0")),
+            node("/dev/input/event8", identity("G502", "usb-4/input0")),
+        ];
+
+        let ambiguous = resolve_devices(&mut config, &connected);
+        assert_eq!(ambiguous.len(), 1);
+        assert_eq!(ambiguous[0].path, "/dev/input/event5");
+        assert_eq!(ambiguous[0].candidates, vec!["/dev/input/event7", "/dev/input/event8"]);
+        assert_eq!(config.device_path, "/dev/input/event5");
+
+        // Still at its saved path: keep it rather than ask
+        let mut config = Config {
+            device_path: "/dev/input/event7".to_string(),
+            ..Default::default()
+        };
+        config.remember_device("/dev/input/event7", identity("G502", "usb-1/input0"));
+        assert!(resolve_devices(&mut config, &connected).is_empty());
+        assert_eq!(config.device_path, "/dev/input/event7");
+    }
+
+    #[test]
+    fn test_resolve_learns_identity_of_older_configs() {
+        let mut config = Config {
+            device_path: "/dev/input/event5".to_string(),
+            ..Default::default()
+        };
+        let mouse = identity("G502", "usb-1/input0");
+        let connected = vec![node("/dev/input/event5", mouse.clone())];
+        assert!(resolve_devices(&mut config, &connected).is_empty());
+        assert_eq!(config.device_identities.get("/dev/input/event5"), Some(&mouse));
+    }
+
     #[test]
     fn test_device_info_debug() {
         let info = DeviceInfo {
@@ -299,6 +880,9 @@ mod tests {
             name: "Test".to_string(),
             vendor_id: 0x1234,
             product_id: 0x5678,
+            phys: None,
+            uniq: None,
+            details: DeviceDetails::default(),
         };
         let debug_str = format!("{:?}", info);
         assert!(debug_str.contains("DeviceInfo"));
@@ -0,0 +1,451 @@
+//! Automatic profile switching based on the focused application.
+//!
+//! An `ActiveWindowProvider` reports which process owns the focused window;
+//! `AutoSwitcher` maps it to a profile using rules matched against the
+//! executable name or command line read from /proc.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    /// File name of the executable, e.g. "cs2"
+    pub exe_name: String,
+    /// Arguments joined with spaces
+    pub cmdline: String,
+}
+
+impl ProcessInfo {
+    /// Read a process' executable name and command line from /proc
+    pub fn from_pid(pid: u32) -> Option<Self> {
+        let proc_dir = format!("/proc/{}", pid);
+
+        let exe_name = fs::read_link(format!("{}/exe", proc_dir))
+            .ok()
+            .and_then(|path| path.file_name().map(|n| n.to_string_lossy().to_string()))
+            .or_else(|| fs::read_to_string(format!("{}/comm", proc_dir)).ok().map(|s| s.trim().to_string()))?;
+
+        let cmdline = fs::read(format!("{}/cmdline", proc_dir))
+            .map(|raw| {
+                raw.split(|&b| b == 0)
+                    .filter(|arg| !arg.is_empty())
+                    .map(|arg| String::from_utf8_lossy(arg).to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .unwrap_or_default();
+
+        Some(Self { pid, exe_name, cmdline })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum MatchTarget {
+    /// Exact (case-insensitive) executable file name
+    #[default]
+    Executable,
+    /// Case-insensitive substring of the full command line
+    CommandLine,
+}
+
+impl MatchTarget {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            MatchTarget::Executable => "Executable",
+            MatchTarget::CommandLine => "Command line",
+        }
+    }
+
+    pub fn all() -> &'static [MatchTarget] {
+        &[MatchTarget::Executable, MatchTarget::CommandLine]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutoSwitchRule {
+    pub profile: String,
+    pub pattern: String,
+    #[serde(default)]
+    pub match_on: MatchTarget,
+}
+
+impl AutoSwitchRule {
+    pub fn matches(&self, process: &ProcessInfo) -> bool {
+        let pattern = self.pattern.trim().to_lowercase();
+        if pattern.is_empty() {
+            return false;
+        }
+        match self.match_on {
+            MatchTarget::Executable => process.exe_name.to_lowercase() == pattern,
+            MatchTarget::CommandLine => process.cmdline.to_lowercase().contains(&pattern),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct AutoSwitchSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Checked in order; the first match wins
+    #[serde(default)]
+    pub rules: Vec<AutoSwitchRule>,
+}
+
+/// Decides which profile should be active for the focused process
+pub struct AutoSwitcher {
+    rules: Vec<AutoSwitchRule>,
+    /// Profile used when no rule matches (the one picked by hand)
+    fallback: String,
+    current: Option<String>,
+}
+
+impl AutoSwitcher {
+    pub fn new(rules: Vec<AutoSwitchRule>, fallback: String) -> Self {
+        Self {
+            rules,
+            fallback,
+            current: None,
+        }
+    }
+
+    pub fn set_rules(&mut self, rules: Vec<AutoSwitchRule>) {
+        self.rules = rules;
+    }
+
+    pub fn set_fallback(&mut self, fallback: String) {
+        self.fallback = fallback;
+    }
+
+    /// Profile that should be active while `process` has focus
+    pub fn target(&self, process: Option<&ProcessInfo>) -> &str {
+        process
+            .and_then(|p| self.rules.iter().find(|rule| rule.matches(p)))
+            .map(|rule| rule.profile.as_str())
+            .unwrap_or(&self.fallback)
+    }
+
+    /// Returns the profile to switch to, or None if it has not changed since the last call
+    pub fn update(&mut self, process: Option<&ProcessInfo>) -> Option<String> {
+        let target = self.target(process).to_string();
+        if self.current.as_deref() == Some(target.as_str()) {
+            return None;
+        }
+        self.current = Some(target.clone());
+        Some(target)
+    }
+}
+
+/// Source of the process that owns the focused window
+pub trait ActiveWindowProvider: Send {
+    fn name(&self) -> &'static str;
+    fn active_process(&mut self) -> Option<ProcessInfo>;
+}
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// X11 (and XWayland-aware compositors exporting EWMH) via `xprop`
+pub struct X11Provider;
+
+/// Parse `xprop -root _NET_ACTIVE_WINDOW` output: "_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007"
+fn parse_xprop_window_id(output: &str) -> Option<String> {
+    let id = output.rsplit('#').next()?.trim();
+    let id = id.split(|c: char| c == ',' || c.is_whitespace()).next()?;
+    (id.starts_with("0x") && id != "0x0").then(|| id.to_string())
+}
+
+/// Parse `xprop -id <id> _NET_WM_PID` output: "_NET_WM_PID(CARDINAL) = 1234"
+fn parse_xprop_pid(output: &str) -> Option<u32> {
+    output.rsplit('=').next()?.trim().parse().ok()
+}
+
+impl ActiveWindowProvider for X11Provider {
+    fn name(&self) -> &'static str {
+        "X11 (xprop)"
+    }
+
+    fn active_process(&mut self) -> Option<ProcessInfo> {
+        let window = parse_xprop_window_id(&command_output("xprop", &["-root", "_NET_ACTIVE_WINDOW"])?)?;
+        let pid = parse_xprop_pid(&command_output("xprop", &["-id", &window, "_NET_WM_PID"])?)?;
+        ProcessInfo::from_pid(pid)
+    }
+}
+
+/// Hyprland via `hyprctl activewindow -j`
+pub struct HyprlandProvider;
+
+impl ActiveWindowProvider for HyprlandProvider {
+    fn name(&self) -> &'static str {
+        "Hyprland"
+    }
+
+    fn active_process(&mut self) -> Option<ProcessInfo> {
+        let output = command_output("hyprctl", &["activewindow", "-j"])?;
+        let json: serde_json::Value = serde_json::from_str(&output).ok()?;
+        let pid = json.get("pid")?.as_u64()?;
+        ProcessInfo::from_pid(u32::try_from(pid).ok()?)
+    }
+}
+
+/// Sway (and other i3-IPC compositors) via `swaymsg -t get_tree`
+pub struct SwayProvider;
+
+/// Depth-first search for the focused node's pid in a sway layout tree
+fn find_focused_pid(node: &serde_json::Value) -> Option<u32> {
+    if node.get("focused").and_then(|f| f.as_bool()) == Some(true)
+        && let Some(pid) = node.get("pid").and_then(|p| p.as_u64())
+    {
+        return u32::try_from(pid).ok();
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node.get(*key).and_then(|n| n.as_array()))
+        .flatten()
+        .find_map(find_focused_pid)
+}
+
+impl ActiveWindowProvider for SwayProvider {
+    fn name(&self) -> &'static str {
+        "Sway"
+    }
+
+    fn active_process(&mut self) -> Option<ProcessInfo> {
+        let output = command_output("swaymsg", &["-t", "get_tree"])?;
+        let tree: serde_json::Value = serde_json::from_str(&output).ok()?;
+        ProcessInfo::from_pid(find_focused_pid(&tree)?)
+    }
+}
+
+/// Pick a provider for the current desktop session, if it exposes the active window
+pub fn detect_provider() -> Option<Box<dyn ActiveWindowProvider>> {
+    let has_env = |name: &str| std::env::var_os(name).is_some_and(|v| !v.is_empty());
+
+    if has_env("HYPRLAND_INSTANCE_SIGNATURE") {
+        Some(Box::new(HyprlandProvider))
+    } else if has_env("SWAYSOCK") {
+        Some(Box::new(SwayProvider))
+    } else if has_env("DISPLAY") {
+        Some(Box::new(X11Provider))
+    } else {
+        None
+    }
+}
+
+/// Background thread polling a provider and reporting focus changes
+pub struct FocusWatcher {
+    changes: Receiver<Option<ProcessInfo>>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl FocusWatcher {
+    pub fn spawn(mut provider: Box<dyn ActiveWindowProvider>, interval: Duration) -> Self {
+        let (sender, changes) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = Arc::clone(&stop);
+
+        log::info!("Watching focused application via {}", provider.name());
+
+        let handle = thread::spawn(move || {
+            let mut last: Option<Option<ProcessInfo>> = None;
+            while !stop_flag.load(Ordering::Relaxed) {
+                let process = provider.active_process();
+                if last.as_ref() != Some(&process) {
+                    if sender.send(process.clone()).is_err() {
+                        break;
+                    }
+                    last = Some(process);
+                }
+                thread::sleep(interval);
+            }
+        });
+
+        Self {
+            changes,
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// Most recent focus change since the last call, if any
+    pub fn latest(&self) -> Option<Option<ProcessInfo>> {
+        self.changes.try_iter().last()
+    }
+}
+
+impl Drop for FocusWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Stand-in provider replaying a scripted focus history
+    struct ScriptedProvider {
+        focus: VecDeque<Option<ProcessInfo>>,
+    }
+
+    impl ActiveWindowProvider for ScriptedProvider {
+        fn name(&self) -> &'static str {
+            "scripted"
+        }
+
+        fn active_process(&mut self) -> Option<ProcessInfo> {
+            self.focus.pop_front().flatten()
+        }
+    }
+
+    fn process(exe_name: &str, cmdline: &str) -> ProcessInfo {
+        ProcessInfo {
+            pid: 1,
+            exe_name: exe_name.to_string(),
+            cmdline: cmdline.to_string(),
+        }
+    }
+
+    fn rules() -> Vec<AutoSwitchRule> {
+        vec![
+            AutoSwitchRule {
+                profile: "cs2".to_string(),
+                pattern: "CS2".to_string(),
+                match_on: MatchTarget::Executable,
+            },
+            AutoSwitchRule {
+                profile: "proton".to_string(),
+                pattern: "eldenring.exe".to_string(),
+                match_on: MatchTarget::CommandLine,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_rule_matching() {
+        let rules = rules();
+        assert!(rules[0].matches(&process("cs2", "")));
+        assert!(!rules[0].matches(&process("cs2-launcher", "")));
+        assert!(rules[1].matches(&process("wine64", "Z:\\games\\EldenRing.exe -dx12")));
+        assert!(!rules[1].matches(&process("eldenring.exe", "")));
+    }
+
+    #[test]
+    fn test_empty_pattern_never_matches() {
+        let rule = AutoSwitchRule {
+            profile: "x".to_string(),
+            pattern: "  ".to_string(),
+            match_on: MatchTarget::CommandLine,
+        };
+        assert!(!rule.matches(&process("anything", "anything")));
+    }
+
+    #[test]
+    fn test_switcher_follows_scripted_focus() {
+        let mut provider = ScriptedProvider {
+            focus: VecDeque::from(vec![
+                Some(process("firefox", "firefox")),
+                Some(process("cs2", "cs2 -steam")),
+                Some(process("cs2", "cs2 -steam")),
+                None,
+                Some(process("wine64", "eldenring.exe")),
+            ]),
+        };
+        let mut switcher = AutoSwitcher::new(rules(), "default".to_string());
+
+        let mut switches = Vec::new();
+        for _ in 0..5 {
+            switches.push(switcher.update(provider.active_process().as_ref()));
+        }
+
+        assert_eq!(
+            switches,
+            vec![
+                Some("default".to_string()),
+                Some("cs2".to_string()),
+                None,
+                Some("default".to_string()),
+                Some("proton".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fallback_change_applies_on_next_update() {
+        let mut switcher = AutoSwitcher::new(rules(), "default".to_string());
+        assert_eq!(switcher.update(None), Some("default".to_string()));
+        switcher.set_fallback("casual".to_string());
+        assert_eq!(switcher.update(None), Some("casual".to_string()));
+    }
+
+    #[test]
+    fn test_watcher_reports_changes_only() {
+        let provider = ScriptedProvider {
+            focus: VecDeque::from(vec![Some(process("cs2", "")), Some(process("cs2", ""))]),
+        };
+        let watcher = FocusWatcher::spawn(Box::new(provider), Duration::from_millis(1));
+
+        // Wait for each change as long as a loaded machine may need, instead of a fixed sleep
+        let changes: Vec<_> = (0..2)
+            .map_while(|_| watcher.changes.recv_timeout(Duration::from_secs(10)).ok())
+            .collect();
+        assert_eq!(changes, vec![Some(process("cs2", "")), None]);
+        // The provider reports nothing from here on, which is no change
+        assert!(watcher.changes.recv_timeout(Duration::from_millis(20)).is_err());
+    }
+
+    #[test]
+    fn test_parse_xprop_output() {
+        assert_eq!(
+            parse_xprop_window_id("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007\n"),
+            Some("0x3a00007".to_string())
+        );
+        assert_eq!(parse_xprop_window_id("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x0"), None);
+        assert_eq!(parse_xprop_pid("_NET_WM_PID(CARDINAL) = 4242\n"), Some(4242));
+        assert_eq!(parse_xprop_pid("_NET_WM_PID:  not found."), None);
+    }
+
+    #[test]
+    fn test_find_focused_pid_in_sway_tree() {
+        let tree: serde_json::Value = serde_json::from_str(
+            r#"{"focused": false, "nodes": [
+                {"focused": false, "pid": 10, "nodes": []},
+                {"focused": false, "nodes": [], "floating_nodes": [
+                    {"focused": true, "pid": 77, "nodes": []}
+                ]}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(find_focused_pid(&tree), Some(77));
+    }
+
+    #[test]
+    fn test_process_info_for_current_process() {
+        let info = ProcessInfo::from_pid(std::process::id()).unwrap();
+        assert!(!info.exe_name.is_empty());
+        assert!(!info.cmdline.is_empty());
+    }
+}
@@ -1,61 +1,352 @@
-use crate::config::{Config, TriggerButton};
-use crate::device::{enumerate_all_input_devices, enumerate_mice, record_button_press, DeviceInfo};
+use crate::config::{
+    key_name, ActivationMode, Binding, Config, RealtimePolicy, Remap, TriggerButton, OUTPUT_PRESETS, REMAP_TARGETS,
+};
+use crate::device::{
+    enumerate_all_input_devices, enumerate_mice, record_button_press, record_macro, resolve_devices, AmbiguousDevice,
+    DeviceIdentity, DeviceInfo,
+};
+use crate::focus::{self, AutoSwitchRule, AutoSwitcher, FocusWatcher, MatchTarget};
+use crate::humanize::TimingDistribution;
+use crate::inspect::DeviceKind;
+use crate::ipc::{self, DaemonStatus, Request, Response};
+use crate::macros::{format_script, parse_script, MacroStep};
+use crate::profile::ProfileStore;
 use crate::proxy::spawn_proxy;
+use crate::sched;
+use crate::stats::{self, Histogram, ProxyStats, SharedStats};
 use eframe::egui;
+use evdev::KeyCode;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
 use std::sync::Arc;
 use std::thread::JoinHandle;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+const DAEMON_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Key that ends a macro recording
+const MACRO_STOP_KEY: KeyCode = KeyCode::KEY_ESC;
+/// Longest a macro recording runs before it is saved as is
+const MACRO_RECORD_LIMIT: Duration = Duration::from_secs(60);
+
+/// What a recorded button press is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RecordTarget {
+    Trigger,
+    Chord,
+    Output,
+    /// A mouse button to remap
+    Remap,
+}
 
 pub struct FerrisFireApp {
+    /// Working copy of the active profile
     config: Config,
+    profiles: ProfileStore,
+    new_profile_name: String,
+    /// Path typed into the profile import/export field
+    profile_file_path: String,
+    focus_watcher: Option<FocusWatcher>,
+    auto_switcher: AutoSwitcher,
     available_devices: Vec<DeviceInfo>,
     selected_device_index: Option<usize>,
     show_all_devices: bool,
+    /// Candidates for the optional trigger keyboard
+    trigger_devices: Vec<DeviceInfo>,
+    /// Saved devices that several connected ones could be, asked about one at a time
+    device_prompts: Vec<AmbiguousDevice>,
+    /// Macro scripts as typed, by binding number (0 = the primary binding); a
+    /// script that does not parse stays here without changing the binding
+    macro_drafts: HashMap<usize, String>,
     running: bool,
     stop_signal: Arc<AtomicBool>,
     proxy_handle: Option<JoinHandle<Result<(), String>>>,
+    /// Live config channel into the local proxy thread
+    config_updates: Option<Sender<Config>>,
+    /// Config the running proxy was last given, to detect edits that need pushing
+    applied_config: Config,
+    /// Control socket of a running daemon; when set the proxy lives there, not in this process
+    daemon_socket: Option<PathBuf>,
+    last_daemon_poll: Instant,
+    /// Status poll running in the background so a slow daemon cannot stall the window
+    daemon_poll: Option<Receiver<DaemonPoll>>,
     status_message: String,
     error_message: Option<String>,
+    /// Real-time tuning the running proxy could not apply
+    tuning_warning: Option<String>,
+    /// Stats published by the local proxy thread
+    proxy_stats: SharedStats,
+    /// Latest stats from the local proxy or the daemon, as shown in the panel
+    stats_snapshot: ProxyStats,
     // Button recording state
     recording: bool,
     recording_cancel: Arc<AtomicBool>,
     recording_handle: Option<JoinHandle<Option<(u16, String)>>>,
+    recording_target: RecordTarget,
     recorded_button_name: Option<String>,
+    /// Macro being recorded in the background; shares `recording_cancel`
+    macro_recording: Option<JoinHandle<Result<Vec<MacroStep>, String>>>,
+    /// Number of the binding the macro is recorded for (0 = the primary binding)
+    macro_recording_binding: usize,
 }
 
 impl FerrisFireApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        let config = Config::load();
+    pub fn new(_cc: &eframe::CreationContext<'_>, profile: Option<String>) -> Self {
+        let mut profiles = ProfileStore::load();
+        let error_message = profile.and_then(|name| profiles.set_active(&name).err());
+        let mut config = profiles.active_config();
         let available_devices = enumerate_mice();
 
-        let selected_device_index = if !config.device_path.is_empty() {
-            available_devices
-                .iter()
-                .position(|d| d.path == config.device_path)
-        } else {
-            None
+        // Hand control to a daemon if one is listening, adopting its settings while it runs
+        let socket_path = ipc::default_socket_path();
+        let mut running = false;
+        let daemon_socket = match ipc::send_request(&socket_path, &Request::Status) {
+            Ok(Response::Status(status)) => {
+                log::info!("Connected to daemon at {}", socket_path.display());
+                if status.running {
+                    config = status.config;
+                    running = true;
+                }
+                Some(socket_path)
+            }
+            _ => None,
         };
 
-        // If there's a custom code, try to get its name
-        let recorded_button_name = config.custom_trigger_code.map(|code| {
-            format!("{:?}", evdev::KeyCode(code))
-        });
+        let status_message = match (&daemon_socket, running) {
+            (Some(_), true) => "Running (daemon)".to_string(),
+            (Some(_), false) => "Ready (daemon)".to_string(),
+            (None, _) => "Ready".to_string(),
+        };
 
-        Self {
+        let auto_switcher = AutoSwitcher::new(profiles.auto_switch.rules.clone(), profiles.active.clone());
+
+        let mut app = Self {
+            applied_config: config.clone(),
             config,
+            profiles,
+            focus_watcher: None,
+            auto_switcher,
+            new_profile_name: String::new(),
+            profile_file_path: String::new(),
             available_devices,
-            selected_device_index,
+            selected_device_index: None,
             show_all_devices: false,
-            running: false,
+            trigger_devices: enumerate_all_input_devices(),
+            device_prompts: Vec::new(),
+            macro_drafts: HashMap::new(),
+            running,
             stop_signal: Arc::new(AtomicBool::new(false)),
             proxy_handle: None,
-            status_message: "Ready".to_string(),
-            error_message: None,
+            config_updates: None,
+            daemon_socket,
+            last_daemon_poll: Instant::now(),
+            daemon_poll: None,
+            status_message,
+            error_message,
+            tuning_warning: None,
+            proxy_stats: SharedStats::default(),
+            stats_snapshot: ProxyStats::default(),
             recording: false,
             recording_cancel: Arc::new(AtomicBool::new(false)),
             recording_handle: None,
-            recorded_button_name,
+            recording_target: RecordTarget::Trigger,
+            recorded_button_name: None,
+            macro_recording: None,
+            macro_recording_binding: 0,
+        };
+        app.sync_from_config();
+        if app.profiles.auto_switch.enabled {
+            app.set_auto_switch(true);
+        }
+        app
+    }
+
+    /// Refresh state derived from the config after it was replaced wholesale
+    fn sync_from_config(&mut self) {
+        let connected: Vec<(String, DeviceIdentity)> =
+            self.trigger_devices.iter().map(|d| (d.path.clone(), d.identity())).collect();
+        self.device_prompts = resolve_devices(&mut self.config, &connected);
+        self.select_configured_device();
+        self.macro_drafts.clear();
+
+        // If there's a custom code, try to get its name
+        self.recorded_button_name = self.config.custom_trigger_code.map(|code| {
+            format!("{:?}", evdev::KeyCode(code))
+        });
+    }
+
+    /// Point the device picker at the configured mouse
+    fn select_configured_device(&mut self) {
+        self.selected_device_index = if !self.config.device_path.is_empty() {
+            self.available_devices
+                .iter()
+                .position(|d| d.path == self.config.device_path)
+        } else {
+            None
+        };
+    }
+
+    /// Ask which device a saved one is when several connected devices match it
+    fn show_device_prompt(&mut self, ctx: &egui::Context) {
+        let Some(prompt) = self.device_prompts.first() else {
+            return;
+        };
+        let mut chosen = None;
+        let mut dismissed = false;
+        egui::Window::new("Which device?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "Several connected devices match {} ({:04x}:{:04x}), saved as {}.",
+                    prompt.identity.name, prompt.identity.vendor_id, prompt.identity.product_id, prompt.path
+                ));
+                for path in &prompt.candidates {
+                    let phys = self.trigger_devices.iter().find(|d| d.path == *path).and_then(|d| d.phys.as_deref());
+                    let label = match phys {
+                        Some(phys) => format!("{} ({})", path, phys),
+                        None => path.clone(),
+                    };
+                    if ui.button(label).clicked() {
+                        chosen = Some(path.clone());
+                    }
+                }
+                if ui.button("Keep saved path").clicked() {
+                    dismissed = true;
+                }
+            });
+
+        if let Some(path) = chosen {
+            let saved = self.device_prompts.remove(0).path;
+            self.config.move_devices(&HashMap::from([(saved, path.clone())]));
+            if let Some(device) = self.trigger_devices.iter().find(|d| d.path == path) {
+                self.config.remember_device(&path, device.identity());
+            }
+            self.select_configured_device();
+        } else if dismissed {
+            self.device_prompts.remove(0);
+        }
+    }
+
+    /// Write the working config back into its profile and persist all profiles
+    fn save_profiles(&mut self) {
+        let active = self.profiles.active.clone();
+        self.profiles.insert(&active, self.config.clone()).ok();
+        self.profiles.save();
+    }
+
+    /// Make the store's active profile the working config. Profiles without a
+    /// device of their own keep using the currently selected one.
+    fn load_active_profile(&mut self) {
+        let mut config = self.profiles.active_config();
+        if config.device_path.is_empty() {
+            config.inherit_device(&self.config);
+        }
+        self.config = config;
+        self.sync_from_config();
+    }
+
+    /// Make `name` the working profile, storing edits to the current one first
+    fn activate_profile(&mut self, name: &str) -> bool {
+        if name == self.profiles.active {
+            return false;
+        }
+        let active = self.profiles.active.clone();
+        self.profiles.insert(&active, self.config.clone()).ok();
+        if let Err(e) = self.profiles.set_active(name) {
+            self.error_message = Some(e);
+            return false;
+        }
+        self.load_active_profile();
+        self.profiles.save();
+        true
+    }
+
+    /// Profile picked by hand; also becomes the auto-switch fallback
+    fn switch_profile(&mut self, name: &str) {
+        self.auto_switcher.set_fallback(name.to_string());
+        if self.activate_profile(name) {
+            self.status_message = format!("Switched to profile '{}'", name);
+        }
+    }
+
+    fn set_auto_switch(&mut self, enabled: bool) {
+        self.profiles.auto_switch.enabled = enabled;
+        self.focus_watcher = None;
+        if !enabled {
+            return;
+        }
+        match focus::detect_provider() {
+            Some(provider) => {
+                self.auto_switcher = AutoSwitcher::new(
+                    self.profiles.auto_switch.rules.clone(),
+                    self.profiles.active.clone(),
+                );
+                self.focus_watcher = Some(FocusWatcher::spawn(provider, focus::POLL_INTERVAL));
+            }
+            None => {
+                self.error_message =
+                    Some("No supported active-window provider (X11, Hyprland or Sway) found".to_string());
+            }
+        }
+    }
+
+    fn poll_focus(&mut self) {
+        let Some(process) = self.focus_watcher.as_ref().and_then(|w| w.latest()) else {
+            return;
+        };
+        self.auto_switcher.set_rules(self.profiles.auto_switch.rules.clone());
+        if let Some(name) = self.auto_switcher.update(process.as_ref())
+            && self.activate_profile(&name)
+        {
+            let app = process.map(|p| p.exe_name).unwrap_or_else(|| "no window".to_string());
+            self.status_message = format!("Auto-switched to profile '{}' ({})", name, app);
+        }
+    }
+
+    fn save_profile_as(&mut self) {
+        let name = self.new_profile_name.trim().to_string();
+        if let Err(e) = self.profiles.insert(&name, self.config.clone()) {
+            self.error_message = Some(e);
+            return;
+        }
+        self.profiles.set_active(&name).ok();
+        self.profiles.save();
+        self.new_profile_name.clear();
+        self.status_message = format!("Saved profile '{}'", name);
+    }
+
+    fn delete_active_profile(&mut self) {
+        let active = self.profiles.active.clone();
+        if let Err(e) = self.profiles.remove(&active) {
+            self.error_message = Some(e);
+            return;
+        }
+        self.load_active_profile();
+        self.profiles.save();
+        self.status_message = format!("Deleted profile '{}'", active);
+    }
+
+    fn import_profile(&mut self) {
+        let path = PathBuf::from(self.profile_file_path.trim());
+        match self.profiles.import_profile(&path) {
+            Ok(name) => {
+                self.profiles.save();
+                self.status_message = format!("Imported profile '{}'", name);
+            }
+            Err(e) => self.error_message = Some(e),
+        }
+    }
+
+    fn export_active_profile(&mut self) {
+        let path = PathBuf::from(self.profile_file_path.trim());
+        let active = self.profiles.active.clone();
+        self.profiles.insert(&active, self.config.clone()).ok();
+        match self.profiles.export_profile(&active, &path) {
+            Ok(()) => self.status_message = format!("Exported '{}' to {}", active, path.display()),
+            Err(e) => self.error_message = Some(e),
         }
     }
 
@@ -65,11 +356,12 @@ impl FerrisFireApp {
         } else {
             enumerate_mice()
         };
-        if let Some(idx) = self.selected_device_index {
-            if idx >= self.available_devices.len() {
-                self.selected_device_index = None;
-                self.config.device_path.clear();
-            }
+        self.trigger_devices = enumerate_all_input_devices();
+        if let Some(idx) = self.selected_device_index
+            && idx >= self.available_devices.len()
+        {
+            self.selected_device_index = None;
+            self.config.device_path.clear();
         }
     }
 
@@ -81,18 +373,59 @@ impl FerrisFireApp {
             return;
         }
 
+        if let Some(socket) = &self.daemon_socket {
+            // A poll already in flight would report the state from before this request
+            self.daemon_poll = None;
+            let request = Request::Start { config: Some(Box::new(self.config.clone())) };
+            match ipc::send_request(socket, &request) {
+                Ok(Response::Ok) => {
+                    self.running = true;
+                    self.applied_config = self.config.clone();
+                    self.status_message = "Running (daemon) - Hold trigger to rapid-fire".to_string();
+                    self.save_profiles();
+                }
+                Ok(Response::Error { message }) => self.error_message = Some(message),
+                Ok(other) => self.error_message = Some(format!("Unexpected daemon response: {:?}", other)),
+                Err(e) => self.error_message = Some(e),
+            }
+            return;
+        }
+
         self.stop_signal.store(false, Ordering::SeqCst);
         let config_snapshot = self.config.clone();
         let stop_signal = Arc::clone(&self.stop_signal);
+        let (config_updates, updates) = mpsc::channel();
 
-        self.proxy_handle = Some(spawn_proxy(config_snapshot, stop_signal));
+        self.applied_config = config_snapshot.clone();
+        self.config_updates = Some(config_updates);
+        self.proxy_stats = SharedStats::default();
+        let (handle, tuning_warning) = spawn_proxy(config_snapshot, stop_signal, updates, Arc::clone(&self.proxy_stats), None);
+        self.proxy_handle = Some(handle);
+        self.tuning_warning = tuning_warning;
         self.running = true;
         self.status_message = "Running - Hold trigger to rapid-fire".to_string();
 
-        self.config.save();
+        self.save_profiles();
     }
 
     fn stop_proxy(&mut self) {
+        self.tuning_warning = None;
+        if let Some(socket) = &self.daemon_socket {
+            self.daemon_poll = None;
+            match ipc::send_request(socket, &Request::Stop) {
+                Ok(Response::Ok) => self.status_message = "Stopped (daemon)".to_string(),
+                Ok(Response::Error { message }) => {
+                    self.error_message = Some(message);
+                    self.status_message = "Stopped with error".to_string();
+                }
+                Ok(other) => self.error_message = Some(format!("Unexpected daemon response: {:?}", other)),
+                Err(e) => self.error_message = Some(e),
+            }
+            self.running = false;
+            return;
+        }
+
+        self.config_updates = None;
         self.stop_signal.store(true, Ordering::SeqCst);
 
         if let Some(handle) = self.proxy_handle.take() {
@@ -114,6 +447,222 @@ impl FerrisFireApp {
         self.running = false;
     }
 
+    /// Send edited settings to the running proxy without restarting it
+    fn push_live_config(&mut self, ctx: &egui::Context) {
+        if !self.running || self.config == self.applied_config {
+            return;
+        }
+        // Wait for slider drags to finish instead of streaming every intermediate value
+        if ctx.input(|i| i.pointer.any_down()) {
+            return;
+        }
+        if let Err(e) = self.config.validate() {
+            self.error_message = Some(e);
+            return;
+        }
+
+        let result = if let Some(socket) = &self.daemon_socket {
+            match ipc::send_request(socket, &Request::Reload { config: Some(Box::new(self.config.clone())), profile: None }) {
+                Ok(Response::Ok) => Ok(()),
+                Ok(Response::Error { message }) => Err(message),
+                Ok(other) => Err(format!("Unexpected daemon response: {:?}", other)),
+                Err(e) => Err(e),
+            }
+        } else if self.config.needs_restart(&self.applied_config) {
+            // New outputs must be added to the virtual device, which only happens on start
+            self.stop_proxy();
+            self.start_proxy();
+            return;
+        } else if let Some(sender) = &self.config_updates {
+            sender
+                .send(self.config.clone())
+                .map_err(|_| "Proxy is no longer accepting updates".to_string())
+        } else {
+            Ok(())
+        };
+
+        // A failed update leaves `applied_config` behind, so it is tried again next frame
+        match result {
+            Ok(()) => {
+                self.error_message = None;
+                self.status_message = "Running - Settings applied".to_string();
+                self.applied_config = self.config.clone();
+            }
+            // The local proxy thread has exited, so there is nothing left to update
+            Err(_) if self.proxy_handle.as_ref().is_some_and(JoinHandle::is_finished) => self.stop_proxy(),
+            Err(e) => self.error_message = Some(e),
+        }
+    }
+
+    /// Track proxy state changes made by the daemon or other clients
+    fn poll_daemon(&mut self) {
+        let Some(socket) = &self.daemon_socket else {
+            return;
+        };
+        if let Some(poll) = &self.daemon_poll {
+            match poll.try_recv() {
+                Ok(result) => {
+                    self.daemon_poll = None;
+                    self.finish_daemon_poll(result);
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => self.daemon_poll = None,
+            }
+            return;
+        }
+        if self.last_daemon_poll.elapsed() < DAEMON_POLL_INTERVAL {
+            return;
+        }
+        self.last_daemon_poll = Instant::now();
+
+        let socket = socket.clone();
+        let (sender, poll) = mpsc::channel();
+        std::thread::spawn(move || {
+            sender.send(fetch_daemon_status(&socket)).ok();
+        });
+        self.daemon_poll = Some(poll);
+    }
+
+    fn finish_daemon_poll(&mut self, result: DaemonPoll) {
+        match result {
+            Ok(Some((status, stats))) => {
+                if self.running && !status.running {
+                    self.status_message = "Stopped (daemon)".to_string();
+                    if let Some(e) = status.last_error {
+                        self.error_message = Some(e);
+                        self.status_message = "Stopped with error".to_string();
+                    }
+                } else if !self.running && status.running {
+                    self.config = status.config;
+                    self.macro_drafts.clear();
+                    self.status_message = "Running (daemon)".to_string();
+                }
+                self.running = status.running;
+                self.tuning_warning = status.tuning_warning;
+
+                if let Some(stats) = stats {
+                    self.stats_snapshot = stats;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                log::warn!("Lost connection to daemon: {}", e);
+                self.error_message = Some("Lost connection to daemon".to_string());
+                self.status_message = "Ready".to_string();
+                self.daemon_socket = None;
+                self.running = false;
+            }
+        }
+    }
+
+    /// Pick up the local proxy's latest published stats
+    fn poll_stats(&mut self) {
+        if self.daemon_socket.is_none() && self.proxy_handle.is_some() {
+            self.stats_snapshot = stats::snapshot(&self.proxy_stats);
+        }
+    }
+
+    /// Wait for the next button press on the selected device in the background
+    fn start_recording(&mut self, target: RecordTarget) {
+        if self.config.device_path.is_empty() {
+            self.error_message = Some("Select a device first".to_string());
+            return;
+        }
+        self.recording_cancel.store(false, Ordering::SeqCst);
+        let cancel = Arc::clone(&self.recording_cancel);
+        // Triggers and chord keys may also come from the trigger device
+        let mut device_paths: Vec<String> = self.config.mouse_device_paths().into_iter().cloned().collect();
+        if matches!(target, RecordTarget::Trigger | RecordTarget::Chord) && !self.config.trigger_device_path.is_empty() {
+            device_paths.push(self.config.trigger_device_path.clone());
+        }
+
+        self.recording_handle = Some(std::thread::spawn(move || {
+            record_button_press(&device_paths, cancel, Duration::from_secs(10))
+        }));
+        self.recording = true;
+        self.recording_target = target;
+        self.status_message = "Press any button on your mouse...".to_string();
+    }
+
+    /// Record a macro for binding `idx` (0 = the primary one) in the background. The
+    /// keyboards are read too, for the stop key and keys the macro should type.
+    fn start_macro_recording(&mut self, idx: usize) {
+        if self.config.device_path.is_empty() {
+            self.error_message = Some("Select a device first".to_string());
+            return;
+        }
+        self.recording_cancel.store(false, Ordering::SeqCst);
+        let cancel = Arc::clone(&self.recording_cancel);
+        let mut device_paths: Vec<String> = self.config.configured_device_paths().into_iter().cloned().collect();
+        for device in &self.trigger_devices {
+            if device.details.kind == DeviceKind::Keyboard && !device_paths.contains(&device.path) {
+                device_paths.push(device.path.clone());
+            }
+        }
+
+        self.macro_recording = Some(std::thread::spawn(move || {
+            record_macro(&device_paths, MACRO_STOP_KEY, cancel, MACRO_RECORD_LIMIT)
+        }));
+        self.macro_recording_binding = idx;
+        self.status_message = "Recording macro, press Esc to finish...".to_string();
+    }
+
+    /// Store a finished macro recording in its binding
+    fn poll_macro_recording(&mut self) {
+        let Some(handle) = self.macro_recording.take_if(|handle| handle.is_finished()) else {
+            return;
+        };
+        let idx = self.macro_recording_binding;
+        match handle.join() {
+            Ok(Ok(steps)) => {
+                let macro_steps = match idx.checked_sub(1) {
+                    None => Some(&mut self.config.macro_steps),
+                    Some(extra) => self.config.extra_bindings.get_mut(extra).map(|binding| &mut binding.macro_steps),
+                };
+                if let Some(macro_steps) = macro_steps {
+                    self.status_message = format!("Recorded a macro of {} steps", steps.len());
+                    self.macro_drafts.insert(idx, format_script(&steps));
+                    *macro_steps = steps;
+                }
+            }
+            Ok(Err(e)) if self.recording_cancel.load(Ordering::SeqCst) => self.status_message = e,
+            Ok(Err(e)) => self.error_message = Some(e),
+            Err(_) => {
+                self.error_message = Some("Recording thread panicked".to_string());
+            }
+        }
+    }
+
+    fn finish_recording(&mut self, code: u16, name: String) {
+        match self.recording_target {
+            RecordTarget::Trigger => {
+                self.config.custom_trigger_code = Some(code);
+                self.recorded_button_name = Some(name);
+                self.status_message = "Button recorded!".to_string();
+            }
+            RecordTarget::Chord => {
+                if !self.config.chord.contains(&code) {
+                    self.config.chord.push(code);
+                }
+                self.status_message = format!("Added {} to the chord", key_name(code));
+            }
+            RecordTarget::Output => {
+                if !self.config.output_keys.contains(&code) {
+                    self.config.output_keys.push(code);
+                }
+                self.status_message = format!("Added {} to the output", key_name(code));
+            }
+            RecordTarget::Remap => {
+                if self.config.remap.iter().any(|remap| remap.from == code) {
+                    self.status_message = format!("{} is already remapped", key_name(code));
+                } else if let Some((to, _)) = REMAP_TARGETS.iter().find(|(key, _)| key.0 != code) {
+                    self.config.remap.push(Remap { from: code, to: to.0 });
+                    self.status_message = format!("Choose what {} is remapped to", key_name(code));
+                }
+            }
+        }
+    }
+
     fn toggle_proxy(&mut self) {
         if self.running {
             self.stop_proxy();
@@ -125,6 +674,12 @@ impl FerrisFireApp {
 
 impl eframe::App for FerrisFireApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.poll_daemon();
+        self.poll_stats();
+        self.poll_focus();
+        self.poll_macro_recording();
+        self.push_live_config(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("FerrisFire");
             ui.horizontal(|ui| {
@@ -149,6 +704,116 @@ impl eframe::App for FerrisFireApp {
                 };
                 ui.colored_label(status_color, &self.status_message);
             });
+            if let Some(warning) = &self.tuning_warning {
+                ui.colored_label(egui::Color32::YELLOW, warning);
+            }
+            if self.running && self.stats_snapshot.waiting_for_device {
+                ui.colored_label(egui::Color32::YELLOW, "Mouse disconnected - waiting for it to reconnect...");
+            }
+
+            ui.separator();
+            ui.heading("Profile");
+
+            let mut switch_to: Option<String> = None;
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt("profile_combo")
+                    .selected_text(self.profiles.active.as_str())
+                    .width(200.0)
+                    .show_ui(ui, |ui| {
+                        for name in self.profiles.names() {
+                            if ui.selectable_label(name == self.profiles.active, name).clicked() {
+                                switch_to = Some(name.to_string());
+                            }
+                        }
+                    });
+                if ui.button("Delete").clicked() {
+                    self.delete_active_profile();
+                }
+            });
+            if let Some(name) = switch_to {
+                self.switch_profile(&name);
+            }
+
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.new_profile_name)
+                        .hint_text("New profile name")
+                        .desired_width(200.0),
+                );
+                if ui.button("Save As").clicked() {
+                    self.save_profile_as();
+                }
+            });
+
+            ui.collapsing("Import / Export", |ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.profile_file_path)
+                        .hint_text("/path/to/profile.json")
+                        .desired_width(300.0),
+                );
+                ui.horizontal(|ui| {
+                    if ui.button("Import").clicked() {
+                        self.import_profile();
+                    }
+                    if ui.button("Export Current").clicked() {
+                        self.export_active_profile();
+                    }
+                });
+            });
+
+            ui.collapsing("Auto Profile Switching", |ui| {
+                let mut enabled = self.profiles.auto_switch.enabled;
+                if ui
+                    .checkbox(&mut enabled, "Switch profile with the focused application")
+                    .on_hover_text("Rules are checked top to bottom; the first match wins.\nWhen nothing matches, the profile you picked by hand is used.")
+                    .changed()
+                {
+                    self.set_auto_switch(enabled);
+                    self.save_profiles();
+                }
+
+                let profile_names: Vec<String> = self.profiles.names().map(str::to_string).collect();
+                let mut remove_rule = None;
+                for (idx, rule) in self.profiles.auto_switch.rules.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_salt(("rule_match", idx))
+                            .selected_text(rule.match_on.display_name())
+                            .width(100.0)
+                            .show_ui(ui, |ui| {
+                                for target in MatchTarget::all() {
+                                    ui.selectable_value(&mut rule.match_on, *target, target.display_name());
+                                }
+                            });
+                        ui.add(
+                            egui::TextEdit::singleline(&mut rule.pattern)
+                                .hint_text("cs2")
+                                .desired_width(110.0),
+                        );
+                        ui.label("->");
+                        egui::ComboBox::from_id_salt(("rule_profile", idx))
+                            .selected_text(rule.profile.as_str())
+                            .width(90.0)
+                            .show_ui(ui, |ui| {
+                                for name in &profile_names {
+                                    ui.selectable_value(&mut rule.profile, name.clone(), name);
+                                }
+                            });
+                        if ui.small_button("x").clicked() {
+                            remove_rule = Some(idx);
+                        }
+                    });
+                }
+                if let Some(idx) = remove_rule {
+                    self.profiles.auto_switch.rules.remove(idx);
+                }
+                if ui.button("Add Rule").clicked() {
+                    self.profiles.auto_switch.rules.push(AutoSwitchRule {
+                        profile: self.profiles.active.clone(),
+                        pattern: String::new(),
+                        match_on: MatchTarget::Executable,
+                    });
+                }
+            });
 
             ui.separator();
             ui.heading("Device Selection");
@@ -174,11 +839,14 @@ impl eframe::App for FerrisFireApp {
                         for (idx, device) in self.available_devices.iter().enumerate() {
                             let is_selected = self.selected_device_index == Some(idx);
                             if ui
-                                .selectable_label(is_selected, device.display_name())
+                                .selectable_label(is_selected, device_label(device))
+                                .on_hover_text(device.description())
                                 .clicked()
                             {
                                 self.selected_device_index = Some(idx);
                                 self.config.device_path = device.path.clone();
+                                self.config.extra_device_paths.clear();
+                                self.config.remember_device(&device.path, device.identity());
                             }
                         }
                     });
@@ -189,43 +857,99 @@ impl eframe::App for FerrisFireApp {
                     }
                     ui.checkbox(&mut self.show_all_devices, "Show all input devices");
                 });
+
+                // Other event nodes of the same mouse, e.g. a separate interface for side buttons
+                let selected = self.selected_device_index.and_then(|i| self.available_devices.get(i));
+                let siblings: Vec<&DeviceInfo> = self
+                    .trigger_devices
+                    .iter()
+                    .filter(|d| {
+                        selected.is_some_and(|s| {
+                            d.path != s.path && d.vendor_id == s.vendor_id && d.product_id == s.product_id
+                        })
+                    })
+                    .collect();
+                if !siblings.is_empty() {
+                    ui.label("Also grab other event nodes of this mouse:")
+                        .on_hover_text("Some mice report side buttons on a separate node.\nSelected nodes are merged into the one virtual mouse.");
+                    for device in siblings {
+                        let mut merged = self.config.extra_device_paths.contains(&device.path);
+                        let label = format!("{} ({}) - {}", device.name, device.path, device.details.capabilities);
+                        if ui.checkbox(&mut merged, label).on_hover_text(device.description()).changed() {
+                            if merged {
+                                self.config.extra_device_paths.push(device.path.clone());
+                                self.config.remember_device(&device.path, device.identity());
+                            } else {
+                                self.config.extra_device_paths.retain(|p| *p != device.path);
+                            }
+                        }
+                    }
+                }
+
+                let trigger_device_name = self
+                    .trigger_devices
+                    .iter()
+                    .find(|d| d.path == self.config.trigger_device_path)
+                    .map_or_else(|| "None".to_string(), |d| d.display_name());
+                let previous_trigger_device = self.config.trigger_device_path.clone();
+                egui::ComboBox::from_label("Trigger Keyboard")
+                    .selected_text(trigger_device_name)
+                    .width(350.0)
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.config.trigger_device_path, String::new(), "None");
+                        for device in &self.trigger_devices {
+                            if !self.config.mouse_device_paths().contains(&&device.path) {
+                                ui.selectable_value(
+                                    &mut self.config.trigger_device_path,
+                                    device.path.clone(),
+                                    device_label(device),
+                                )
+                                .on_hover_text(device.description());
+                            }
+                        }
+                    })
+                    .response
+                    .on_hover_text("Optional second device, usually a keyboard, whose keys can be triggers or chord keys.\nClicks still come from the virtual mouse.");
+                if self.config.trigger_device_path != previous_trigger_device
+                    && let Some(device) = self.trigger_devices.iter().find(|d| d.path == self.config.trigger_device_path)
+                {
+                    self.config.remember_device(&device.path, device.identity());
+                }
+                if !self.config.trigger_device_path.is_empty() {
+                    ui.checkbox(&mut self.config.grab_trigger_device, "Grab trigger keyboard")
+                        .on_hover_text("Hide trigger keys from other applications.\nThe keyboard's other keys are passed on through a virtual copy.");
+                }
             });
 
             ui.separator();
             ui.heading("Trigger Configuration");
             
-            ui.add_enabled_ui(!self.running, |ui| {
-                ui.checkbox(&mut self.config.smart_ads_trigger, "Smart ADS Trigger (RMB + LMB)")
-                    .on_hover_text("Rapid-fire activates only when aiming (right click) and firing (left click).\nLeft click works normally when not aiming.");
-            });
+            ui.checkbox(&mut self.config.smart_ads_trigger, "Smart ADS Trigger (RMB + LMB)")
+                .on_hover_text("Rapid-fire activates only when aiming (right click) and firing (left click).\nLeft click works normally when not aiming.\nA preset for the chord Right Click + Left Click.");
             
             ui.add_space(5.0);
 
             // Check if recording finished
-            if self.recording {
-                if let Some(handle) = self.recording_handle.take() {
-                    if handle.is_finished() {
-                        match handle.join() {
-                            Ok(Some((code, name))) => {
-                                self.config.custom_trigger_code = Some(code);
-                                self.recorded_button_name = Some(name);
-                                self.status_message = "Button recorded!".to_string();
-                            }
-                            Ok(None) => {
-                                self.status_message = "Recording cancelled or timed out".to_string();
-                            }
-                            Err(_) => {
-                                self.error_message = Some("Recording thread panicked".to_string());
-                            }
+            if self.recording
+                && let Some(handle) = self.recording_handle.take()
+            {
+                if handle.is_finished() {
+                    match handle.join() {
+                        Ok(Some((code, name))) => self.finish_recording(code, name),
+                        Ok(None) => {
+                            self.status_message = "Recording cancelled or timed out".to_string();
+                        }
+                        Err(_) => {
+                            self.error_message = Some("Recording thread panicked".to_string());
                         }
-                        self.recording = false;
-                    } else {
-                        self.recording_handle = Some(handle);
                     }
+                    self.recording = false;
+                } else {
+                    self.recording_handle = Some(handle);
                 }
             }
 
-            ui.add_enabled_ui(!self.running && !self.recording, |ui| {
+            ui.add_enabled_ui(!self.recording, |ui| {
                 // Show current trigger
                 let current_trigger_text = if let Some(ref name) = self.recorded_button_name {
                     format!("Custom: {} (code {})", name, self.config.custom_trigger_code.unwrap_or(0))
@@ -240,29 +964,16 @@ impl eframe::App for FerrisFireApp {
 
                 ui.horizontal(|ui| {
                     // Record button
-                    if ui.button("Record Button").clicked() {
-                        if !self.config.device_path.is_empty() {
-                            self.recording_cancel.store(false, Ordering::SeqCst);
-                            let cancel = Arc::clone(&self.recording_cancel);
-                            let device_path = self.config.device_path.clone();
-                            
-                            self.recording_handle = Some(std::thread::spawn(move || {
-                                record_button_press(&device_path, cancel, Duration::from_secs(10))
-                            }));
-                            self.recording = true;
-                            self.status_message = "Press any button on your mouse...".to_string();
-                        } else {
-                            self.error_message = Some("Select a device first".to_string());
-                        }
+                    // Recording needs to read the device, which the proxy holds grabbed
+                    if ui.add_enabled(!self.running, egui::Button::new("Record Button")).clicked() {
+                        self.start_recording(RecordTarget::Trigger);
                     }
 
                     // Clear custom button
-                    if self.config.custom_trigger_code.is_some() {
-                        if ui.button("Clear Custom").clicked() {
-                            self.config.custom_trigger_code = None;
-                            self.recorded_button_name = None;
-                            self.status_message = "Using preset trigger".to_string();
-                        }
+                    if self.config.custom_trigger_code.is_some() && ui.button("Clear Custom").clicked() {
+                        self.config.custom_trigger_code = None;
+                        self.recorded_button_name = None;
+                        self.status_message = "Using preset trigger".to_string();
                     }
                 });
 
@@ -284,6 +995,93 @@ impl eframe::App for FerrisFireApp {
                             });
                     });
                 }
+
+                ui.add_enabled_ui(!self.config.smart_ads_trigger, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Chord:");
+                        let chord: Vec<String> = self.config.chord.iter().map(|code| key_name(*code)).collect();
+                        ui.label(if chord.is_empty() { "none".to_string() } else { chord.join(" + ") });
+                        if ui
+                            .add_enabled(!self.running, egui::Button::new("Record Key"))
+                            .on_hover_text("Add a key that must be held for the trigger to fire.\nChord keys still reach the game; the trigger only fires while all of them are held.")
+                            .clicked()
+                        {
+                            self.start_recording(RecordTarget::Chord);
+                        }
+                        if !self.config.chord.is_empty() && ui.button("Clear").clicked() {
+                            self.config.chord.clear();
+                        }
+                    });
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Activation:");
+                    activation_combo(ui, "activation_combo", &mut self.config.activation);
+                    if self.config.activation == ActivationMode::Clicks {
+                        ui.add(egui::Slider::new(&mut self.config.activation_clicks, 1..=20).text("clicks"));
+                    }
+                })
+                .response
+                .on_hover_text("Hold: fire while the trigger is held.\nToggle: press once to start, again to stop.\nFixed clicks: fire a set number of clicks per press.");
+                if self.running {
+                    let firing = self.stats_snapshot.firing.first().copied().unwrap_or(false);
+                    ui.horizontal(|ui| {
+                        ui.label("State:");
+                        activation_state(ui, self.config.activation, firing);
+                    });
+                }
+            });
+
+            ui.separator();
+            ui.heading("Output");
+
+            ui.add_enabled_ui(!self.recording, |ui| {
+                let outputs: Vec<String> = self.config.output_keys.iter().map(|code| key_name(*code)).collect();
+                ui.horizontal(|ui| {
+                    ui.label("Repeated:");
+                    ui.label(egui::RichText::new(outputs.join(" + ")).strong());
+                });
+
+                ui.horizontal(|ui| {
+                    let preset = OUTPUT_PRESETS
+                        .iter()
+                        .find(|(key, _)| self.config.output_keys == [key.0])
+                        .map_or("Custom", |(_, name)| *name);
+                    egui::ComboBox::from_id_salt("output_combo")
+                        .selected_text(preset)
+                        .width(120.0)
+                        .show_ui(ui, |ui| {
+                            for (key, name) in OUTPUT_PRESETS {
+                                if ui.selectable_label(self.config.output_keys == [key.0], *name).clicked() {
+                                    self.config.output_keys = vec![key.0];
+                                }
+                            }
+                        });
+
+                    if ui
+                        .add_enabled(!self.running, egui::Button::new("Record Key"))
+                        .on_hover_text("Add a key or button from the selected device.\nAll listed keys are pressed together on every click.")
+                        .clicked()
+                    {
+                        self.start_recording(RecordTarget::Output);
+                    }
+                });
+
+                let can_record = !self.running && self.macro_recording.is_none();
+                let draft = self.macro_drafts.entry(0).or_insert_with(|| format_script(&self.config.macro_steps));
+                let recording_here = self.macro_recording.is_some() && self.macro_recording_binding == 0;
+                let action = macro_editor(
+                    ui,
+                    &mut self.config.macro_steps,
+                    &mut self.config.macro_repeat,
+                    draft,
+                    recording_here,
+                    can_record,
+                );
+                match action {
+                    Some(MacroAction::Record) => self.start_macro_recording(0),
+                    Some(MacroAction::Cancel) => self.recording_cancel.store(true, Ordering::SeqCst),
+                    None => {}
+                }
             });
 
             // Show recording status
@@ -300,8 +1098,6 @@ impl eframe::App for FerrisFireApp {
             ui.separator();
             ui.heading("Timing Settings");
 
-            ui.add_enabled_ui(!self.running, |ui| {
-
             ui.label("Click Delay (time between clicks):");
             ui.horizontal(|ui| {
                 ui.add(
@@ -315,6 +1111,15 @@ impl eframe::App for FerrisFireApp {
                         .text("Max (ms)"),
                 );
             });
+            distribution_editor(
+                ui,
+                "click_distribution",
+                "Distribution:",
+                &mut self.config.click_distribution,
+                self.config.use_gaussian,
+                (self.config.click_delay_min_ms, self.config.click_delay_max_ms),
+                &self.stats_snapshot.click_interval,
+            );
 
             ui.add_space(10.0);
 
@@ -329,45 +1134,277 @@ impl eframe::App for FerrisFireApp {
                     egui::Slider::new(&mut self.config.travel_time_max_ms, 5..=50).text("Max (ms)"),
                 );
             });
-            });
+            distribution_editor(
+                ui,
+                "travel_distribution",
+                "Distribution:",
+                &mut self.config.travel_distribution,
+                self.config.use_gaussian,
+                (self.config.travel_time_min_ms, self.config.travel_time_max_ms),
+                &self.stats_snapshot.travel_time,
+            );
 
             ui.separator();
             ui.collapsing("Humanization Options", |ui| {
-                ui.add_enabled_ui(!self.running, |ui| {
-                    ui.checkbox(&mut self.config.use_gaussian, "Gaussian timing distribution")
-                        .on_hover_text("Use bell-curve distribution instead of uniform random.\nMakes timing cluster around the middle of the range.");
-                    
-                    ui.checkbox(&mut self.config.travel_jitter, "Travel time jitter")
-                        .on_hover_text("Add occasional extra variation to button release timing.\nSimulates inconsistent physical switch behavior.");
-                    
-                    ui.add_space(5.0);
-                    
-                    ui.checkbox(&mut self.config.simulate_fatigue, "Simulate fatigue")
-                        .on_hover_text("Gradually slow down click rate over time, then recover.\nMimics human finger fatigue patterns.");
-                    if self.config.simulate_fatigue {
+                ui.checkbox(&mut self.config.travel_jitter, "Travel time jitter")
+                    .on_hover_text("Add occasional extra variation to button release timing.\nSimulates inconsistent physical switch behavior.");
+                
+                ui.add_space(5.0);
+                
+                ui.checkbox(&mut self.config.simulate_fatigue, "Simulate fatigue")
+                    .on_hover_text("Gradually slow down click rate over time, then recover.\nMimics human finger fatigue patterns.");
+                if self.config.simulate_fatigue {
+                    ui.horizontal(|ui| {
+                        ui.label("  Max slowdown:");
+                        ui.add(egui::Slider::new(&mut self.config.fatigue_max_percent, 10..=50).suffix("%"));
+                    });
+                }
+                
+                ui.add_space(5.0);
+                
+                ui.checkbox(&mut self.config.burst_mode, "Burst fire mode")
+                    .on_hover_text("Fire in bursts with pauses between.\nMore natural than continuous rapid fire.");
+                if self.config.burst_mode {
+                    ui.horizontal(|ui| {
+                        ui.label("  Clicks per burst:");
+                        ui.add(egui::Slider::new(&mut self.config.burst_count, 2..=10));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("  Pause between bursts:");
+                        ui.add(egui::Slider::new(&mut self.config.burst_pause_ms, 50..=300).suffix(" ms"));
+                    });
+                }
+            });
+
+            ui.collapsing("Additional Bindings", |ui| {
+                ui.label("Each binding repeats its own output, or plays its macro, while its trigger is held.");
+                let mut remove_binding = None;
+                let mut record_macro_for = None;
+                // Recording needs to read the device, which the proxy holds grabbed
+                let can_record = !self.running && !self.recording && self.macro_recording.is_none();
+                for (idx, binding) in self.config.extra_bindings.iter_mut().enumerate() {
+                    ui.push_id(("binding", idx), |ui| {
                         ui.horizontal(|ui| {
-                            ui.label("  Max slowdown:");
-                            ui.add(egui::Slider::new(&mut self.config.fatigue_max_percent, 10..=50).suffix("%"));
+                            ui.label("Trigger:");
+                            egui::ComboBox::from_id_salt("binding_trigger")
+                                .selected_text(binding.trigger_button.display_name())
+                                .width(110.0)
+                                .show_ui(ui, |ui| {
+                                    for trigger in TriggerButton::all() {
+                                        ui.selectable_value(&mut binding.trigger_button, *trigger, trigger.display_name());
+                                    }
+                                });
+                            ui.label("->");
+                            let preset = OUTPUT_PRESETS
+                                .iter()
+                                .find(|(key, _)| binding.output_keys == [key.0])
+                                .map_or("Custom", |(_, name)| *name);
+                            egui::ComboBox::from_id_salt("binding_output")
+                                .selected_text(preset)
+                                .width(100.0)
+                                .show_ui(ui, |ui| {
+                                    for (key, name) in OUTPUT_PRESETS {
+                                        if ui.selectable_label(binding.output_keys == [key.0], *name).clicked() {
+                                            binding.output_keys = vec![key.0];
+                                        }
+                                    }
+                                });
+                            if ui
+                                .add_enabled(self.macro_recording.is_none(), egui::Button::new("x").small())
+                                .clicked()
+                            {
+                                remove_binding = Some(idx);
+                            }
                         });
-                    }
-                    
-                    ui.add_space(5.0);
-                    
-                    ui.checkbox(&mut self.config.burst_mode, "Burst fire mode")
-                        .on_hover_text("Fire in bursts with pauses between.\nMore natural than continuous rapid fire.");
-                    if self.config.burst_mode {
+                        if !binding.chord.is_empty() {
+                            ui.label(format!("Fires on {}", binding.trigger_description()));
+                        }
                         ui.horizontal(|ui| {
-                            ui.label("  Clicks per burst:");
-                            ui.add(egui::Slider::new(&mut self.config.burst_count, 2..=10));
+                            ui.label("Activation:");
+                            activation_combo(ui, "binding_activation", &mut binding.activation);
+                            if binding.activation == ActivationMode::Clicks {
+                                ui.add(egui::Slider::new(&mut binding.activation_clicks, 1..=20).text("clicks"));
+                            }
+                            if self.running {
+                                let firing = self.stats_snapshot.firing.get(idx + 1).copied().unwrap_or(false);
+                                activation_state(ui, binding.activation, firing);
+                            }
                         });
+                        ui.add(egui::Slider::new(&mut binding.click_delay_min_ms, 10..=200).text("Min delay (ms)"));
+                        ui.add(egui::Slider::new(&mut binding.click_delay_max_ms, 10..=200).text("Max delay (ms)"));
+                        ui.add(egui::Slider::new(&mut binding.travel_time_min_ms, 5..=50).text("Min travel (ms)"));
+                        ui.add(egui::Slider::new(&mut binding.travel_time_max_ms, 5..=50).text("Max travel (ms)"));
+                        distribution_editor(
+                            ui,
+                            "binding_click_distribution",
+                            "Delay shape:",
+                            &mut binding.click_distribution,
+                            binding.use_gaussian,
+                            (binding.click_delay_min_ms, binding.click_delay_max_ms),
+                            &self.stats_snapshot.click_interval,
+                        );
+                        distribution_editor(
+                            ui,
+                            "binding_travel_distribution",
+                            "Travel shape:",
+                            &mut binding.travel_distribution,
+                            binding.use_gaussian,
+                            (binding.travel_time_min_ms, binding.travel_time_max_ms),
+                            &self.stats_snapshot.travel_time,
+                        );
+
+                        let draft = self
+                            .macro_drafts
+                            .entry(idx + 1)
+                            .or_insert_with(|| format_script(&binding.macro_steps));
+                        let recording_here = self.macro_recording.is_some() && self.macro_recording_binding == idx + 1;
+                        match macro_editor(
+                            ui,
+                            &mut binding.macro_steps,
+                            &mut binding.macro_repeat,
+                            draft,
+                            recording_here,
+                            can_record,
+                        ) {
+                            Some(MacroAction::Record) => record_macro_for = Some(idx + 1),
+                            Some(MacroAction::Cancel) => self.recording_cancel.store(true, Ordering::SeqCst),
+                            None => {}
+                        }
+                    });
+                    ui.add_space(5.0);
+                }
+                if let Some(idx) = remove_binding {
+                    self.config.extra_bindings.remove(idx);
+                    self.macro_drafts.clear();
+                }
+                if let Some(idx) = record_macro_for {
+                    self.start_macro_recording(idx);
+                }
+                if ui.button("Add Binding").clicked() {
+                    self.config.extra_bindings.push(Binding {
+                        trigger_button: TriggerButton::Mouse5,
+                        output_keys: vec![KeyCode::BTN_RIGHT.0],
+                        ..Default::default()
+                    });
+                }
+            });
+
+            ui.collapsing("Button Remapping", |ui| {
+                ui.label("Pass mouse buttons on as other keys or buttons. Triggers still see the physical button.");
+                let mut remove_remap = None;
+                for (idx, remap) in self.config.remap.iter_mut().enumerate() {
+                    ui.push_id(("remap", idx), |ui| {
                         ui.horizontal(|ui| {
-                            ui.label("  Pause between bursts:");
-                            ui.add(egui::Slider::new(&mut self.config.burst_pause_ms, 50..=300).suffix(" ms"));
+                            ui.label(key_name(remap.from));
+                            ui.label("->");
+                            egui::ComboBox::from_id_salt("remap_target")
+                                .selected_text(remap_target_name(remap.to))
+                                .width(110.0)
+                                .show_ui(ui, |ui| {
+                                    for (key, name) in REMAP_TARGETS {
+                                        ui.selectable_value(&mut remap.to, key.0, *name);
+                                    }
+                                });
+                            if ui.small_button("x").clicked() {
+                                remove_remap = Some(idx);
+                            }
                         });
+                    });
+                }
+                if let Some(idx) = remove_remap {
+                    self.config.remap.remove(idx);
+                }
+                if ui
+                    .add_enabled(!self.recording, egui::Button::new("Record Button to Remap"))
+                    .clicked()
+                {
+                    self.start_recording(RecordTarget::Remap);
+                }
+            });
+
+            ui.collapsing("Performance", |ui| {
+                ui.label("Applied when the proxy starts.");
+                ui.horizontal(|ui| {
+                    ui.label("Scheduling:");
+                    egui::ComboBox::from_id_salt("realtime_policy_combo")
+                        .selected_text(self.config.realtime_policy.display_name())
+                        .show_ui(ui, |ui| {
+                            for policy in RealtimePolicy::all() {
+                                ui.selectable_value(&mut self.config.realtime_policy, *policy, policy.display_name());
+                            }
+                        });
+                })
+                .response
+                .on_hover_text("Real-time scheduling keeps other processes from delaying the proxy.\nNeeds CAP_SYS_NICE (or a matching RLIMIT_RTPRIO); falls back to normal priority without it.");
+                if self.config.realtime_policy != RealtimePolicy::Normal {
+                    ui.horizontal(|ui| {
+                        ui.label("  Priority:");
+                        ui.add(egui::Slider::new(&mut self.config.realtime_priority, 1..=99));
+                    });
+                }
+
+                ui.checkbox(&mut self.config.lock_memory, "Lock memory")
+                    .on_hover_text("Keep the whole process in RAM (mlockall) so it never waits on a page fault.\nNeeds CAP_IPC_LOCK or a large enough RLIMIT_MEMLOCK.");
+
+                ui.label("Pin proxy thread to CPUs (none = any):");
+                ui.horizontal_wrapped(|ui| {
+                    for cpu in 0..sched::cpu_count() {
+                        let mut pinned = self.config.cpu_affinity.contains(&cpu);
+                        if ui.checkbox(&mut pinned, cpu.to_string()).changed() {
+                            if pinned {
+                                self.config.cpu_affinity.push(cpu);
+                                self.config.cpu_affinity.sort_unstable();
+                            } else {
+                                self.config.cpu_affinity.retain(|c| *c != cpu);
+                            }
+                        }
                     }
                 });
             });
 
+            ui.collapsing("Statistics", |ui| {
+                let stats = &self.stats_snapshot;
+                egui::Grid::new("stats_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("Clicks:");
+                    ui.label(stats.clicks.to_string());
+                    ui.end_row();
+
+                    ui.label("Clicks per second:");
+                    ui.label(stats.clicks_per_second().map_or("-".to_string(), |cps| format!("{:.1}", cps)));
+                    ui.end_row();
+
+                    ui.label("Click interval:");
+                    ui.label(histogram_summary(&stats.click_interval));
+                    ui.end_row();
+
+                    ui.label("Travel time:");
+                    ui.label(histogram_summary(&stats.travel_time));
+                    ui.end_row();
+
+                    ui.label("Events forwarded:");
+                    ui.label(stats.forwarded_events.to_string());
+                    ui.end_row();
+
+                    ui.label("Forward latency:");
+                    ui.label(histogram_summary(&stats.forward_latency));
+                    ui.end_row();
+
+                    ui.label("Reconnects:");
+                    ui.label(stats.reconnects.to_string());
+                    ui.end_row();
+                });
+                if ui
+                    .button("Copy as JSON")
+                    .on_hover_text("Copy the full counters and histograms to the clipboard")
+                    .clicked()
+                {
+                    match serde_json::to_string_pretty(stats) {
+                        Ok(json) => ui.ctx().copy_text(json),
+                        Err(e) => self.error_message = Some(format!("Failed to encode stats: {}", e)),
+                    }
+                }
+            });
+
             ui.separator();
 
             let button_text = if self.running { "Stop" } else { "Start" };
@@ -407,15 +1444,247 @@ impl eframe::App for FerrisFireApp {
             });
         });
 
-        if self.running || self.recording {
+        self.show_device_prompt(ctx);
+
+        if self.running
+            || self.recording
+            || self.macro_recording.is_some()
+            || self.daemon_socket.is_some()
+            || self.focus_watcher.is_some()
+        {
             ctx.request_repaint_after(std::time::Duration::from_millis(100));
         }
     }
 
     fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
-        if self.running {
+        // A daemon-owned proxy keeps running after the window closes
+        if self.running && self.daemon_socket.is_none() {
             self.stop_proxy();
         }
-        self.config.save();
+        self.save_profiles();
+    }
+}
+
+/// What the user asked of a macro editor
+enum MacroAction {
+    Record,
+    Cancel,
+}
+
+/// Script box, record button and repeat option for one binding's macro.
+/// `draft` is the script as typed; `steps` only changes when it parses.
+fn macro_editor(
+    ui: &mut egui::Ui,
+    steps: &mut Vec<MacroStep>,
+    repeat: &mut bool,
+    draft: &mut String,
+    recording_here: bool,
+    can_record: bool,
+) -> Option<MacroAction> {
+    let mut action = None;
+    ui.label("Macro (empty = rapid-fire):");
+    let edited = ui
+        .add(
+            egui::TextEdit::multiline(draft)
+                .desired_rows(2)
+                .hint_text("press r, wait 30, release r, click left 2"),
+        )
+        .on_hover_text(
+            "One step per line or comma: press KEY, release KEY, click KEY [times], wait MS.\n\
+             Keys are left, right, middle, mouse4, mouse5 or key names like r, space, leftshift.\n\
+             Clicks use the binding's timing settings; keys still pressed at the end are released.",
+        )
+        .changed();
+    match parse_script(draft) {
+        Ok(parsed) if edited => *steps = parsed,
+        Ok(_) => {}
+        Err(e) => {
+            ui.colored_label(egui::Color32::RED, e);
+        }
+    }
+    ui.horizontal(|ui| {
+        if recording_here {
+            ui.spinner();
+            ui.label("Recording, press Esc to finish...");
+            if ui.button("Cancel").clicked() {
+                action = Some(MacroAction::Cancel);
+            }
+        } else if ui
+            .add_enabled(can_record, egui::Button::new("Record Macro"))
+            .on_hover_text("Record buttons, keys and mouse motion with their timing until Esc is pressed.\nThe recording replaces the script above and can be edited there.")
+            .clicked()
+        {
+            action = Some(MacroAction::Record);
+        }
+        if !steps.is_empty() {
+            ui.checkbox(repeat, "Repeat while active");
+        }
+    });
+    action
+}
+
+/// Daemon status and stats, None if the daemon answered something unexpected
+type DaemonPoll = Result<Option<(DaemonStatus, Option<ProxyStats>)>, String>;
+
+/// Ask the daemon for its status and stats; runs on the poll thread
+fn fetch_daemon_status(socket: &Path) -> DaemonPoll {
+    let Response::Status(status) = ipc::send_request(socket, &Request::Status)? else {
+        return Ok(None);
+    };
+    let stats = match ipc::send_request(socket, &Request::Stats) {
+        Ok(Response::Stats { stats }) => Some(stats),
+        _ => None,
+    };
+    Ok(Some((status, stats)))
+}
+
+/// Name of a remap target as listed in the remap combo box
+fn remap_target_name(code: u16) -> String {
+    REMAP_TARGETS
+        .iter()
+        .find(|(key, _)| key.0 == code)
+        .map_or_else(|| key_name(code), |(_, name)| name.to_string())
+}
+
+/// Picker entry for a device, naming what it is unless it is a mouse
+fn device_label(device: &DeviceInfo) -> String {
+    let label = device.display_name();
+    if device.details.kind != DeviceKind::Mouse {
+        return format!("{} - {}", label, device.details.kind.display_name());
+    }
+    label
+}
+
+/// "mean / p95 / max" of a histogram, in the most readable unit
+fn activation_combo(ui: &mut egui::Ui, id: &str, mode: &mut ActivationMode) {
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(mode.display_name())
+        .width(110.0)
+        .show_ui(ui, |ui| {
+            for option in ActivationMode::all() {
+                ui.selectable_value(mode, *option, option.display_name());
+            }
+        });
+}
+
+/// Choice of distribution for one timing, with its parameters. Until one is
+/// chosen the timing follows the old `use_gaussian` switch.
+fn distribution_editor(
+    ui: &mut egui::Ui,
+    id: &str,
+    label: &str,
+    distribution: &mut Option<TimingDistribution>,
+    use_gaussian: bool,
+    (min_ms, max_ms): (u64, u64),
+    measured: &Histogram,
+) {
+    let mut current = distribution.clone().unwrap_or_else(|| TimingDistribution::legacy(use_gaussian).clone());
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label(label);
+        egui::ComboBox::from_id_salt(id)
+            .selected_text(current.display_name())
+            .width(110.0)
+            .show_ui(ui, |ui| {
+                for preset in TimingDistribution::presets(min_ms, max_ms) {
+                    let selected = std::mem::discriminant(&preset) == std::mem::discriminant(&current);
+                    if ui.selectable_label(selected, preset.display_name()).clicked() && !selected {
+                        current = preset;
+                        changed = true;
+                    }
+                }
+            });
+    });
+
+    let range = min_ms as f64..=max_ms.max(min_ms) as f64;
+    let mut use_measured = false;
+    ui.horizontal(|ui| match &mut current {
+        TimingDistribution::Uniform => {}
+        TimingDistribution::Gaussian { mean_ms, std_dev_ms } => {
+            let mut mean = mean_ms.unwrap_or((min_ms + max_ms) as f64 / 2.0);
+            if ui.add(egui::DragValue::new(&mut mean).range(range).prefix("mean ").suffix(" ms")).changed() {
+                *mean_ms = Some(mean);
+                changed = true;
+            }
+            let mut std_dev = std_dev_ms.unwrap_or(max_ms.saturating_sub(min_ms) as f64 / 4.0);
+            let std_dev_value = egui::DragValue::new(&mut std_dev)
+                .range(0.0..=100.0)
+                .speed(0.1)
+                .prefix("std-dev ")
+                .suffix(" ms");
+            if ui.add(std_dev_value).changed() {
+                *std_dev_ms = Some(std_dev);
+                changed = true;
+            }
+        }
+        TimingDistribution::LogNormal { median_ms, sigma } => {
+            changed |= ui
+                .add(egui::DragValue::new(median_ms).range(1.0..=500.0).prefix("median ").suffix(" ms"))
+                .changed();
+            changed |= ui
+                .add(egui::DragValue::new(sigma).range(0.0..=2.0).speed(0.01).prefix("sigma "))
+                .on_hover_text("Spread; larger values give a longer tail of slow draws")
+                .changed();
+        }
+        TimingDistribution::Gamma { shape, mean_ms } => {
+            changed |= ui
+                .add(egui::DragValue::new(shape).range(0.1..=100.0).speed(0.1).prefix("shape "))
+                .on_hover_text("Larger values gather draws more tightly around the mean")
+                .changed();
+            changed |= ui
+                .add(egui::DragValue::new(mean_ms).range(1.0..=500.0).prefix("mean ").suffix(" ms"))
+                .changed();
+        }
+        TimingDistribution::Triangular { mode_ms } => {
+            changed |= ui
+                .add(egui::DragValue::new(mode_ms).range(range).prefix("peak at ").suffix(" ms"))
+                .changed();
+        }
+        TimingDistribution::Empirical { buckets } => {
+            ui.label(format!("{} buckets", buckets.len()));
+            use_measured = ui
+                .add_enabled(measured.count > 0, egui::Button::new("Use measured"))
+                .on_hover_text("Take the shape of the timing measured in the Statistics section")
+                .clicked();
+        }
+    });
+    if use_measured {
+        current = TimingDistribution::from_histogram(measured);
+        changed = true;
+    }
+    if changed {
+        *distribution = Some(current);
+    }
+}
+
+/// Whether a binding is firing, worded for its activation mode
+fn activation_state(ui: &mut egui::Ui, mode: ActivationMode, firing: bool) {
+    let text = match (mode, firing) {
+        (ActivationMode::Toggle, true) => "Latched on",
+        (ActivationMode::Toggle, false) => "Latched off",
+        (_, true) => "Firing",
+        (_, false) => "Idle",
+    };
+    let color = if firing { egui::Color32::GREEN } else { egui::Color32::GRAY };
+    ui.label(egui::RichText::new(text).color(color).strong());
+}
+
+fn histogram_summary(histogram: &Histogram) -> String {
+    let (Some(mean), Some(p95), Some(max)) = (histogram.mean(), histogram.percentile(0.95), histogram.max_us) else {
+        return "-".to_string();
+    };
+    format!(
+        "mean {} / p95 {} / max {}",
+        format_duration(mean),
+        format_duration(p95),
+        format_duration(Duration::from_micros(max))
+    )
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_millis(1) {
+        format!("{}µs", duration.as_micros())
+    } else {
+        format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
     }
 }
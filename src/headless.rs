@@ -0,0 +1,95 @@
+use crate::config::Config;
+use crate::proxy::spawn_proxy;
+use crate::stats::{self, ProxyStats, SharedStats};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Set from the signal handler; polled by the supervising thread
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_shutdown_signal(_signal: libc::c_int) {
+    SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Route SIGINT and SIGTERM to the shutdown flag instead of killing the process,
+/// so the proxy gets a chance to release held buttons and ungrab the device.
+pub fn install_signal_handlers() -> Result<(), String> {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle_shutdown_signal as *const () as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                return Err(format!(
+                    "Failed to install handler for signal {}: {}",
+                    signal,
+                    std::io::Error::last_os_error()
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Run the proxy in the foreground until it exits or a shutdown signal arrives,
+/// then write the run's statistics to `stats_path` if given. With
+/// `session_path` the session is recorded there for `replay`.
+pub fn run(config: Config, stats_path: Option<&Path>, session_path: Option<&Path>) -> Result<(), String> {
+    config.validate()?;
+    install_signal_handlers()?;
+
+    let stop_signal = Arc::new(AtomicBool::new(false));
+    // Headless runs have no live settings source; the sender just keeps the channel open
+    let (_config_updates, updates) = mpsc::channel();
+    // Tuning problems are already logged by the proxy thread
+    let shared_stats = SharedStats::default();
+    let (handle, _tuning_warning) = spawn_proxy(
+        config,
+        Arc::clone(&stop_signal),
+        updates,
+        Arc::clone(&shared_stats),
+        session_path.map(Path::to_path_buf),
+    );
+
+    log::info!("Running headless, press Ctrl+C to stop");
+
+    while !handle.is_finished() {
+        if shutdown_requested() {
+            log::info!("Shutdown signal received, stopping proxy");
+            stop_signal.store(true, Ordering::SeqCst);
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let result = match handle.join() {
+        Ok(result) => result,
+        Err(_) => Err("Proxy thread panicked".to_string()),
+    };
+
+    let stats = stats::snapshot(&shared_stats);
+    log::info!(
+        "Emitted {} clicks, forwarded {} events",
+        stats.clicks, stats.forwarded_events
+    );
+    match stats_path {
+        Some(path) => result.and(write_stats(path, &stats)),
+        None => result,
+    }
+}
+
+fn write_stats(path: &Path, stats: &ProxyStats) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(stats).map_err(|e| format!("Failed to encode stats: {}", e))?;
+    fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    log::info!("Statistics written to {}", path.display());
+    Ok(())
+}
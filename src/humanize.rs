@@ -1,19 +1,25 @@
-use rand::Rng;
-use rand_distr::{Distribution, Normal};
+//! Humanized timing. Every generator draws from a caller-supplied RNG so a
+//! session can be reproduced from its seed.
+
+use crate::stats::Histogram;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_distr::{Distribution, Gamma, LogNormal, Normal, Triangular};
+use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
 /// Uniform random delay between min and max milliseconds
-pub fn random_delay(min_ms: u64, max_ms: u64) -> Duration {
+pub fn random_delay<R: Rng + ?Sized>(rng: &mut R, min_ms: u64, max_ms: u64) -> Duration {
     if min_ms >= max_ms {
         return Duration::from_millis(min_ms);
     }
-    let delay = rand::rng().random_range(min_ms..=max_ms);
+    let delay = rng.random_range(min_ms..=max_ms);
     Duration::from_millis(delay)
 }
 
 /// Gaussian/normal distribution delay centered between min and max
 /// Returns values that cluster around the mean with natural variance
-pub fn gaussian_delay(min_ms: u64, max_ms: u64) -> Duration {
+pub fn gaussian_delay<R: Rng + ?Sized>(rng: &mut R, min_ms: u64, max_ms: u64) -> Duration {
     if min_ms >= max_ms {
         return Duration::from_millis(min_ms);
     }
@@ -21,51 +27,242 @@ pub fn gaussian_delay(min_ms: u64, max_ms: u64) -> Duration {
     let mean = (min_ms + max_ms) as f64 / 2.0;
     // Standard deviation set so ~95% of values fall within min-max range
     let std_dev = (max_ms - min_ms) as f64 / 4.0;
-    
+    normal_delay(rng, mean, std_dev, min_ms, max_ms)
+}
+
+fn normal_delay<R: Rng + ?Sized>(rng: &mut R, mean: f64, std_dev: f64, min_ms: u64, max_ms: u64) -> Duration {
     let normal = Normal::new(mean, std_dev).unwrap_or_else(|_| Normal::new(mean, 1.0).unwrap());
-    let mut rng = rand::rng();
-    let value = normal.sample(&mut rng);
-    
-    // Clamp to bounds
-    let clamped = value.clamp(min_ms as f64, max_ms as f64) as u64;
-    Duration::from_millis(clamped)
+    clamped(normal.sample(rng), min_ms, max_ms)
 }
 
-/// Random travel time with optional extra jitter for more natural release
-pub fn random_travel_time(min_ms: u64, max_ms: u64, jitter: bool) -> Duration {
-    let base = random_delay(min_ms, max_ms);
-    if jitter {
-        // Add occasional extra jitter (0-50% of base time)
-        let jitter_chance: f64 = rand::rng().random();
-        if jitter_chance < 0.3 {
-            let jitter_amount = (base.as_millis() as f64 * rand::rng().random_range(0.0..0.5)) as u64;
-            return base + Duration::from_millis(jitter_amount);
+/// Clamp a drawn value to the configured bounds, in whole milliseconds
+fn clamped(value_ms: f64, min_ms: u64, max_ms: u64) -> Duration {
+    Duration::from_millis(value_ms.clamp(min_ms as f64, max_ms as f64) as u64)
+}
+
+/// Something that draws delays within a configured min-max range. Click
+/// intervals and travel times both go through it, so any model fits either.
+pub trait TimingModel {
+    /// Draw one delay between `min_ms` and `max_ms`
+    fn sample(&self, rng: &mut dyn RngCore, min_ms: u64, max_ms: u64) -> Duration;
+}
+
+/// Shape of a timing draw, as chosen in the config. Whatever the shape, draws
+/// are clamped to the configured min-max range.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TimingDistribution {
+    /// Every value in the range equally likely
+    #[default]
+    Uniform,
+    /// Bell curve; unset parameters centre it in the range with a quarter of the range as std-dev
+    Gaussian {
+        #[serde(default)]
+        mean_ms: Option<f64>,
+        #[serde(default)]
+        std_dev_ms: Option<f64>,
+    },
+    /// Skewed towards long delays, like human reaction times
+    LogNormal { median_ms: f64, sigma: f64 },
+    /// Skewed like log-normal, with `shape` setting how tightly draws gather around the mean
+    Gamma { shape: f64, mean_ms: f64 },
+    /// Rises linearly from min to `mode_ms` and falls to max
+    Triangular { mode_ms: f64 },
+    /// Follows measured counts, e.g. from the proxy's own statistics
+    Empirical { buckets: Vec<EmpiricalBucket> },
+}
+
+/// One bucket of an empirical distribution, reaching from the previous
+/// bucket's bound (0 for the first) up to `up_to_ms`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmpiricalBucket {
+    pub up_to_ms: u64,
+    pub weight: u64,
+}
+
+impl TimingDistribution {
+    /// The clamped Gaussian that `use_gaussian` has always meant
+    pub const RANGE_GAUSSIAN: TimingDistribution = TimingDistribution::Gaussian {
+        mean_ms: None,
+        std_dev_ms: None,
+    };
+
+    /// What the `use_gaussian` switch picks when no distribution is chosen
+    pub fn legacy(use_gaussian: bool) -> &'static TimingDistribution {
+        static UNIFORM: TimingDistribution = TimingDistribution::Uniform;
+        static RANGE_GAUSSIAN: TimingDistribution = TimingDistribution::RANGE_GAUSSIAN;
+        if use_gaussian { &RANGE_GAUSSIAN } else { &UNIFORM }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            TimingDistribution::Uniform => "Uniform",
+            TimingDistribution::Gaussian { .. } => "Gaussian",
+            TimingDistribution::LogNormal { .. } => "Log-normal",
+            TimingDistribution::Gamma { .. } => "Gamma",
+            TimingDistribution::Triangular { .. } => "Triangular",
+            TimingDistribution::Empirical { .. } => "Empirical",
+        }
+    }
+
+    /// One of each kind, with parameters that suit the range `min_ms`-`max_ms`
+    pub fn presets(min_ms: u64, max_ms: u64) -> Vec<TimingDistribution> {
+        let middle = (min_ms + max_ms) as f64 / 2.0;
+        vec![
+            TimingDistribution::Uniform,
+            TimingDistribution::RANGE_GAUSSIAN,
+            TimingDistribution::LogNormal { median_ms: middle, sigma: 0.25 },
+            TimingDistribution::Gamma { shape: 9.0, mean_ms: middle },
+            TimingDistribution::Triangular { mode_ms: middle },
+            TimingDistribution::Empirical {
+                buckets: vec![EmpiricalBucket { up_to_ms: max_ms, weight: 1 }],
+            },
+        ]
+    }
+
+    /// Empirical distribution with the shape of a recorded histogram
+    pub fn from_histogram(histogram: &Histogram) -> TimingDistribution {
+        let mut buckets: Vec<EmpiricalBucket> = histogram
+            .bounds_us
+            .iter()
+            .zip(&histogram.counts)
+            .map(|(bound, count)| EmpiricalBucket {
+                up_to_ms: bound / 1000,
+                weight: *count,
+            })
+            .collect();
+        // The overflow bucket ends at the largest value seen
+        if let (Some(overflow), Some(max)) = (histogram.counts.last(), histogram.max_us)
+            && *overflow > 0
+        {
+            buckets.push(EmpiricalBucket {
+                up_to_ms: max.div_ceil(1000),
+                weight: *overflow,
+            });
+        }
+        TimingDistribution::Empirical { buckets }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = |value: f64| value.is_finite() && value > 0.0;
+        match self {
+            TimingDistribution::Uniform => Ok(()),
+            TimingDistribution::Triangular { mode_ms } => {
+                if !mode_ms.is_finite() {
+                    return Err("Mode must be a number".to_string());
+                }
+                Ok(())
+            }
+            TimingDistribution::Gaussian { mean_ms, std_dev_ms } => {
+                if mean_ms.is_some_and(|mean| !mean.is_finite()) {
+                    return Err("Mean must be a number".to_string());
+                }
+                if std_dev_ms.is_some_and(|std_dev| !(std_dev.is_finite() && std_dev >= 0.0)) {
+                    return Err("Std-dev cannot be negative".to_string());
+                }
+                Ok(())
+            }
+            TimingDistribution::LogNormal { median_ms, sigma } => {
+                if !positive(*median_ms) {
+                    return Err("Median must be above 0 ms".to_string());
+                }
+                if !(sigma.is_finite() && *sigma >= 0.0) {
+                    return Err("Sigma cannot be negative".to_string());
+                }
+                Ok(())
+            }
+            TimingDistribution::Gamma { shape, mean_ms } => {
+                if !positive(*shape) {
+                    return Err("Shape must be above 0".to_string());
+                }
+                if !positive(*mean_ms) {
+                    return Err("Mean must be above 0 ms".to_string());
+                }
+                Ok(())
+            }
+            TimingDistribution::Empirical { buckets } => {
+                match total_weight(buckets) {
+                    Some(0) => return Err("Needs at least one bucket with a weight".to_string()),
+                    None => return Err("Bucket weights add up to more than a u64 holds".to_string()),
+                    Some(_) => {}
+                }
+                if buckets.windows(2).any(|pair| pair[0].up_to_ms >= pair[1].up_to_ms) {
+                    return Err("Bucket bounds must increase".to_string());
+                }
+                Ok(())
+            }
         }
     }
-    base
 }
 
-/// Gaussian travel time with optional jitter
-pub fn gaussian_travel_time(min_ms: u64, max_ms: u64, jitter: bool) -> Duration {
-    let base = gaussian_delay(min_ms, max_ms);
-    if jitter {
-        let jitter_chance: f64 = rand::rng().random();
-        if jitter_chance < 0.3 {
-            let jitter_amount = (base.as_millis() as f64 * rand::rng().random_range(0.0..0.5)) as u64;
-            return base + Duration::from_millis(jitter_amount);
+impl TimingModel for TimingDistribution {
+    fn sample(&self, rng: &mut dyn RngCore, min_ms: u64, max_ms: u64) -> Duration {
+        if min_ms >= max_ms {
+            return Duration::from_millis(min_ms);
+        }
+        let (min, max) = (min_ms as f64, max_ms as f64);
+        match self {
+            TimingDistribution::Uniform => random_delay(rng, min_ms, max_ms),
+            TimingDistribution::Gaussian { mean_ms: None, std_dev_ms: None } => gaussian_delay(rng, min_ms, max_ms),
+            TimingDistribution::Gaussian { mean_ms, std_dev_ms } => {
+                let mean = mean_ms.unwrap_or((min + max) / 2.0);
+                let std_dev = std_dev_ms.unwrap_or((max - min) / 4.0);
+                normal_delay(rng, mean, std_dev, min_ms, max_ms)
+            }
+            TimingDistribution::LogNormal { median_ms, sigma } => match LogNormal::new(median_ms.ln(), *sigma) {
+                Ok(log_normal) => clamped(log_normal.sample(rng), min_ms, max_ms),
+                Err(_) => random_delay(rng, min_ms, max_ms),
+            },
+            TimingDistribution::Gamma { shape, mean_ms } => match Gamma::new(*shape, mean_ms / shape) {
+                Ok(gamma) => clamped(gamma.sample(rng), min_ms, max_ms),
+                Err(_) => random_delay(rng, min_ms, max_ms),
+            },
+            TimingDistribution::Triangular { mode_ms } => {
+                let triangular = Triangular::new(min, max, mode_ms.clamp(min, max)).unwrap();
+                clamped(triangular.sample(rng), min_ms, max_ms)
+            }
+            TimingDistribution::Empirical { buckets } => {
+                let Some(total) = total_weight(buckets).filter(|total| *total > 0) else {
+                    return random_delay(rng, min_ms, max_ms);
+                };
+                let mut pick = rng.random_range(0..total);
+                let mut from_ms = 0;
+                for bucket in buckets {
+                    if pick < bucket.weight {
+                        let drawn = random_delay(rng, from_ms, bucket.up_to_ms);
+                        return drawn.clamp(Duration::from_millis(min_ms), Duration::from_millis(max_ms));
+                    }
+                    pick -= bucket.weight;
+                    from_ms = bucket.up_to_ms;
+                }
+                unreachable!("pick is below the total weight")
+            }
         }
     }
-    base
 }
 
-/// Random click interval (uniform distribution)
-pub fn random_click_interval(min_ms: u64, max_ms: u64) -> Duration {
-    random_delay(min_ms, max_ms)
+/// Sum of the buckets' weights, None if it overflows
+fn total_weight(buckets: &[EmpiricalBucket]) -> Option<u64> {
+    buckets.iter().try_fold(0u64, |total, bucket| total.checked_add(bucket.weight))
+}
+
+/// Click interval drawn from `model`
+pub fn click_interval(model: &dyn TimingModel, rng: &mut dyn RngCore, min_ms: u64, max_ms: u64) -> Duration {
+    model.sample(rng, min_ms, max_ms)
 }
 
-/// Gaussian click interval (normal distribution)
-pub fn gaussian_click_interval(min_ms: u64, max_ms: u64) -> Duration {
-    gaussian_delay(min_ms, max_ms)
+/// Travel time drawn from `model`, with optional extra jitter for more natural release
+pub fn travel_time(model: &dyn TimingModel, rng: &mut dyn RngCore, min_ms: u64, max_ms: u64, jitter: bool) -> Duration {
+    let base = model.sample(rng, min_ms, max_ms);
+    if jitter {
+        // Add occasional extra jitter (0-50% of base time)
+        let jitter_chance: f64 = rng.random();
+        if jitter_chance < 0.3 {
+            let jitter_amount = (base.as_millis() as f64 * rng.random_range(0.0..0.5)) as u64;
+            return base + Duration::from_millis(jitter_amount);
+        }
+    }
+    base
 }
 
 /// Fatigue simulation state tracker
@@ -148,9 +345,9 @@ impl BurstTracker {
     }
     
     /// Get pause duration
-    pub fn pause_duration(&self) -> Duration {
+    pub fn pause_duration<R: Rng + ?Sized>(&self, rng: &mut R) -> Duration {
         // Add some randomness to pause duration (80-120% of base)
-        let variance: f64 = rand::rng().random_range(0.8..1.2);
+        let variance: f64 = rng.random_range(0.8..1.2);
         Duration::from_millis((self.pause_ms as f64 * variance) as u64)
     }
     
@@ -182,26 +379,32 @@ impl BurstTracker {
     }
 }
 
+/// Create the RNG for a proxy session, returning it with the seed actually used
+pub fn seeded_rng(seed: Option<u64>) -> (StdRng, u64) {
+    let seed = seed.unwrap_or_else(rand::random);
+    (StdRng::seed_from_u64(seed), seed)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_random_delay_returns_min_when_equal() {
-        let delay = random_delay(50, 50);
+        let delay = random_delay(&mut rand::rng(), 50, 50);
         assert_eq!(delay, Duration::from_millis(50));
     }
 
     #[test]
     fn test_random_delay_returns_min_when_min_greater() {
-        let delay = random_delay(100, 50);
+        let delay = random_delay(&mut rand::rng(), 100, 50);
         assert_eq!(delay, Duration::from_millis(100));
     }
 
     #[test]
     fn test_random_delay_within_range() {
         for _ in 0..100 {
-            let delay = random_delay(10, 50);
+            let delay = random_delay(&mut rand::rng(), 10, 50);
             let ms = delay.as_millis() as u64;
             assert!(ms >= 10, "delay {} should be >= 10", ms);
             assert!(ms <= 50, "delay {} should be <= 50", ms);
@@ -211,7 +414,7 @@ mod tests {
     #[test]
     fn test_random_travel_time_within_range() {
         for _ in 0..100 {
-            let delay = random_travel_time(5, 25, false);
+            let delay = travel_time(&TimingDistribution::Uniform, &mut rand::rng(), 5, 25, false);
             let ms = delay.as_millis() as u64;
             assert!(ms >= 5, "travel time {} should be >= 5", ms);
             assert!(ms <= 25, "travel time {} should be <= 25", ms);
@@ -221,7 +424,7 @@ mod tests {
     #[test]
     fn test_random_click_interval_within_range() {
         for _ in 0..100 {
-            let delay = random_click_interval(45, 80);
+            let delay = click_interval(&TimingDistribution::Uniform, &mut rand::rng(), 45, 80);
             let ms = delay.as_millis() as u64;
             assert!(ms >= 45, "click interval {} should be >= 45", ms);
             assert!(ms <= 80, "click interval {} should be <= 80", ms);
@@ -232,7 +435,7 @@ mod tests {
     fn test_randomness_produces_variance() {
         let mut values = std::collections::HashSet::new();
         for _ in 0..100 {
-            let delay = random_delay(10, 100);
+            let delay = random_delay(&mut rand::rng(), 10, 100);
             values.insert(delay.as_millis());
         }
         assert!(values.len() > 10, "Expected variance in random delays, got {} unique values", values.len());
@@ -240,16 +443,158 @@ mod tests {
 
     #[test]
     fn test_zero_delay_edge_case() {
-        let delay = random_delay(0, 0);
+        let delay = random_delay(&mut rand::rng(), 0, 0);
         assert_eq!(delay, Duration::from_millis(0));
     }
 
     #[test]
     fn test_large_range() {
         for _ in 0..50 {
-            let delay = random_delay(1, 1000);
+            let delay = random_delay(&mut rand::rng(), 1, 1000);
             let ms = delay.as_millis() as u64;
-            assert!(ms >= 1 && ms <= 1000);
+            assert!((1..=1000).contains(&ms));
         }
     }
+
+    #[test]
+    fn test_same_seed_reproduces_timing() {
+        let sample = |seed: u64| {
+            let (mut rng, used) = seeded_rng(Some(seed));
+            assert_eq!(used, seed);
+            let burst = BurstTracker::new(4, 100);
+            (0..20)
+                .map(|_| {
+                    (
+                        click_interval(&TimingDistribution::Uniform, &mut rng, 45, 80),
+                        travel_time(&TimingDistribution::RANGE_GAUSSIAN, &mut rng, 10, 25, true),
+                        burst.pause_duration(&mut rng),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sample(1234), sample(1234));
+        assert_ne!(sample(1234), sample(4321));
+    }
+
+    #[test]
+    fn test_seeded_rng_reports_generated_seed() {
+        let (mut a, seed) = seeded_rng(None);
+        let (mut b, _) = seeded_rng(Some(seed));
+        assert_eq!(random_delay(&mut a, 0, 1_000_000), random_delay(&mut b, 0, 1_000_000));
+    }
+
+    fn draws(distribution: &TimingDistribution, min_ms: u64, max_ms: u64) -> Vec<u64> {
+        let (mut rng, _) = seeded_rng(Some(7));
+        (0..2000)
+            .map(|_| distribution.sample(&mut rng, min_ms, max_ms).as_millis() as u64)
+            .collect()
+    }
+
+    fn median(mut values: Vec<u64>) -> u64 {
+        values.sort_unstable();
+        values[values.len() / 2]
+    }
+
+    #[test]
+    fn test_every_distribution_stays_in_range() {
+        for distribution in TimingDistribution::presets(40, 90) {
+            assert_eq!(distribution.validate(), Ok(()));
+            let values = draws(&distribution, 40, 90);
+            assert!(values.iter().all(|ms| (40..=90).contains(ms)), "{:?}", distribution);
+            assert!(
+                values.iter().collect::<std::collections::HashSet<_>>().len() > 10,
+                "{:?} should vary",
+                distribution
+            );
+        }
+    }
+
+    #[test]
+    fn test_legacy_distributions_draw_as_before() {
+        let (mut a, _) = seeded_rng(Some(11));
+        let (mut b, _) = seeded_rng(Some(11));
+        for _ in 0..50 {
+            assert_eq!(TimingDistribution::Uniform.sample(&mut a, 45, 80), random_delay(&mut b, 45, 80));
+            assert_eq!(TimingDistribution::RANGE_GAUSSIAN.sample(&mut a, 45, 80), gaussian_delay(&mut b, 45, 80));
+        }
+    }
+
+    #[test]
+    fn test_distribution_parameters_shape_draws() {
+        let fixed = TimingDistribution::Gaussian { mean_ms: Some(70.0), std_dev_ms: Some(0.0) };
+        assert!(draws(&fixed, 50, 100).iter().all(|ms| *ms == 70));
+
+        let log_normal = TimingDistribution::LogNormal { median_ms: 60.0, sigma: 0.2 };
+        assert!((58..=62).contains(&median(draws(&log_normal, 20, 200))));
+
+        let gamma = TimingDistribution::Gamma { shape: 16.0, mean_ms: 80.0 };
+        let values = draws(&gamma, 20, 200);
+        let mean = values.iter().sum::<u64>() / values.len() as u64;
+        assert!((77..=81).contains(&mean), "mean {}", mean);
+
+        // A mode at the top of the range puts most draws in its upper half
+        let triangular = TimingDistribution::Triangular { mode_ms: 100.0 };
+        assert!(draws(&triangular, 0, 100).iter().filter(|ms| **ms >= 50).count() > 1400);
+    }
+
+    #[test]
+    fn test_empirical_follows_bucket_weights() {
+        let empirical = TimingDistribution::Empirical {
+            buckets: vec![
+                EmpiricalBucket { up_to_ms: 50, weight: 0 },
+                EmpiricalBucket { up_to_ms: 60, weight: 3 },
+                EmpiricalBucket { up_to_ms: 70, weight: 1 },
+            ],
+        };
+        let values = draws(&empirical, 40, 100);
+        assert!(values.iter().all(|ms| (50..=70).contains(ms)));
+        let low = values.iter().filter(|ms| **ms < 60).count();
+        assert!((1300..1700).contains(&low), "{} of 2000 below 60 ms", low);
+
+        let unweighted = TimingDistribution::Empirical {
+            buckets: vec![EmpiricalBucket { up_to_ms: 50, weight: 0 }],
+        };
+        assert!(unweighted.validate().is_err());
+        let unordered = TimingDistribution::Empirical {
+            buckets: vec![EmpiricalBucket { up_to_ms: 60, weight: 1 }, EmpiricalBucket { up_to_ms: 50, weight: 1 }],
+        };
+        assert_eq!(unordered.validate().unwrap_err(), "Bucket bounds must increase");
+
+        let overflowing = TimingDistribution::Empirical {
+            buckets: vec![
+                EmpiricalBucket { up_to_ms: 50, weight: u64::MAX },
+                EmpiricalBucket { up_to_ms: 60, weight: 1 },
+            ],
+        };
+        assert!(overflowing.validate().is_err());
+        assert!(draws(&overflowing, 40, 100).iter().all(|ms| (40..=100).contains(ms)));
+    }
+
+    #[test]
+    fn test_empirical_from_histogram() {
+        let mut histogram = Histogram::new(&[50_000, 60_000]);
+        for ms in [45, 55, 55, 130] {
+            histogram.record(Duration::from_millis(ms));
+        }
+        assert_eq!(
+            TimingDistribution::from_histogram(&histogram),
+            TimingDistribution::Empirical {
+                buckets: vec![
+                    EmpiricalBucket { up_to_ms: 50, weight: 1 },
+                    EmpiricalBucket { up_to_ms: 60, weight: 2 },
+                    EmpiricalBucket { up_to_ms: 130, weight: 1 },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        let negative = TimingDistribution::Gaussian { mean_ms: None, std_dev_ms: Some(-1.0) };
+        assert_eq!(negative.validate().unwrap_err(), "Std-dev cannot be negative");
+        let log_normal = TimingDistribution::LogNormal { median_ms: 0.0, sigma: 0.2 };
+        assert_eq!(log_normal.validate().unwrap_err(), "Median must be above 0 ms");
+        let gamma = TimingDistribution::Gamma { shape: 0.0, mean_ms: 60.0 };
+        assert_eq!(gamma.validate().unwrap_err(), "Shape must be above 0");
+    }
 }
@@ -0,0 +1,386 @@
+//! Descriptive details of an input device beyond its name and ids, to tell the
+//! several event nodes of one mouse apart, and a guess at what kind of device
+//! it is. Shared by the device picker and the diagnose tool.
+
+use evdev::{AbsoluteAxisCode, BusType, Device, KeyCode, PropType, RelativeAxisCode};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Key codes from here up to `FIRST_KEY_AFTER_BUTTONS` are buttons rather than keys
+const FIRST_BUTTON: u16 = 0x100;
+const FIRST_KEY_AFTER_BUTTONS: u16 = 0x160;
+
+/// Mouse buttons beyond left, right and middle
+const EXTRA_MOUSE_BUTTONS: &[KeyCode] = &[
+    KeyCode::BTN_SIDE,
+    KeyCode::BTN_EXTRA,
+    KeyCode::BTN_FORWARD,
+    KeyCode::BTN_BACK,
+    KeyCode::BTN_TASK,
+];
+
+/// Vendors that mostly sell gaming mice: Logitech, Razer, SteelSeries, Corsair,
+/// Roccat, Glorious, Zowie and Finalmouse
+const GAMING_VENDORS: &[u16] = &[0x046d, 0x1532, 0x1038, 0x1b1c, 0x1e7d, 0x258a, 0x1af3, 0x361d];
+
+/// What kind of device an event node most likely is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeviceKind {
+    Mouse,
+    Touchpad,
+    /// Trackpoint or similar nub
+    PointingStick,
+    Keyboard,
+    Gamepad,
+    /// Created in software, e.g. through uinput (including our own virtual mouse)
+    Virtual,
+    #[default]
+    Other,
+}
+
+impl DeviceKind {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            DeviceKind::Mouse => "Mouse",
+            DeviceKind::Touchpad => "Touchpad",
+            DeviceKind::PointingStick => "Pointing stick",
+            DeviceKind::Keyboard => "Keyboard",
+            DeviceKind::Gamepad => "Gamepad",
+            DeviceKind::Virtual => "Virtual",
+            DeviceKind::Other => "Other",
+        }
+    }
+}
+
+/// What a device reports, as far as classifying it goes
+#[derive(Debug, Clone, Default)]
+struct DeviceTraits {
+    name: String,
+    bus: u16,
+    vendor_id: u16,
+    keys: Vec<KeyCode>,
+    relative_axes: Vec<RelativeAxisCode>,
+    absolute_axes: Vec<AbsoluteAxisCode>,
+    properties: Vec<PropType>,
+    /// Its sysfs node lives under /sys/devices/virtual, i.e. no hardware behind it
+    sysfs_virtual: bool,
+}
+
+impl DeviceTraits {
+    fn of(path: &Path, device: &Device) -> Self {
+        let id = device.input_id();
+        Self {
+            name: device.name().unwrap_or_default().to_string(),
+            bus: id.bus_type().0,
+            vendor_id: id.vendor(),
+            keys: device.supported_keys().map_or_else(Vec::new, |keys| keys.iter().collect()),
+            relative_axes: device.supported_relative_axes().map_or_else(Vec::new, |axes| axes.iter().collect()),
+            absolute_axes: device.supported_absolute_axes().map_or_else(Vec::new, |axes| axes.iter().collect()),
+            properties: device.properties().iter().collect(),
+            sysfs_virtual: sysfs_is_virtual(path),
+        }
+    }
+
+    fn has_key(&self, key: KeyCode) -> bool {
+        self.keys.contains(&key)
+    }
+
+    fn has_rel(&self, axis: RelativeAxisCode) -> bool {
+        self.relative_axes.contains(&axis)
+    }
+
+    fn has_abs(&self, axis: AbsoluteAxisCode) -> bool {
+        self.absolute_axes.contains(&axis)
+    }
+
+    fn has_prop(&self, prop: PropType) -> bool {
+        self.properties.contains(&prop)
+    }
+
+    fn kind(&self) -> DeviceKind {
+        let name = self.name.to_lowercase();
+        if self.sysfs_virtual
+            || self.bus == BusType::BUS_VIRTUAL.0
+            || name.contains("virtual")
+            || name.contains("uinput")
+        {
+            return DeviceKind::Virtual;
+        }
+        // BTN_TRIGGER is the first joystick button
+        if self.has_key(KeyCode::BTN_SOUTH) || self.has_key(KeyCode::BTN_TRIGGER) {
+            return DeviceKind::Gamepad;
+        }
+        let absolute_pointer =
+            self.has_abs(AbsoluteAxisCode::ABS_X) || self.has_abs(AbsoluteAxisCode::ABS_MT_POSITION_X);
+        if absolute_pointer
+            && (self.has_key(KeyCode::BTN_TOOL_FINGER)
+                || self.has_prop(PropType::BUTTONPAD)
+                || (self.has_prop(PropType::POINTER) && self.has_key(KeyCode::BTN_TOUCH)))
+        {
+            return DeviceKind::Touchpad;
+        }
+        let relative_pointer = self.has_rel(RelativeAxisCode::REL_X) && self.has_rel(RelativeAxisCode::REL_Y);
+        if relative_pointer && self.has_prop(PropType::POINTING_STICK) {
+            return DeviceKind::PointingStick;
+        }
+        if relative_pointer && self.has_key(KeyCode::BTN_LEFT) {
+            return DeviceKind::Mouse;
+        }
+        if self.has_key(KeyCode::KEY_A) && self.has_key(KeyCode::KEY_SPACE) && self.has_key(KeyCode::KEY_ENTER) {
+            return DeviceKind::Keyboard;
+        }
+        DeviceKind::Other
+    }
+
+    /// How likely this is the gaming mouse someone wants to rapid-fire; higher first
+    fn score(&self, kind: DeviceKind) -> i32 {
+        let base = match kind {
+            DeviceKind::Mouse => 100,
+            DeviceKind::PointingStick => 20,
+            DeviceKind::Touchpad => 10,
+            DeviceKind::Other => 0,
+            DeviceKind::Keyboard | DeviceKind::Gamepad => -50,
+            DeviceKind::Virtual => -100,
+        };
+        if kind != DeviceKind::Mouse {
+            return base;
+        }
+        let extra_buttons = EXTRA_MOUSE_BUTTONS.iter().filter(|button| self.has_key(**button)).count() as i32;
+        let mut score = base + 10 * extra_buttons;
+        if self.has_rel(RelativeAxisCode::REL_WHEEL) {
+            score += 5;
+        }
+        if self.has_rel(RelativeAxisCode::REL_HWHEEL) || self.has_rel(RelativeAxisCode::REL_WHEEL_HI_RES) {
+            score += 5;
+        }
+        if self.bus == BusType::BUS_USB.0 {
+            score += 10;
+        }
+        if GAMING_VENDORS.contains(&self.vendor_id) {
+            score += 15;
+        }
+        score
+    }
+}
+
+/// What kind of device `device`, opened from `path`, is and how likely it is a
+/// gaming mouse
+pub fn classify(path: &Path, device: &Device) -> (DeviceKind, i32) {
+    let traits = DeviceTraits::of(path, device);
+    let kind = traits.kind();
+    (kind, traits.score(kind))
+}
+
+/// Whether the sysfs node behind `path` (/dev/input/eventN) has no hardware parent
+fn sysfs_is_virtual(path: &Path) -> bool {
+    let Some(node) = path.file_name() else {
+        return false;
+    };
+    fs::canonicalize(Path::new("/sys/class/input").join(node).join("device"))
+        .is_ok_and(|sysfs| sysfs.starts_with("/sys/devices/virtual"))
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeviceDetails {
+    pub kind: DeviceKind,
+    /// How likely this is a gaming mouse; devices are listed highest first
+    pub score: i32,
+    /// Bus the device is attached through, e.g. "USB" or "Bluetooth"
+    pub bus: String,
+    pub version: u16,
+    /// Short summary of the buttons, keys and axes the device reports
+    pub capabilities: String,
+    /// Symlinks to the node in /dev/input/by-id
+    pub by_id: Vec<String>,
+    /// Symlinks to the node in /dev/input/by-path
+    pub by_path: Vec<String>,
+}
+
+impl DeviceDetails {
+    /// Look `device`, opened from `path`, over without disturbing its input
+    pub fn inspect(path: &Path, device: &mut Device) -> Self {
+        let id = device.input_id();
+        let (kind, score) = classify(path, device);
+        Self {
+            kind,
+            score,
+            bus: id.bus_type().to_string(),
+            version: id.version(),
+            capabilities: capability_summary(device),
+            by_id: links_to(Path::new("/dev/input/by-id"), path),
+            by_path: links_to(Path::new("/dev/input/by-path"), path),
+        }
+    }
+
+    /// One "label: value" line per detail, for tooltips and the diagnose tool
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("Kind: {} (score {})", self.kind.display_name(), self.score),
+            format!("Bus: {} (version {:04x})", self.bus, self.version),
+            format!("Reports: {}", self.capabilities),
+        ];
+        lines.extend(self.by_id.iter().map(|link| format!("By id: {}", link)));
+        lines.extend(self.by_path.iter().map(|link| format!("By path: {}", link)));
+        lines
+    }
+}
+
+/// e.g. "5 buttons, 2 keys, REL X Y WHEEL"
+fn capability_summary(device: &Device) -> String {
+    let is_button = |key: &KeyCode| (FIRST_BUTTON..FIRST_KEY_AFTER_BUTTONS).contains(&key.0);
+    let (buttons, keys): (Vec<KeyCode>, Vec<KeyCode>) = device
+        .supported_keys()
+        .map_or_else(Default::default, |supported| supported.iter().partition(is_button));
+    let mut parts = Vec::new();
+    if !buttons.is_empty() {
+        parts.push(format!("{} buttons", buttons.len()));
+    }
+    if !keys.is_empty() {
+        parts.push(format!("{} keys", keys.len()));
+    }
+    if let Some(axes) = device.supported_relative_axes() {
+        let names: Vec<String> = axes
+            .iter()
+            .map(|axis| format!("{:?}", axis).trim_start_matches("REL_").to_string())
+            .collect();
+        if !names.is_empty() {
+            parts.push(format!("REL {}", names.join(" ")));
+        }
+    }
+    if let Some(axes) = device.supported_absolute_axes() {
+        let count = axes.iter().count();
+        if count > 0 {
+            parts.push(format!("{} absolute axes", count));
+        }
+    }
+    if parts.is_empty() {
+        "nothing".to_string()
+    } else {
+        parts.join(", ")
+    }
+}
+
+/// Names of the symlinks in `dir` that point at `target`
+fn links_to(dir: &Path, target: &Path) -> Vec<String> {
+    let Ok(target) = fs::canonicalize(target) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut links: Vec<String> = entries
+        .flatten()
+        .filter(|entry| fs::canonicalize(entry.path()).is_ok_and(|resolved| resolved == target))
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    links.sort();
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines_list_links() {
+        let details = DeviceDetails {
+            kind: DeviceKind::Mouse,
+            score: 135,
+            bus: "USB".to_string(),
+            version: 0x0111,
+            capabilities: "5 buttons, REL X Y WHEEL".to_string(),
+            by_id: vec!["usb-Logitech_G502-event-mouse".to_string()],
+            by_path: Vec::new(),
+        };
+        assert_eq!(
+            details.lines(),
+            vec![
+                "Kind: Mouse (score 135)",
+                "Bus: USB (version 0111)",
+                "Reports: 5 buttons, REL X Y WHEEL",
+                "By id: usb-Logitech_G502-event-mouse",
+            ]
+        );
+    }
+
+    fn mouse(vendor_id: u16, bus: BusType, buttons: &[KeyCode]) -> DeviceTraits {
+        DeviceTraits {
+            name: "Mouse".to_string(),
+            bus: bus.0,
+            vendor_id,
+            keys: [KeyCode::BTN_LEFT, KeyCode::BTN_RIGHT, KeyCode::BTN_MIDDLE]
+                .into_iter()
+                .chain(buttons.iter().copied())
+                .collect(),
+            relative_axes: vec![RelativeAxisCode::REL_X, RelativeAxisCode::REL_Y, RelativeAxisCode::REL_WHEEL],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_classifies_common_devices() {
+        let plain_mouse = mouse(0x093a, BusType::BUS_USB, &[]);
+        assert_eq!(plain_mouse.kind(), DeviceKind::Mouse);
+
+        let touchpad = DeviceTraits {
+            keys: vec![KeyCode::BTN_LEFT, KeyCode::BTN_TOUCH, KeyCode::BTN_TOOL_FINGER],
+            absolute_axes: vec![AbsoluteAxisCode::ABS_X, AbsoluteAxisCode::ABS_Y, AbsoluteAxisCode::ABS_MT_POSITION_X],
+            properties: vec![PropType::POINTER, PropType::BUTTONPAD],
+            ..Default::default()
+        };
+        assert_eq!(touchpad.kind(), DeviceKind::Touchpad);
+
+        let trackpoint = DeviceTraits {
+            properties: vec![PropType::POINTER, PropType::POINTING_STICK],
+            ..plain_mouse.clone()
+        };
+        assert_eq!(trackpoint.kind(), DeviceKind::PointingStick);
+
+        let keyboard = DeviceTraits {
+            keys: vec![KeyCode::KEY_A, KeyCode::KEY_SPACE, KeyCode::KEY_ENTER],
+            ..Default::default()
+        };
+        assert_eq!(keyboard.kind(), DeviceKind::Keyboard);
+
+        let gamepad = DeviceTraits {
+            keys: vec![KeyCode::BTN_SOUTH, KeyCode::BTN_EAST],
+            absolute_axes: vec![AbsoluteAxisCode::ABS_X, AbsoluteAxisCode::ABS_Y],
+            ..Default::default()
+        };
+        assert_eq!(gamepad.kind(), DeviceKind::Gamepad);
+    }
+
+    #[test]
+    fn test_virtual_devices_are_recognised_despite_a_real_name() {
+        // Our own virtual mouse copies the real mouse's name and ids
+        let clone = DeviceTraits {
+            sysfs_virtual: true,
+            ..mouse(0x046d, BusType::BUS_USB, &[KeyCode::BTN_SIDE])
+        };
+        assert_eq!(clone.kind(), DeviceKind::Virtual);
+        assert_eq!(mouse(0x046d, BusType::BUS_VIRTUAL, &[]).kind(), DeviceKind::Virtual);
+    }
+
+    #[test]
+    fn test_gaming_mice_rank_first() {
+        let gaming = mouse(0x1532, BusType::BUS_USB, &[KeyCode::BTN_SIDE, KeyCode::BTN_EXTRA]);
+        let office = mouse(0x093a, BusType::BUS_USB, &[]);
+        let bluetooth = mouse(0x093a, BusType::BUS_BLUETOOTH, &[]);
+        let score = |traits: &DeviceTraits| traits.score(traits.kind());
+
+        assert!(score(&gaming) > score(&office));
+        assert!(score(&office) > score(&bluetooth));
+        let touchpad = DeviceTraits {
+            keys: vec![KeyCode::BTN_TOOL_FINGER],
+            absolute_axes: vec![AbsoluteAxisCode::ABS_X],
+            ..Default::default()
+        };
+        assert!(score(&bluetooth) > score(&touchpad));
+    }
+
+    #[test]
+    fn test_links_to_missing_node() {
+        assert!(links_to(Path::new("/dev/input/by-id"), Path::new("/dev/input/event99999")).is_empty());
+    }
+}
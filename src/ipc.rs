@@ -0,0 +1,153 @@
+//! JSON protocol spoken over the daemon's Unix domain control socket.
+//!
+//! Each connection carries exactly one request line and one response line.
+
+use crate::config::Config;
+use crate::device::DeviceInfo;
+use crate::stats::ProxyStats;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// Start the proxy, optionally replacing the daemon's config first
+    Start {
+        #[serde(default)]
+        config: Option<Box<Config>>,
+    },
+    Stop,
+    Status,
+    /// Replace the daemon's config, or re-read it from disk if none is given
+    /// (switching to `profile` when one is named)
+    Reload {
+        #[serde(default)]
+        config: Option<Box<Config>>,
+        #[serde(default)]
+        profile: Option<String>,
+    },
+    ListDevices {
+        #[serde(default)]
+        all: bool,
+    },
+    /// Statistics of the running proxy, or of the last run if it has stopped
+    Stats,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    pub running: bool,
+    pub config: Config,
+    /// Error from the last proxy run, if it exited abnormally
+    pub last_error: Option<String>,
+    /// Real-time tuning the running proxy could not apply
+    #[serde(default)]
+    pub tuning_warning: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    Status(DaemonStatus),
+    Devices { devices: Vec<DeviceInfo> },
+    Stats { stats: ProxyStats },
+    Error { message: String },
+}
+
+impl Response {
+    pub fn error(message: impl Into<String>) -> Self {
+        Response::Error { message: message.into() }
+    }
+}
+
+/// Default location of the control socket: $XDG_RUNTIME_DIR/ferrisfire.sock,
+/// falling back to a private per-user directory in /tmp
+pub fn default_socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("ferrisfire.sock"),
+        _ => fallback_socket_dir().join("ferrisfire.sock"),
+    }
+}
+
+/// Directory for the socket when there is no runtime dir; the daemon keeps it at 0700
+pub fn fallback_socket_dir() -> PathBuf {
+    PathBuf::from(format!("/tmp/ferrisfire-{}", unsafe { libc::getuid() }))
+}
+
+/// Send a single request to the daemon and wait for its response
+pub fn send_request(socket_path: &Path, request: &Request) -> Result<Response, String> {
+    let mut stream = UnixStream::connect(socket_path)
+        .map_err(|e| format!("Cannot connect to daemon at {}: {}", socket_path.display(), e))?;
+    stream.set_read_timeout(Some(IO_TIMEOUT)).ok();
+    stream.set_write_timeout(Some(IO_TIMEOUT)).ok();
+
+    write_message(&mut stream, request)?;
+    read_message(&mut BufReader::new(stream))?
+        .ok_or_else(|| "Daemon closed the connection without responding".to_string())
+}
+
+pub fn write_message<T: Serialize>(writer: &mut impl Write, message: &T) -> Result<(), String> {
+    let mut line = serde_json::to_string(message).map_err(|e| format!("Failed to encode message: {}", e))?;
+    line.push('\n');
+    writer
+        .write_all(line.as_bytes())
+        .and_then(|_| writer.flush())
+        .map_err(|e| format!("Failed to send message: {}", e))
+}
+
+/// Read one newline-terminated JSON message; Ok(None) means the peer hung up
+pub fn read_message<T: for<'de> Deserialize<'de>>(reader: &mut impl BufRead) -> Result<Option<T>, String> {
+    let mut line = String::new();
+    let read = reader
+        .read_line(&mut line)
+        .map_err(|e| format!("Failed to read message: {}", e))?;
+    if read == 0 {
+        return Ok(None);
+    }
+    serde_json::from_str(line.trim_end())
+        .map(Some)
+        .map_err(|e| format!("Invalid message: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_wire_format() {
+        let json = serde_json::to_string(&Request::Stop).unwrap();
+        assert_eq!(json, r#"{"command":"stop"}"#);
+
+        let parsed: Request = serde_json::from_str(r#"{"command":"list_devices"}"#).unwrap();
+        assert_eq!(parsed, Request::ListDevices { all: false });
+
+        let parsed: Request = serde_json::from_str(r#"{"command":"start"}"#).unwrap();
+        assert_eq!(parsed, Request::Start { config: None });
+    }
+
+    #[test]
+    fn test_message_roundtrip() {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &Response::error("boom")).unwrap();
+        assert!(buffer.ends_with(b"\n"));
+
+        let mut reader = BufReader::new(buffer.as_slice());
+        match read_message::<Response>(&mut reader).unwrap() {
+            Some(Response::Error { message }) => assert_eq!(message, "boom"),
+            other => panic!("unexpected response: {:?}", other),
+        }
+        assert!(read_message::<Response>(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_connect_to_missing_socket_fails() {
+        let result = send_request(Path::new("/nonexistent/ferrisfire.sock"), &Request::Status);
+        assert!(result.unwrap_err().starts_with("Cannot connect to daemon"));
+    }
+}
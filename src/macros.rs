@@ -0,0 +1,351 @@
+//! Macro sequences a binding can play instead of rapid-fire, the small
+//! script language they are edited in, e.g. "press r, wait 30, release r, click left 2",
+//! and turning recorded input into such a sequence
+
+use evdev::{EventType, InputEvent, KeyCode, RelativeAxisCode};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Button names accepted in scripts besides the KEY_/BTN_ code names
+const BUTTON_ALIASES: &[(&str, KeyCode)] = &[
+    ("left", KeyCode::BTN_LEFT),
+    ("right", KeyCode::BTN_RIGHT),
+    ("middle", KeyCode::BTN_MIDDLE),
+    ("mouse4", KeyCode::BTN_SIDE),
+    ("mouse5", KeyCode::BTN_EXTRA),
+];
+
+/// One step of a macro
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum MacroStep {
+    Press {
+        key: u16,
+    },
+    Release {
+        key: u16,
+    },
+    /// Press and release `count` times, held for the binding's travel time
+    /// with its click delay between clicks, like rapid-fire
+    Click {
+        key: u16,
+        #[serde(default = "default_click_count")]
+        count: u32,
+    },
+    Wait {
+        ms: u64,
+    },
+    /// Relative motion on one axis, e.g. the mouse moving or the wheel turning
+    Move {
+        axis: u16,
+        value: i32,
+    },
+}
+
+fn default_click_count() -> u32 {
+    1
+}
+
+impl MacroStep {
+    /// The key the step presses or releases, if any
+    pub fn key(&self) -> Option<u16> {
+        match self {
+            MacroStep::Press { key } | MacroStep::Release { key } | MacroStep::Click { key, .. } => Some(*key),
+            MacroStep::Wait { .. } | MacroStep::Move { .. } => None,
+        }
+    }
+}
+
+/// Script name of a key: a button alias, the KEY_ name without its prefix in
+/// lower case, or the full code name
+pub fn key_token(code: u16) -> String {
+    if let Some((alias, _)) = BUTTON_ALIASES.iter().find(|(_, key)| key.0 == code) {
+        return alias.to_string();
+    }
+    let name = format!("{:?}", KeyCode(code));
+    match name.strip_prefix("KEY_") {
+        Some(key) => key.to_lowercase(),
+        None => name,
+    }
+}
+
+/// Parse a key as written by `key_token`; code names are accepted in any case
+pub fn parse_key(token: &str) -> Option<u16> {
+    let lower = token.to_lowercase();
+    if let Some((_, key)) = BUTTON_ALIASES.iter().find(|(alias, _)| *alias == lower) {
+        return Some(key.0);
+    }
+    let upper = token.to_uppercase();
+    KeyCode::from_str(&upper)
+        .or_else(|_| KeyCode::from_str(&format!("KEY_{}", upper)))
+        .ok()
+        .map(|key| key.0)
+}
+
+/// Script name of a relative axis: the REL_ name without its prefix in lower case
+pub fn axis_token(code: u16) -> String {
+    let name = format!("{:?}", RelativeAxisCode(code));
+    match name.strip_prefix("REL_") {
+        Some(axis) => axis.to_lowercase(),
+        None => name,
+    }
+}
+
+/// Parse an axis as written by `axis_token`, or its full REL_ name
+pub fn parse_axis(token: &str) -> Option<u16> {
+    let upper = token.to_uppercase();
+    RelativeAxisCode::from_str(&upper)
+        .or_else(|_| RelativeAxisCode::from_str(&format!("REL_{}", upper)))
+        .ok()
+        .map(|axis| axis.0)
+}
+
+/// Parse a script of steps separated by commas or new lines
+pub fn parse_script(script: &str) -> Result<Vec<MacroStep>, String> {
+    script
+        .split([',', '\n'])
+        .map(str::trim)
+        .filter(|step| !step.is_empty())
+        .enumerate()
+        .map(|(idx, step)| parse_step(step).map_err(|e| format!("Step {}: {}", idx + 1, e)))
+        .collect()
+}
+
+fn parse_step(step: &str) -> Result<MacroStep, String> {
+    let words: Vec<&str> = step.split_whitespace().collect();
+    let key = |idx: usize| -> Result<u16, String> {
+        let token = words.get(idx).ok_or("missing key")?;
+        parse_key(token).ok_or_else(|| format!("unknown key '{}'", token))
+    };
+    let expected_words = match words[0].to_lowercase().as_str() {
+        "press" | "release" | "wait" => 2,
+        "click" => words.len().clamp(2, 3),
+        "move" => 3,
+        other => return Err(format!("unknown action '{}'", other)),
+    };
+    if words.len() > expected_words {
+        return Err(format!("unexpected '{}'", words[expected_words]));
+    }
+
+    match words[0].to_lowercase().as_str() {
+        "press" => Ok(MacroStep::Press { key: key(1)? }),
+        "release" => Ok(MacroStep::Release { key: key(1)? }),
+        "click" => {
+            let count = match words.get(2) {
+                Some(count) => count
+                    .parse()
+                    .ok()
+                    .filter(|count| *count > 0)
+                    .ok_or_else(|| format!("'{}' is not a click count", count))?,
+                None => 1,
+            };
+            Ok(MacroStep::Click { key: key(1)?, count })
+        }
+        "move" => {
+            let axis = words.get(1).ok_or("missing axis")?;
+            let axis = parse_axis(axis).ok_or_else(|| format!("unknown axis '{}'", axis))?;
+            let value = words.get(2).ok_or("missing distance")?;
+            let value = value.parse().map_err(|_| format!("'{}' is not a distance", value))?;
+            Ok(MacroStep::Move { axis, value })
+        }
+        _ => {
+            let ms = words.get(1).ok_or("missing duration")?;
+            ms.trim_end_matches("ms")
+                .parse()
+                .map(|ms| MacroStep::Wait { ms })
+                .map_err(|_| format!("'{}' is not a duration in ms", ms))
+        }
+    }
+}
+
+/// Write steps back as a script, one per line
+pub fn format_script(steps: &[MacroStep]) -> String {
+    steps
+        .iter()
+        .map(|step| match step {
+            MacroStep::Press { key } => format!("press {}", key_token(*key)),
+            MacroStep::Release { key } => format!("release {}", key_token(*key)),
+            MacroStep::Click { key, count: 1 } => format!("click {}", key_token(*key)),
+            MacroStep::Click { key, count } => format!("click {} {}", key_token(*key), count),
+            MacroStep::Wait { ms } => format!("wait {}", ms),
+            MacroStep::Move { axis, value } => format!("move {} {}", axis_token(*axis), value),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Steps built up from recorded input. Gaps between events become waits in
+/// whole milliseconds, motion within the same millisecond is summed per axis,
+/// and releases of keys pressed before recording started are dropped.
+#[derive(Debug, Default)]
+pub struct MacroRecording {
+    steps: Vec<MacroStep>,
+    /// Time of the last step, in whole milliseconds since recording started
+    last_ms: u64,
+    /// Keys pressed during the recording and not released yet
+    pressed: Vec<u16>,
+}
+
+impl MacroRecording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an event that happened `at` after recording started; only key
+    /// presses and releases and relative motion are kept
+    pub fn push(&mut self, at: Duration, event: &InputEvent) {
+        let step = match event.event_type() {
+            EventType::KEY => match event.value() {
+                1 if !self.pressed.contains(&event.code()) => {
+                    self.pressed.push(event.code());
+                    MacroStep::Press { key: event.code() }
+                }
+                0 if self.pressed.contains(&event.code()) => {
+                    self.pressed.retain(|key| *key != event.code());
+                    MacroStep::Release { key: event.code() }
+                }
+                // Key repeats, and releases of keys held before recording
+                _ => return,
+            },
+            EventType::RELATIVE if event.value() != 0 => MacroStep::Move {
+                axis: event.code(),
+                value: event.value(),
+            },
+            _ => return,
+        };
+
+        let ms = at.as_millis() as u64;
+        if ms > self.last_ms {
+            self.steps.push(MacroStep::Wait { ms: ms - self.last_ms });
+            self.last_ms = ms;
+        } else if let MacroStep::Move { axis, value } = step {
+            // Fold into motion on the same axis since the last wait or key
+            let merged = self
+                .steps
+                .iter_mut()
+                .rev()
+                .map_while(|step| match step {
+                    MacroStep::Move { axis, value } => Some((*axis, value)),
+                    _ => None,
+                })
+                .find(|(earlier, _)| *earlier == axis);
+            // Motion too large for one step goes into a new one
+            if let Some((_, earlier)) = merged
+                && let Some(sum) = earlier.checked_add(value)
+            {
+                *earlier = sum;
+                return;
+            }
+        }
+        self.steps.push(step);
+    }
+
+    /// Whether nothing worth keeping was recorded yet
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn into_steps(self) -> Vec<MacroStep> {
+        self.steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let steps = parse_script("press R, wait 30ms, release r\nclick left 2").unwrap();
+        assert_eq!(
+            steps,
+            vec![
+                MacroStep::Press { key: KeyCode::KEY_R.0 },
+                MacroStep::Wait { ms: 30 },
+                MacroStep::Release { key: KeyCode::KEY_R.0 },
+                MacroStep::Click {
+                    key: KeyCode::BTN_LEFT.0,
+                    count: 2
+                },
+            ]
+        );
+        assert_eq!(parse_script(" , \n").unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn test_parse_script_errors() {
+        assert_eq!(parse_script("press r, hold r").unwrap_err(), "Step 2: unknown action 'hold'");
+        assert_eq!(parse_script("press banana").unwrap_err(), "Step 1: unknown key 'banana'");
+        assert_eq!(parse_script("release").unwrap_err(), "Step 1: missing key");
+        assert_eq!(parse_script("wait soon").unwrap_err(), "Step 1: 'soon' is not a duration in ms");
+        assert_eq!(parse_script("click left 0").unwrap_err(), "Step 1: '0' is not a click count");
+        assert_eq!(parse_script("press r now").unwrap_err(), "Step 1: unexpected 'now'");
+        assert_eq!(parse_script("move z2 3").unwrap_err(), "Step 1: unknown axis 'z2'");
+        assert_eq!(parse_script("move x").unwrap_err(), "Step 1: missing distance");
+    }
+
+    #[test]
+    fn test_format_script_round_trips() {
+        let script = "press leftshift\nclick mouse5 3\nwait 120\nmove x -4\nrelease leftshift\nclick BTN_TASK";
+        let steps = parse_script(script).unwrap();
+        assert_eq!(format_script(&steps), script);
+        assert_eq!(parse_script(&format_script(&steps)).unwrap(), steps);
+    }
+
+    #[test]
+    fn test_steps_serialize_tagged() {
+        let json = serde_json::to_string(&MacroStep::Wait { ms: 30 }).unwrap();
+        assert_eq!(json, r#"{"step":"wait","ms":30}"#);
+        let click: MacroStep = serde_json::from_str(r#"{"step":"click","key":272}"#).unwrap();
+        assert_eq!(click, MacroStep::Click { key: 272, count: 1 });
+    }
+
+    fn at(us: u64) -> Duration {
+        Duration::from_micros(us)
+    }
+
+    fn rel(axis: RelativeAxisCode, value: i32) -> InputEvent {
+        InputEvent::new(EventType::RELATIVE.0, axis.0, value)
+    }
+
+    fn key(code: KeyCode, value: i32) -> InputEvent {
+        InputEvent::new(EventType::KEY.0, code.0, value)
+    }
+
+    #[test]
+    fn test_recording_times_and_merges_input() {
+        let mut recording = MacroRecording::new();
+        // The release of the click that started recording is not part of the macro
+        recording.push(at(0), &key(KeyCode::BTN_LEFT, 0));
+        recording.push(at(0), &rel(RelativeAxisCode::REL_X, 2));
+        recording.push(at(0), &rel(RelativeAxisCode::REL_Y, -1));
+        recording.push(at(400), &rel(RelativeAxisCode::REL_X, 3));
+        recording.push(at(400), &InputEvent::new(EventType::SYNCHRONIZATION.0, 0, 0));
+        recording.push(at(1_600), &key(KeyCode::KEY_R, 1));
+        recording.push(at(1_700), &key(KeyCode::KEY_R, 2));
+        recording.push(at(31_900), &key(KeyCode::KEY_R, 0));
+        recording.push(at(32_000), &rel(RelativeAxisCode::REL_WHEEL, -1));
+
+        assert_eq!(
+            format_script(&recording.into_steps()),
+            "move x 5\nmove y -1\nwait 1\npress r\nwait 30\nrelease r\nwait 1\nmove wheel -1"
+        );
+    }
+
+    #[test]
+    fn test_recording_splits_motion_that_would_overflow() {
+        let mut recording = MacroRecording::new();
+        recording.push(at(0), &rel(RelativeAxisCode::REL_X, i32::MAX));
+        recording.push(at(0), &rel(RelativeAxisCode::REL_X, 1));
+        recording.push(at(0), &rel(RelativeAxisCode::REL_X, 2));
+
+        assert_eq!(
+            recording.into_steps(),
+            vec![
+                MacroStep::Move { axis: RelativeAxisCode::REL_X.0, value: i32::MAX },
+                MacroStep::Move { axis: RelativeAxisCode::REL_X.0, value: 3 },
+            ]
+        );
+    }
+}
@@ -1,15 +1,96 @@
+mod backend;
+mod cli;
 mod config;
+mod daemon;
 mod device;
+mod focus;
 mod gui;
+mod headless;
 mod humanize;
+mod inspect;
+mod ipc;
+mod macros;
+mod profile;
 mod proxy;
+mod sched;
+mod session;
+mod stats;
 
+use cli::Command;
 use eframe::egui;
 use gui::FerrisFireApp;
 
 fn main() -> eframe::Result<()> {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
+    let options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    match options.command {
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+        Command::Headless => {
+            log::info!("FerrisFire starting in headless mode...");
+            let config = profile::load_config(options.config_path.as_deref(), options.profile.as_deref());
+            let result = config.and_then(|config| {
+                headless::run(config, options.stats_path.as_deref(), options.session_path.as_deref())
+            });
+            if let Err(e) = result {
+                log::error!("{}", e);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Replay { path, virtual_device } => {
+            if let Err(e) = session::run_replay(&path, virtual_device) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Daemon => {
+            log::info!("FerrisFire starting in daemon mode...");
+            let config = profile::load_config(options.config_path.as_deref(), options.profile.as_deref());
+            let socket_path = options.socket_path.unwrap_or_else(ipc::default_socket_path);
+            let result = config.and_then(|config| {
+                let daemon = daemon::Daemon::new(config, options.config_path, options.profile);
+                daemon::run(daemon, &socket_path)
+            });
+            if let Err(e) = result {
+                log::error!("{}", e);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Ctl(request) => {
+            let socket_path = options.socket_path.unwrap_or_else(ipc::default_socket_path);
+            match ipc::send_request(&socket_path, &request) {
+                Ok(response) => {
+                    let failed = matches!(response, ipc::Response::Error { .. });
+                    println!("{}", serde_json::to_string_pretty(&response).unwrap_or_default());
+                    if failed {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+            Ok(())
+        }
+        Command::Gui => run_gui(options.profile),
+    }
+}
+
+fn run_gui(profile: Option<String>) -> eframe::Result<()> {
     log::info!("FerrisFire starting...");
 
     let icon = load_icon();
@@ -26,7 +107,7 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "FerrisFire",
         options,
-        Box::new(|cc| Ok(Box::new(FerrisFireApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(FerrisFireApp::new(cc, profile)))),
     )
 }
 
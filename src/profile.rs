@@ -0,0 +1,343 @@
+//! Named profiles stored side by side in ~/.config/ferrisfire/config.json.
+//!
+//! Older versions saved a single flat `Config` to the same file; such files are
+//! read as a store containing one "default" profile and rewritten on the next save.
+
+use crate::config::Config;
+use crate::focus::AutoSwitchSettings;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileStore {
+    /// Name of the profile used when none is requested explicitly
+    pub active: String,
+    pub profiles: BTreeMap<String, Config>,
+    /// Rules for picking a profile from the focused application
+    #[serde(default)]
+    pub auto_switch: AutoSwitchSettings,
+}
+
+/// On-disk layouts we accept, newest first
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredProfiles {
+    Store(ProfileStore),
+    Legacy(Box<Config>),
+}
+
+impl Default for ProfileStore {
+    fn default() -> Self {
+        Self::from_config(Config::default())
+    }
+}
+
+impl ProfileStore {
+    /// A store holding `config` as its only, active profile
+    pub fn from_config(config: Config) -> Self {
+        let mut profiles = BTreeMap::new();
+        profiles.insert(DEFAULT_PROFILE.to_string(), config);
+        Self {
+            active: DEFAULT_PROFILE.to_string(),
+            profiles,
+            auto_switch: AutoSwitchSettings::default(),
+        }
+    }
+
+    fn store_path() -> PathBuf {
+        let mut path = dirs_next::config_dir().unwrap_or_else(|| PathBuf::from("."));
+        path.push("ferrisfire");
+        fs::create_dir_all(&path).ok();
+        path.push("config.json");
+        path
+    }
+
+    fn parse(contents: &str) -> Result<Self, serde_json::Error> {
+        let mut store = match serde_json::from_str(contents)? {
+            StoredProfiles::Store(store) => store,
+            StoredProfiles::Legacy(config) => {
+                log::info!("Migrating single config into profile '{}'", DEFAULT_PROFILE);
+                Self::from_config(*config)
+            }
+        };
+        if store.profiles.is_empty() {
+            store = Self::default();
+        }
+        if !store.profiles.contains_key(&store.active) {
+            store.active = store.profiles.keys().next().cloned().unwrap_or_default();
+        }
+        Ok(store)
+    }
+
+    /// Load the saved profiles, falling back to a single default profile
+    pub fn load() -> Self {
+        Self::load_or_default(&Self::store_path())
+    }
+
+    fn load_or_default(path: &Path) -> Self {
+        if !path.exists() {
+            return Self::default();
+        }
+        Self::load_from(path).unwrap_or_else(|e| {
+            log::error!("{}; starting from default settings, the file is kept until the next save", e);
+            Self::default()
+        })
+    }
+
+    /// Load profiles (or a single legacy config) from an explicit path
+    pub fn load_from(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::parse(&contents).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self) {
+        self.save_to(&Self::store_path());
+    }
+
+    /// Write the store to `path`. A file there that cannot be read as a store
+    /// is moved to `<path>.bak` first rather than overwritten, so profiles lost
+    /// to a bad edit can still be recovered by hand.
+    fn save_to(&self, path: &Path) {
+        if path.exists()
+            && let Err(e) = Self::load_from(path)
+        {
+            let backup = path.with_extension("json.bak");
+            match fs::rename(path, &backup) {
+                Ok(()) => log::warn!("{}; moved it to {} before saving", e, backup.display()),
+                Err(rename_error) => {
+                    log::error!("{}; not saving over it ({})", e, rename_error);
+                    return;
+                }
+            }
+        }
+        match serde_json::to_string_pretty(self) {
+            Ok(json) => {
+                if let Err(e) = fs::write(path, json) {
+                    log::error!("Failed to write {}: {}", path.display(), e);
+                }
+            }
+            Err(e) => log::error!("Failed to encode profiles: {}", e),
+        }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Config> {
+        self.profiles.get(name)
+    }
+
+    pub fn active_config(&self) -> Config {
+        self.profiles.get(&self.active).cloned().unwrap_or_default()
+    }
+
+    /// Pick a profile by name, or the active one when no name is given
+    pub fn select(&self, name: Option<&str>) -> Result<Config, String> {
+        match name {
+            Some(name) => self
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("Unknown profile: {}", name)),
+            None => Ok(self.active_config()),
+        }
+    }
+
+    pub fn set_active(&mut self, name: &str) -> Result<(), String> {
+        if !self.profiles.contains_key(name) {
+            return Err(format!("Unknown profile: {}", name));
+        }
+        self.active = name.to_string();
+        Ok(())
+    }
+
+    /// Insert or replace a profile
+    pub fn insert(&mut self, name: &str, config: Config) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Profile name cannot be empty".to_string());
+        }
+        self.profiles.insert(name.to_string(), config);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), String> {
+        if self.profiles.len() <= 1 {
+            return Err("Cannot delete the last profile".to_string());
+        }
+        if self.profiles.remove(name).is_none() {
+            return Err(format!("Unknown profile: {}", name));
+        }
+        if self.active == name {
+            self.active = self.profiles.keys().next().cloned().unwrap_or_default();
+        }
+        Ok(())
+    }
+
+    /// `base`, or `base (2)`, `base (3)`... if that name is taken
+    fn unique_name(&self, base: &str) -> String {
+        if !self.profiles.contains_key(base) {
+            return base.to_string();
+        }
+        (2..)
+            .map(|n| format!("{} ({})", base, n))
+            .find(|name| !self.profiles.contains_key(name))
+            .unwrap_or_default()
+    }
+
+    /// Write one profile to `path` as a standalone config file
+    pub fn export_profile(&self, name: &str, path: &Path) -> Result<(), String> {
+        let config = self.get(name).ok_or_else(|| format!("Unknown profile: {}", name))?;
+        config.save_to(path)
+    }
+
+    /// Add a standalone config file as a new profile named after the file.
+    /// Returns the name it was stored under.
+    pub fn import_profile(&mut self, path: &Path) -> Result<String, String> {
+        let config = Config::load_from(path)?;
+        let base = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .filter(|s| !s.is_empty())
+            .unwrap_or("imported");
+        let name = self.unique_name(base);
+        self.insert(&name, config)?;
+        Ok(name)
+    }
+}
+
+/// Load the profile store at `path`, or the saved one
+pub fn load_store(path: Option<&Path>) -> Result<ProfileStore, String> {
+    match path {
+        Some(path) => ProfileStore::load_from(path),
+        None => Ok(ProfileStore::load()),
+    }
+}
+
+/// Load the profile store at `path` (or the saved one) and pick `profile` (or the active one)
+pub fn load_config(path: Option<&Path>, profile: Option<&str>) -> Result<Config, String> {
+    load_store(path)?.select(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ferrisfire-{}-{}.json", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_legacy_config_migrates_to_default_profile() {
+        let legacy = r#"{
+            "device_path": "/dev/input/event5",
+            "trigger_button": "Mouse5",
+            "click_delay_min_ms": 30,
+            "click_delay_max_ms": 60,
+            "travel_time_min_ms": 15,
+            "travel_time_max_ms": 30
+        }"#;
+        let store = ProfileStore::parse(legacy).unwrap();
+        assert_eq!(store.active, DEFAULT_PROFILE);
+        assert_eq!(store.profiles.len(), 1);
+        assert_eq!(store.active_config().device_path, "/dev/input/event5");
+        assert_eq!(store.active_config().click_delay_min_ms, 30);
+    }
+
+    #[test]
+    fn test_corrupt_store_is_kept_before_saving() {
+        let path = temp_path("corrupt-store");
+        let backup = path.with_extension("json.bak");
+        let corrupt = r#"{"active":"apex","profiles":{"apex":{"click_delay_min_ms":"fast"}}}"#;
+        fs::write(&path, corrupt).unwrap();
+
+        let store = ProfileStore::load_or_default(&path);
+        assert_eq!(store, ProfileStore::default());
+        store.save_to(&path);
+        let saved = ProfileStore::load_from(&path);
+        let backed_up = fs::read_to_string(&backup);
+        fs::remove_file(&path).ok();
+        fs::remove_file(&backup).ok();
+
+        assert_eq!(saved.unwrap(), store);
+        assert_eq!(backed_up.unwrap(), corrupt);
+    }
+
+    #[test]
+    fn test_store_roundtrip() {
+        let mut store = ProfileStore::default();
+        let fast = Config {
+            click_delay_min_ms: 20,
+            click_delay_max_ms: 30,
+            ..Default::default()
+        };
+        store.insert("fast", fast.clone()).unwrap();
+        store.set_active("fast").unwrap();
+
+        let json = serde_json::to_string(&store).unwrap();
+        let parsed = ProfileStore::parse(&json).unwrap();
+        assert_eq!(parsed, store);
+        assert_eq!(parsed.active_config(), fast);
+    }
+
+    #[test]
+    fn test_dangling_active_profile_is_repaired() {
+        let json = r#"{"active": "gone", "profiles": {"a": {
+            "device_path": "", "trigger_button": "Mouse4",
+            "click_delay_min_ms": 45, "click_delay_max_ms": 80,
+            "travel_time_min_ms": 10, "travel_time_max_ms": 25
+        }}}"#;
+        let store = ProfileStore::parse(json).unwrap();
+        assert_eq!(store.active, "a");
+    }
+
+    #[test]
+    fn test_select_profile() {
+        let store = ProfileStore::default();
+        assert!(store.select(None).is_ok());
+        assert!(store.select(Some(DEFAULT_PROFILE)).is_ok());
+        assert_eq!(store.select(Some("nope")).unwrap_err(), "Unknown profile: nope");
+    }
+
+    #[test]
+    fn test_remove_profile() {
+        let mut store = ProfileStore::default();
+        assert_eq!(store.remove(DEFAULT_PROFILE).unwrap_err(), "Cannot delete the last profile");
+
+        store.insert("other", Config::default()).unwrap();
+        store.remove(DEFAULT_PROFILE).unwrap();
+        assert_eq!(store.active, "other");
+    }
+
+    #[test]
+    fn test_insert_rejects_empty_name() {
+        let mut store = ProfileStore::default();
+        assert!(store.insert("  ", Config::default()).is_err());
+    }
+
+    #[test]
+    fn test_export_import_roundtrip() {
+        let path = temp_path("apex");
+        let mut store = ProfileStore::default();
+        let config = Config {
+            click_delay_min_ms: 55,
+            ..Default::default()
+        };
+        store.insert("apex", config.clone()).unwrap();
+        store.export_profile("apex", &path).unwrap();
+
+        let name = store.import_profile(&path);
+        let second = store.import_profile(&path);
+        fs::remove_file(&path).ok();
+
+        let name = name.unwrap();
+        assert!(name.starts_with("ferrisfire-apex"));
+        assert_eq!(store.get(&name), Some(&config));
+        assert_eq!(second.unwrap(), format!("{} (2)", name));
+    }
+}
@@ -1,258 +1,2073 @@
-use crate::config::Config;
-use crate::device::{create_virtual_clone, open_device};
-use crate::humanize::{
-    random_click_interval, gaussian_click_interval,
-    random_travel_time, gaussian_travel_time,
-    FatigueTracker, BurstTracker,
+use crate::backend::{Clock, EventSink, EventSource, EvdevSource, Origin, SystemClock};
+use crate::config::{ActivationMode, Binding, Config};
+use crate::device::{
+    create_virtual_clone, create_virtual_key_clone, find_devices, open_device, resolve_config_devices, DeviceIdentity,
+    InputDirWatcher,
 };
-use evdev::{EventType, InputEvent, KeyCode, SynchronizationCode};
+use crate::humanize::{click_interval, travel_time, seeded_rng, FatigueTracker, BurstTracker};
+use crate::macros::MacroStep;
+use crate::sched;
+use crate::session::{SessionWriter, Stream};
+use crate::stats::{ProxyStats, SharedStats};
+use evdev::uinput::VirtualDevice;
+use evdev::{Device, EventType, InputEvent, KeyCode, SynchronizationCode};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
 use std::os::fd::AsRawFd;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
 use std::sync::Arc;
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
-pub fn spawn_proxy(config: Config, stop_signal: Arc<AtomicBool>) -> thread::JoinHandle<Result<(), String>> {
-    thread::spawn(move || run_proxy_loop(config, stop_signal))
+/// Spawn the proxy thread. Configs sent through `updates` are applied live,
+/// without ungrabbing the device or recreating the virtual mouse, and a
+/// snapshot of the run's statistics is kept up to date in `stats`. With
+/// `session_path` the whole session is recorded there for `replay`.
+///
+/// Waits for the thread to apply its real-time tuning and also returns a
+/// message describing any tuning that could not be applied; the proxy runs
+/// either way.
+pub fn spawn_proxy(
+    config: Config,
+    stop_signal: Arc<AtomicBool>,
+    updates: Receiver<Config>,
+    stats: SharedStats,
+    session_path: Option<PathBuf>,
+) -> (thread::JoinHandle<Result<(), String>>, Option<String>) {
+    let (tuned_tx, tuned) = mpsc::sync_channel(1);
+    let handle = thread::spawn(move || {
+        let warning = sched::tune_current_thread(&config);
+        if let Some(warning) = &warning {
+            log::warn!("{}", warning);
+        }
+        tuned_tx.send(warning).ok();
+        let control = Control {
+            stop: stop_signal,
+            updates,
+            stats,
+            session_path,
+        };
+        run_proxy_loop(config, &control)
+    });
+    let warning = tuned.recv().unwrap_or(None);
+    (handle, warning)
 }
 
-fn get_click_interval(config: &Config) -> Duration {
-    if config.use_gaussian {
-        gaussian_click_interval(config.click_delay_min_ms, config.click_delay_max_ms)
-    } else {
-        random_click_interval(config.click_delay_min_ms, config.click_delay_max_ms)
-    }
+fn get_click_interval(binding: &Binding, rng: &mut StdRng) -> Duration {
+    click_interval(binding.click_timing(), rng, binding.click_delay_min_ms, binding.click_delay_max_ms)
 }
 
-fn get_travel_time(config: &Config) -> Duration {
-    if config.use_gaussian {
-        gaussian_travel_time(config.travel_time_min_ms, config.travel_time_max_ms, config.travel_jitter)
-    } else {
-        random_travel_time(config.travel_time_min_ms, config.travel_time_max_ms, config.travel_jitter)
-    }
+fn get_travel_time(binding: &Binding, rng: &mut StdRng) -> Duration {
+    travel_time(
+        binding.travel_timing(),
+        rng,
+        binding.travel_time_min_ms,
+        binding.travel_time_max_ms,
+        binding.travel_jitter,
+    )
 }
 
-fn run_proxy_loop(config: Config, stop: Arc<AtomicBool>) -> Result<(), String> {
-    let mut physical = open_device(&config.device_path)
-        .map_err(|e| format!("Failed to open device: {}", e))?;
+/// One run through a binding's macro. Click timing is drawn up front from
+/// the binding's settings, so the whole run is a fixed timeline of events.
+struct MacroPlayer {
+    started: Instant,
+    /// Offset from the start and the event, for every key edge and motion, in order
+    events: Vec<(Duration, InputEvent)>,
+    /// Events emitted so far
+    played: usize,
+    /// Offset at which the run is over, including trailing waits
+    length: Duration,
+    /// Keys the macro pressed and has not released yet
+    held: Vec<KeyCode>,
+}
 
-    // Set non-blocking mode so we can check the stop signal
-    let fd = physical.as_raw_fd();
-    unsafe {
-        let flags = libc::fcntl(fd, libc::F_GETFL);
-        libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+impl MacroPlayer {
+    fn new(binding: &Binding, now: Instant, rng: &mut StdRng) -> Self {
+        let key = |key: u16, value: i32| InputEvent::new(EventType::KEY.0, key, value);
+        let mut events = Vec::new();
+        let mut offset = Duration::ZERO;
+        // Back-to-back clicks are spaced by the click delay, like rapid-fire
+        let mut after_click = false;
+        for step in &binding.macro_steps {
+            match *step {
+                MacroStep::Press { key: code } => events.push((offset, key(code, 1))),
+                MacroStep::Release { key: code } => events.push((offset, key(code, 0))),
+                MacroStep::Click { key: code, count } => {
+                    for _ in 0..count {
+                        if after_click {
+                            offset += get_click_interval(binding, rng);
+                        }
+                        events.push((offset, key(code, 1)));
+                        offset += get_travel_time(binding, rng);
+                        events.push((offset, key(code, 0)));
+                        after_click = true;
+                    }
+                    continue;
+                }
+                MacroStep::Wait { ms } => offset += Duration::from_millis(ms),
+                MacroStep::Move { axis, value } => {
+                    events.push((offset, InputEvent::new(EventType::RELATIVE.0, axis, value)));
+                }
+            }
+            after_click = false;
+        }
+        Self {
+            started: now,
+            events,
+            played: 0,
+            length: offset,
+            held: Vec::new(),
+        }
     }
 
-    physical.grab().map_err(|e| format!("Failed to grab device: {}", e))?;
+    /// Emit the events due by `now`, one report per instant. Returns whether
+    /// the run is over, releasing any key the macro left pressed.
+    fn advance(&mut self, now: Instant, sink: &mut impl EventSink) -> bool {
+        let elapsed = now.saturating_duration_since(self.started);
+        while let Some(&(offset, _)) = self.events.get(self.played)
+            && offset <= elapsed
+        {
+            let mut report = Vec::new();
+            while let Some(&(at, event)) = self.events.get(self.played)
+                && at == offset
+            {
+                if event.event_type() == EventType::KEY {
+                    let key = KeyCode(event.code());
+                    self.held.retain(|held| *held != key);
+                    if event.value() != 0 {
+                        self.held.push(key);
+                    }
+                }
+                report.push(event);
+                self.played += 1;
+            }
+            report.push(InputEvent::new(EventType::SYNCHRONIZATION.0, SynchronizationCode::SYN_REPORT.0, 0));
+            if let Err(e) = sink.emit(&report) {
+                log::warn!("Failed to emit macro step: {}", e);
+            }
+        }
+        let finished = self.played == self.events.len() && elapsed >= self.length;
+        if finished {
+            self.release_held(sink);
+        }
+        finished
+    }
+
+    /// When `advance` next has something to do
+    fn next_deadline(&self) -> Instant {
+        let offset = self.events.get(self.played).map_or(self.length, |(at, _)| *at);
+        self.started + offset
+    }
+
+    /// Release every key the macro still holds
+    fn release_held(&mut self, sink: &mut impl EventSink) {
+        if !self.held.is_empty() {
+            emit_button_up(sink, &std::mem::take(&mut self.held));
+        }
+    }
+}
 
-    let mut virtual_dev = create_virtual_clone(&physical)
-        .map_err(|e| format!("Failed to create virtual device: {}", e))?;
+/// Trigger and click timing state of one binding
+struct BindingState {
+    binding: Binding,
+    trigger_key: KeyCode,
+    trigger_held: bool,
+    /// Keys that must be held for the trigger to fire
+    chord: Vec<KeyCode>,
+    chord_held: bool,
+    /// Toggle mode: firing until the trigger is pressed again
+    latched: bool,
+    /// Fixed-clicks mode: presses still to fire for the last trigger press
+    clicks_left: u64,
+    /// Keys pressed and released together for each virtual click
+    output_keys: Vec<KeyCode>,
 
-    let trigger_key = config.effective_trigger_code();
-    let mut trigger_held = false;
-    
-    // Smart ADS state (RMB + LMB mode)
-    let mut rmb_held = false;
-    let mut lmb_held = false;
-    
     // Click timing state
-    let mut last_click_complete = Instant::now();
-    let mut next_interval = get_click_interval(&config);
-    let mut button_down_since: Option<Instant> = None;
-    let mut current_travel = get_travel_time(&config);
-    
+    last_click_complete: Instant,
+    next_interval: Duration,
+    button_down_since: Option<Instant>,
+    current_travel: Duration,
+
     // Humanization trackers
-    let mut fatigue_tracker = FatigueTracker::new(config.fatigue_max_percent);
-    let mut burst_tracker = BurstTracker::new(config.burst_count, config.burst_pause_ms);
-    let mut burst_pause_start: Option<Instant> = None;
-    let mut current_burst_pause = Duration::ZERO;
+    fatigue_tracker: FatigueTracker,
+    burst_tracker: BurstTracker,
+    burst_pause_start: Option<Instant>,
+    current_burst_pause: Duration,
 
-    log::info!("Proxy started for device: {}", config.device_path);
-    if config.smart_ads_trigger {
-        log::info!("Smart ADS trigger enabled (RMB + LMB)");
-    } else {
-        log::info!("Trigger key: {:?} (code {})", trigger_key, trigger_key.0);
+    /// Last virtual press of the current firing streak, for interval stats
+    last_press: Option<Instant>,
+
+    /// Run of the macro in progress, for macro bindings
+    macro_player: Option<MacroPlayer>,
+    /// The macro already ran for this activation and does not repeat
+    macro_done: bool,
+}
+
+impl BindingState {
+    fn new(binding: Binding, now: Instant, rng: &mut StdRng) -> Self {
+        Self {
+            trigger_key: binding.effective_trigger_code(),
+            trigger_held: false,
+            chord: binding.chord_key_codes(),
+            chord_held: binding.chord.is_empty(),
+            latched: false,
+            clicks_left: 0,
+            output_keys: binding.output_key_codes(),
+            last_click_complete: now,
+            next_interval: get_click_interval(&binding, rng),
+            button_down_since: None,
+            current_travel: get_travel_time(&binding, rng),
+            fatigue_tracker: FatigueTracker::new(binding.fatigue_max_percent),
+            burst_tracker: BurstTracker::new(binding.burst_count, binding.burst_pause_ms),
+            burst_pause_start: None,
+            current_burst_pause: Duration::ZERO,
+            last_press: None,
+            macro_player: None,
+            macro_done: false,
+            binding,
+        }
     }
 
-    while !stop.load(Ordering::Relaxed) {
-        // Process input events
-        match physical.fetch_events() {
-            Ok(events) => {
-                for event in events {
-                    if event.event_type() == EventType::KEY {
-                        let key_code = KeyCode(event.code());
-                        
-                        // Smart ADS mode: RMB + LMB triggers rapid-fire
-                        if config.smart_ads_trigger {
-                            let is_rmb = key_code == KeyCode::BTN_RIGHT;
-                            let is_lmb = key_code == KeyCode::BTN_LEFT;
-                            
-                            if is_rmb {
-                                let was_held = rmb_held;
-                                rmb_held = event.value() == 1;
-                                
-                                // On RMB release while rapid-firing, clean up
-                                if was_held && !rmb_held && lmb_held {
-                                    if button_down_since.is_some() {
-                                        emit_button_up(&mut virtual_dev);
-                                        button_down_since = None;
-                                    }
-                                    fatigue_tracker.reset();
-                                    burst_tracker.reset();
-                                    burst_pause_start = None;
-                                }
-                                // Pass through RMB events
-                                if let Err(e) = virtual_dev.emit(&[event]) {
-                                    log::warn!("Failed to emit event: {}", e);
-                                }
-                                continue;
-                            }
-                            
-                            if is_lmb {
-                                let was_held = lmb_held;
-                                lmb_held = event.value() == 1;
-                                
-                                // If RMB is held, we handle LMB for rapid-fire
-                                if rmb_held {
-                                    // On LMB release while rapid-firing, clean up
-                                    if was_held && !lmb_held {
-                                        if button_down_since.is_some() {
-                                            emit_button_up(&mut virtual_dev);
-                                            button_down_since = None;
-                                        }
-                                        fatigue_tracker.reset();
-                                        burst_tracker.reset();
-                                        burst_pause_start = None;
-                                    }
-                                    // Don't pass through LMB when rapid-firing
-                                    continue;
-                                }
-                                // RMB not held: pass through LMB normally
-                                if let Err(e) = virtual_dev.emit(&[event]) {
-                                    log::warn!("Failed to emit event: {}", e);
-                                }
-                                continue;
-                            }
-                        } else {
-                            // Standard trigger mode
-                            if key_code == trigger_key {
-                                let was_held = trigger_held;
-                                trigger_held = event.value() == 1;
-                                
-                                // On trigger release, release any held click and reset trackers
-                                if was_held && !trigger_held {
-                                    if button_down_since.is_some() {
-                                        emit_button_up(&mut virtual_dev);
-                                        button_down_since = None;
-                                    }
-                                    fatigue_tracker.reset();
-                                    burst_tracker.reset();
-                                    burst_pause_start = None;
-                                }
-                                continue;
-                            }
-                        }
-                    }
-                    
-                    if let Err(e) = virtual_dev.emit(&[event]) {
-                        log::warn!("Failed to emit event: {}", e);
+    /// Whether the trigger currently asks for rapid-fire
+    fn firing(&self) -> bool {
+        match self.binding.activation {
+            ActivationMode::Hold => self.trigger_held && self.chord_held,
+            ActivationMode::Toggle => self.latched,
+            ActivationMode::Clicks => self.clicks_left > 0,
+        }
+    }
+
+    /// Track a trigger press or release and start or stop firing as the activation mode says
+    fn trigger(&mut self, pressed: bool, sink: &mut impl EventSink) {
+        let was_held = self.trigger_held;
+        self.trigger_held = pressed;
+        let press = pressed && !was_held;
+
+        match self.binding.activation {
+            ActivationMode::Hold => {
+                // On trigger release, release any held click and reset trackers
+                if was_held && !pressed {
+                    self.stop_firing(sink);
+                }
+            }
+            ActivationMode::Toggle => {
+                if press {
+                    self.latched = !self.latched;
+                    if !self.latched {
+                        self.stop_firing(sink);
                     }
                 }
             }
-            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
-            Err(e) => {
-                log::error!("Error reading events: {}", e);
-                break;
+            ActivationMode::Clicks => {
+                if press {
+                    // A press while still counting restarts the count
+                    if self.clicks_left == 0 {
+                        self.reset_streak();
+                    }
+                    self.clicks_left = self.binding.activation_clicks;
+                }
             }
         }
+    }
+
+    /// Track whether the chord is held; letting go of it stops hold-to-fire
+    fn set_chord_held(&mut self, held: bool, sink: &mut impl EventSink) {
+        if self.chord_held && !held && self.binding.activation == ActivationMode::Hold && self.trigger_held {
+            self.stop_firing(sink);
+        }
+        self.chord_held = held;
+    }
+
+    /// Forget any latch or pending clicks, releasing what is in progress
+    fn deactivate(&mut self, sink: &mut impl EventSink) {
+        self.stop_firing(sink);
+        self.trigger_held = false;
+        self.latched = false;
+        self.clicks_left = 0;
+    }
+
+    /// Release any click or macro in progress and reset the humanization trackers
+    fn stop_firing(&mut self, sink: &mut impl EventSink) {
+        if self.button_down_since.take().is_some() {
+            emit_button_up(sink, &self.output_keys);
+        }
+        if let Some(mut player) = self.macro_player.take() {
+            player.release_held(sink);
+        }
+        self.reset_streak();
+    }
+
+    /// Start the humanization trackers and interval stats over for a new firing streak
+    fn reset_streak(&mut self) {
+        self.fatigue_tracker.reset();
+        self.burst_tracker.reset();
+        self.burst_pause_start = None;
+        self.last_press = None;
+        self.macro_done = false;
+    }
+
+    /// Switch to new settings, keeping timing state that still applies
+    fn update(&mut self, update: Binding, sink: &mut impl EventSink, rng: &mut StdRng) {
+        if update.effective_trigger_code() != self.trigger_key
+            || update.chord != self.binding.chord
+            || update.activation != self.binding.activation
+        {
+            // Stop any rapid-fire driven by the old trigger or mode
+            self.deactivate(sink);
+            self.trigger_key = update.effective_trigger_code();
+            self.chord = update.chord_key_codes();
+        }
+        if update.output_keys != self.binding.output_keys || update.macro_steps != self.binding.macro_steps {
+            // Release the old outputs before switching
+            self.stop_firing(sink);
+            self.output_keys = update.output_key_codes();
+        }
+        if update.fatigue_max_percent != self.binding.fatigue_max_percent {
+            self.fatigue_tracker = FatigueTracker::new(update.fatigue_max_percent);
+        }
+        if update.burst_count != self.binding.burst_count || update.burst_pause_ms != self.binding.burst_pause_ms {
+            self.burst_tracker = BurstTracker::new(update.burst_count, update.burst_pause_ms);
+            self.burst_pause_start = None;
+        }
+
+        self.binding = update;
+        self.next_interval = get_click_interval(&self.binding, rng);
+    }
+
+    /// Advance click timing to `now`, emitting any press or release that is due
+    fn tick(
+        &mut self,
+        active: bool,
+        now: Instant,
+        sink: &mut impl EventSink,
+        rng: &mut StdRng,
+        stats: &mut ProxyStats,
+    ) {
+        if self.binding.is_macro() {
+            self.tick_macro(active, now, sink, rng);
+            return;
+        }
 
         // Handle burst pause
-        if config.burst_mode {
-            if let Some(pause_start) = burst_pause_start {
-                if pause_start.elapsed() >= current_burst_pause {
-                    burst_tracker.end_pause();
-                    burst_pause_start = None;
-                    last_click_complete = Instant::now();
-                } else {
-                    // Still in pause, skip click logic
-                    thread::sleep(Duration::from_micros(250));
-                    continue;
-                }
+        if self.binding.burst_mode
+            && let Some(pause_start) = self.burst_pause_start
+        {
+            if now.saturating_duration_since(pause_start) >= self.current_burst_pause {
+                self.burst_tracker.end_pause();
+                self.burst_pause_start = None;
+                self.last_click_complete = now;
+            } else {
+                // Still in pause, skip click logic
+                return;
             }
         }
 
         // Handle click release
-        if let Some(down_time) = button_down_since {
-            if down_time.elapsed() >= current_travel {
-                emit_button_up(&mut virtual_dev);
-                button_down_since = None;
-                last_click_complete = Instant::now();
-                
-                // Record click for trackers
-                if config.simulate_fatigue {
-                    fatigue_tracker.click();
-                }
-                if config.burst_mode && burst_tracker.click() {
-                    // Burst complete, start pause
-                    burst_pause_start = Some(Instant::now());
-                    current_burst_pause = burst_tracker.pause_duration();
-                }
-                
-                // Get next interval with optional fatigue
-                next_interval = get_click_interval(&config);
-                if config.simulate_fatigue {
-                    next_interval = fatigue_tracker.apply(next_interval);
-                }
+        if let Some(down_time) = self.button_down_since
+            && now.saturating_duration_since(down_time) >= self.current_travel
+        {
+            emit_button_up(sink, &self.output_keys);
+            self.button_down_since = None;
+            self.last_click_complete = now;
+            stats.clicks += 1;
+            stats.travel_time.record(now.saturating_duration_since(down_time));
+
+            // Record click for trackers
+            if self.binding.simulate_fatigue {
+                self.fatigue_tracker.click();
+            }
+            if self.binding.burst_mode && self.burst_tracker.click() {
+                // Burst complete, start pause
+                self.burst_pause_start = Some(now);
+                self.current_burst_pause = self.burst_tracker.pause_duration(rng);
+            }
+
+            // Get next interval with optional fatigue
+            self.next_interval = get_click_interval(&self.binding, rng);
+            if self.binding.simulate_fatigue {
+                self.next_interval = self.fatigue_tracker.apply(self.next_interval);
             }
         }
 
-        // Determine if we should be rapid-firing
-        let rapid_fire_active = if config.smart_ads_trigger {
-            rmb_held && lmb_held
-        } else {
-            trigger_held
-        };
-        
         // Start new click if trigger held and ready
-        let should_click = rapid_fire_active 
-            && button_down_since.is_none() 
-            && last_click_complete.elapsed() >= next_interval
-            && (!config.burst_mode || !burst_tracker.should_pause());
-            
+        let should_click = active
+            && self.button_down_since.is_none()
+            && now.saturating_duration_since(self.last_click_complete) >= self.next_interval
+            && (!self.binding.burst_mode || !self.burst_tracker.should_pause());
+
         if should_click {
-            emit_button_down(&mut virtual_dev);
-            button_down_since = Some(Instant::now());
-            current_travel = get_travel_time(&config);
+            emit_button_down(sink, &self.output_keys);
+            if self.binding.activation == ActivationMode::Clicks {
+                self.clicks_left = self.clicks_left.saturating_sub(1);
+            }
+            if let Some(last_press) = self.last_press {
+                stats.click_interval.record(now.saturating_duration_since(last_press));
+            }
+            self.last_press = Some(now);
+            self.button_down_since = Some(now);
+            self.current_travel = get_travel_time(&self.binding, rng);
+        }
+    }
+
+    /// Start, advance or cancel the macro. Fixed-clicks mode plays it once
+    /// per click; a repeating macro waits a click delay between runs.
+    fn tick_macro(&mut self, active: bool, now: Instant, sink: &mut impl EventSink, rng: &mut StdRng) {
+        if !active {
+            if let Some(mut player) = self.macro_player.take() {
+                player.release_held(sink);
+            }
+            self.macro_done = false;
+            return;
+        }
+
+        if self.macro_player.is_none()
+            && !self.macro_done
+            && now.saturating_duration_since(self.last_click_complete) >= self.next_interval
+        {
+            self.macro_player = Some(MacroPlayer::new(&self.binding, now, rng));
+        }
+        if let Some(player) = &mut self.macro_player
+            && player.advance(now, sink)
+        {
+            self.macro_player = None;
+            self.last_click_complete = now;
+            self.next_interval = get_click_interval(&self.binding, rng);
+            match self.binding.activation {
+                ActivationMode::Clicks => self.clicks_left = self.clicks_left.saturating_sub(1),
+                _ => self.macro_done = !self.binding.macro_repeat,
+            }
+        }
+    }
+
+    /// When `tick` next has something to do, or `None` while idle
+    fn next_deadline(&self, active: bool) -> Option<Instant> {
+        if self.binding.is_macro() {
+            return match &self.macro_player {
+                Some(player) => Some(player.next_deadline()),
+                None if active && !self.macro_done => Some(self.last_click_complete + self.next_interval),
+                None => None,
+            };
+        }
+        if self.binding.burst_mode
+            && let Some(pause_start) = self.burst_pause_start
+        {
+            return Some(pause_start + self.current_burst_pause);
+        }
+        if let Some(down_time) = self.button_down_since {
+            return Some(down_time + self.current_travel);
+        }
+        if active {
+            return Some(self.last_click_complete + self.next_interval);
+        }
+        None
+    }
+}
+
+/// Trigger handling and click timing, independent of where events come from
+/// or go to. Time is always passed in, so the same logic runs against a real
+/// device or a scripted test clock.
+pub struct ProxyEngine {
+    config: Config,
+    /// One per configured binding, the primary binding first
+    bindings: Vec<BindingState>,
+
+    /// Physical keys and buttons currently down, for chord matching
+    held_keys: Vec<KeyCode>,
+    /// Code each forwarded key went out as while it is down, so its release
+    /// matches its press even when the remapping changes in between
+    forwarded_as: HashMap<u16, u16>,
+
+    /// Source of all timing randomness, reproducible from `seed`
+    rng: StdRng,
+    seed: u64,
+
+    stats: ProxyStats,
+}
+
+impl ProxyEngine {
+    pub fn new(config: Config, now: Instant) -> Self {
+        let (mut rng, seed) = seeded_rng(config.seed);
+        let bindings = config
+            .bindings()
+            .into_iter()
+            .map(|binding| BindingState::new(binding, now, &mut rng))
+            .collect();
+        Self {
+            config,
+            bindings,
+            held_keys: Vec::new(),
+            forwarded_as: HashMap::new(),
+            rng,
+            seed,
+            stats: ProxyStats::default(),
+        }
+    }
+
+    /// Seed of the timing RNG; set it as `seed` in the config to replay the same timing
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The config currently applied, including live updates
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Counters and timing histograms for this run so far
+    pub fn stats(&self) -> &ProxyStats {
+        &self.stats
+    }
+
+    /// Record that a physical event stamped `timestamp` by the kernel was forwarded at `now`
+    pub fn record_forward(&mut self, timestamp: SystemTime, now: SystemTime) {
+        self.stats.forwarded_events += 1;
+        // A clock step can put the timestamp in the future; count that as no delay
+        self.stats
+            .forward_latency
+            .record(now.duration_since(timestamp).unwrap_or(Duration::ZERO));
+    }
+
+    /// Apply a live config update; the device itself cannot change while running
+    pub fn apply_config(&mut self, mut update: Config, now: Instant, sink: &mut impl EventSink) {
+        if update.device_path != self.config.device_path {
+            log::warn!(
+                "Switching device to {} requires a restart, keeping {}",
+                update.device_path, self.config.device_path
+            );
+            update.device_path = self.config.device_path.clone();
+        }
+        if update.extra_device_paths != self.config.extra_device_paths {
+            log::warn!("Changing the merged event nodes requires a restart");
+            update.extra_device_paths = self.config.extra_device_paths.clone();
+        }
+        if update.trigger_device_path != self.config.trigger_device_path
+            || update.grab_trigger_device != self.config.grab_trigger_device
+        {
+            log::warn!("Trigger device changes take effect when the proxy restarts");
+            update.trigger_device_path = self.config.trigger_device_path.clone();
+            update.grab_trigger_device = self.config.grab_trigger_device;
+        }
+        if update.realtime_policy != self.config.realtime_policy
+            || update.realtime_priority != self.config.realtime_priority
+            || update.lock_memory != self.config.lock_memory
+            || update.cpu_affinity != self.config.cpu_affinity
+        {
+            log::warn!("Scheduling, memory locking and CPU affinity changes take effect when the proxy restarts");
+        }
+        if let Some(seed) = update.seed
+            && update.seed != self.config.seed
+        {
+            self.rng = StdRng::seed_from_u64(seed);
+            self.seed = seed;
+            log::info!("Humanize RNG reseeded with {}", seed);
+        }
+
+        let bindings = update.bindings();
+        for mut removed in self.bindings.drain(bindings.len().min(self.bindings.len())..) {
+            removed.stop_firing(sink);
+        }
+        for (idx, binding) in bindings.into_iter().enumerate() {
+            match self.bindings.get_mut(idx) {
+                Some(state) => state.update(binding, sink, &mut self.rng),
+                None => self.bindings.push(BindingState::new(binding, now, &mut self.rng)),
+            }
+        }
+
+        self.refresh_chords(sink);
+
+        self.config = update;
+        log::info!("Applied live config update");
+    }
+
+    /// Process one physical event: update trigger state and forward or swallow it.
+    /// Returns whether the event was forwarded.
+    pub fn handle_event(&mut self, event: InputEvent, sink: &mut impl EventSink) -> bool {
+        let pass = self.route(event, sink);
+        if pass {
+            forward(sink, self.remap(event));
+        }
+        pass
+    }
+
+    /// The event as the virtual device should see it after the configured remapping.
+    /// Repeats and releases keep the code the press went out as.
+    fn remap(&mut self, event: InputEvent) -> InputEvent {
+        if event.event_type() != EventType::KEY {
+            return event;
+        }
+        let pressed_as = match event.value() {
+            0 => self.forwarded_as.remove(&event.code()),
+            1 => None,
+            _ => self.forwarded_as.get(&event.code()).copied(),
+        };
+        let code = pressed_as.unwrap_or_else(|| self.config.remapped(event.code()));
+        if event.value() == 1 {
+            self.forwarded_as.insert(event.code(), code);
+        }
+        if code == event.code() {
+            event
+        } else {
+            InputEvent::new(EventType::KEY.0, code, event.value())
+        }
+    }
+
+    /// Process one event from the trigger device. Its non-trigger events go to
+    /// `passthrough` when the device is grabbed, and are left alone otherwise
+    /// since other applications already see them. Returns whether the event was forwarded.
+    pub fn handle_trigger_device_event(
+        &mut self,
+        event: InputEvent,
+        sink: &mut impl EventSink,
+        passthrough: Option<&mut impl EventSink>,
+    ) -> bool {
+        let pass = self.route(event, sink);
+        match passthrough {
+            Some(passthrough) if pass => {
+                forward(passthrough, event);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Update trigger state for one physical event; returns whether it should pass through
+    fn route(&mut self, event: InputEvent, sink: &mut impl EventSink) -> bool {
+        if event.event_type() == EventType::KEY {
+            let key_code = KeyCode(event.code());
+
+            // Key repeats (value 2) count as still held
+            let pressed = event.value() != 0;
+            if pressed {
+                if !self.held_keys.contains(&key_code) {
+                    self.held_keys.push(key_code);
+                }
+            } else {
+                self.held_keys.retain(|key| *key != key_code);
+            }
+            self.refresh_chords(sink);
+
+            // A trigger a binding took stays with it until released
+            if let Some(state) = self.bindings.iter_mut().find(|s| s.trigger_key == key_code && s.trigger_held) {
+                state.trigger(pressed, sink);
+                return false;
+            }
+
+            // A fresh press goes to the binding with the longest chord currently held
+            if event.value() == 1
+                && let Some(state) = self
+                    .bindings
+                    .iter_mut()
+                    .filter(|s| s.trigger_key == key_code && s.chord_held)
+                    .max_by_key(|s| s.chord.len())
+            {
+                state.trigger(true, sink);
+                return false;
+            }
+        }
+
+        // Anything else, chord keys included, passes through
+        true
+    }
+
+    /// Recheck which chords are held after the held keys or the bindings changed
+    fn refresh_chords(&mut self, sink: &mut impl EventSink) {
+        for state in &mut self.bindings {
+            let held = state.chord.iter().all(|key| self.held_keys.contains(key));
+            state.set_chord_held(held, sink);
+        }
+    }
+
+    /// Advance click timing to `now`, emitting any press or release that is due
+    pub fn tick(&mut self, now: Instant, sink: &mut impl EventSink) {
+        self.stats.firing.resize(self.bindings.len(), false);
+        for (idx, state) in self.bindings.iter_mut().enumerate() {
+            let active = state.firing();
+            state.tick(active, now, sink, &mut self.rng, &mut self.stats);
+            self.stats.firing[idx] = active;
+        }
+    }
+
+    /// When `tick` next has something to do, or `None` while idle
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.bindings
+            .iter()
+            .filter_map(|state| state.next_deadline(state.firing()))
+            .min()
+    }
+
+    /// Forget all physical input after the device went away, releasing any click in progress
+    pub fn disconnect(&mut self, sink: &mut impl EventSink) {
+        for state in &mut self.bindings {
+            state.deactivate(sink);
         }
+        self.held_keys.clear();
+        self.forwarded_as.clear();
+    }
+
+    pub fn set_waiting_for_device(&mut self, waiting: bool) {
+        self.stats.waiting_for_device = waiting;
+    }
 
-        // Sleep to prevent CPU spinning
-        thread::sleep(Duration::from_micros(250));
+    pub fn record_reconnect(&mut self) {
+        self.stats.reconnects += 1;
     }
 
-    // Clean up: release button if held
-    if button_down_since.is_some() {
-        emit_button_up(&mut virtual_dev);
+    /// Release the virtual buttons of any click or macro in progress
+    pub fn shutdown(&mut self, sink: &mut impl EventSink) {
+        for state in &mut self.bindings {
+            if state.button_down_since.take().is_some() {
+                emit_button_up(sink, &state.output_keys);
+            }
+            if let Some(mut player) = state.macro_player.take() {
+                player.release_held(sink);
+            }
+        }
     }
+}
+
+/// Longest the loop sleeps without input or a click deadline, which bounds how
+/// late the stop signal and live config updates are noticed
+const IDLE_WAKEUP: Duration = Duration::from_millis(50);
+
+/// How often the proxy thread copies its stats out for the GUI or daemon
+const STATS_PUBLISH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// What the owner of the proxy thread uses to stop it, update it and read its stats
+struct Control {
+    stop: Arc<AtomicBool>,
+    updates: Receiver<Config>,
+    stats: SharedStats,
+    /// Where to record the session, if anywhere
+    session_path: Option<PathBuf>,
+}
+
+/// Passes emitted events on to `sink`, copying them into the session recording if there is one
+struct RecordingSink<'a, S> {
+    sink: &'a mut S,
+    session: Option<&'a RefCell<SessionWriter>>,
+    stream: Stream,
+}
 
-    physical.ungrab().ok();
+impl<'a, S: EventSink> RecordingSink<'a, S> {
+    fn new(sink: &'a mut S, session: Option<&'a RefCell<SessionWriter>>, stream: Stream) -> Self {
+        Self { sink, session, stream }
+    }
+}
+
+impl<S: EventSink> EventSink for RecordingSink<'_, S> {
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        if let Some(session) = self.session {
+            session.borrow_mut().output(self.stream, events);
+        }
+        self.sink.emit(events)
+    }
+}
+
+/// Run the engine against a source and sink until stopped or the source fails
+fn drive(
+    engine: &mut ProxyEngine,
+    source: &mut impl EventSource,
+    sink: &mut impl EventSink,
+    key_sink: &mut Option<impl EventSink>,
+    clock: &impl Clock,
+    control: &Control,
+    session: Option<&RefCell<SessionWriter>>,
+) -> io::Result<()> {
+    let mut sink = RecordingSink::new(sink, session, Stream::Virtual);
+    let mut key_sink = key_sink.as_mut().map(|sink| RecordingSink::new(sink, session, Stream::Passthrough));
+    let mut last_publish = clock.now();
+    let mut result = Ok(());
+    while !control.stop.load(Ordering::Relaxed) {
+        if let Err(e) = step(engine, source, &mut sink, &mut key_sink, clock, &control.updates, session) {
+            result = Err(e);
+            break;
+        }
+        if clock.now().saturating_duration_since(last_publish) >= STATS_PUBLISH_INTERVAL {
+            publish_stats(engine, &control.stats);
+            last_publish = clock.now();
+        }
+    }
+    publish_stats(engine, &control.stats);
+    result
+}
+
+fn publish_stats(engine: &ProxyEngine, stats: &SharedStats) {
+    if let Ok(mut shared) = stats.lock() {
+        shared.clone_from(engine.stats());
+    }
+}
+
+/// One wakeup of the proxy loop: apply pending config updates, forward input,
+/// advance timing, then sleep until new input or the next click deadline.
+/// `key_sink` passes on a grabbed trigger device's other keys. Whatever the
+/// engine is handed goes into `session` too, so the wakeup can be replayed.
+fn step(
+    engine: &mut ProxyEngine,
+    source: &mut impl EventSource,
+    sink: &mut impl EventSink,
+    key_sink: &mut Option<impl EventSink>,
+    clock: &impl Clock,
+    updates: &Receiver<Config>,
+    session: Option<&RefCell<SessionWriter>>,
+) -> io::Result<()> {
+    // Apply live config updates, keeping the grab and virtual device alive
+    while let Ok(update) = updates.try_recv() {
+        let now = clock.now();
+        if let Some(session) = session {
+            session.borrow_mut().config(now, &update);
+        }
+        engine.apply_config(update, now, sink);
+    }
+
+    let batch = source.fetch()?;
+    let now = clock.now();
+    for (origin, event) in batch {
+        if let Some(session) = session {
+            session.borrow_mut().input(now, origin, event);
+        }
+        let forwarded = match origin {
+            Origin::Mouse => engine.handle_event(event, sink),
+            Origin::TriggerDevice => engine.handle_trigger_device_event(event, sink, key_sink.as_mut()),
+        };
+        if forwarded {
+            engine.record_forward(event.timestamp(), clock.wall_time());
+        }
+    }
+
+    let now = clock.now();
+    if let Some(session) = session {
+        session.borrow_mut().tick(now);
+    }
+    engine.tick(now, sink);
+
+    source.wait(engine.next_deadline(), IDLE_WAKEUP)
+}
+
+/// Grabbed devices and the virtual devices they are proxied to
+struct Session {
+    source: EvdevSource,
+    virtual_dev: VirtualDevice,
+    /// Passes on a grabbed trigger device's other keys
+    virtual_keys: Option<VirtualDevice>,
+    /// What to look for when the devices need to be found again after a disconnect
+    mouse_ids: Vec<DeviceIdentity>,
+    trigger_id: Option<DeviceIdentity>,
+}
+
+impl Session {
+    fn open(config: &Config, mouse_paths: &[String], trigger_path: Option<&str>) -> Result<Self, String> {
+        let mut nodes = Vec::new();
+        for path in mouse_paths {
+            let mut node = open_nonblocking(path).map_err(|e| format!("Failed to open device {}: {}", path, e))?;
+            // A failed grab drops the nodes opened so far, which releases their grabs
+            node.grab().map_err(|e| grab_error("device", path, e))?;
+            nodes.push(node);
+        }
+        let mouse_ids = nodes.iter().map(DeviceIdentity::of).collect();
+
+        let virtual_dev = create_virtual_clone(&nodes, &config.all_output_keys())
+            .map_err(|e| format!("Failed to create virtual device: {}", e))?;
+
+        let mut trigger_device = None;
+        let mut virtual_keys = None;
+        if let Some(path) = trigger_path {
+            let mut device = open_nonblocking(path).map_err(|e| format!("Failed to open trigger device: {}", e))?;
+            if config.grab_trigger_device {
+                device.grab().map_err(|e| grab_error("trigger device", path, e))?;
+                virtual_keys = Some(
+                    create_virtual_key_clone(&device)
+                        .map_err(|e| format!("Failed to create virtual trigger device: {}", e))?,
+                );
+            }
+            log::info!(
+                "Listening for triggers on {}{}",
+                path,
+                if config.grab_trigger_device { " (grabbed)" } else { "" }
+            );
+            trigger_device = Some(device);
+        }
+        let trigger_id = trigger_device.as_ref().map(DeviceIdentity::of);
+
+        let mut source = EvdevSource::new(nodes).map_err(|e| format!("Failed to set up event polling: {}", e))?;
+        if let Some(device) = trigger_device {
+            source = source
+                .with_trigger_device(device)
+                .map_err(|e| format!("Failed to set up event polling: {}", e))?;
+        }
+
+        Ok(Self {
+            source,
+            virtual_dev,
+            virtual_keys,
+            mouse_ids,
+            trigger_id,
+        })
+    }
+
+    /// Release the grabs; the virtual devices go away when the session is dropped
+    fn close(&mut self) {
+        for node in self.source.devices_mut() {
+            node.ungrab().ok();
+        }
+        if self.virtual_keys.is_some()
+            && let Some(device) = self.source.trigger_device_mut()
+        {
+            device.ungrab().ok();
+        }
+    }
+}
+
+/// Why a device could not be grabbed, naming the usual culprit when it is already held
+fn grab_error(what: &str, path: &str, error: io::Error) -> String {
+    if error.raw_os_error() == Some(libc::EBUSY) {
+        format!("Failed to grab {} {}: another program already grabbed it", what, path)
+    } else {
+        format!("Failed to grab {} {}: {}", what, path, error)
+    }
+}
+
+/// How often to look for a disconnected device when no /dev/input change wakes us first
+const RECONNECT_POLL: Duration = Duration::from_millis(500);
+/// Longest wait between attempts to reopen a device that is back but cannot be opened yet
+const REOPEN_BACKOFF_MAX: Duration = Duration::from_secs(8);
+
+/// Whether a read error means the device went away, e.g. unplugged or a wireless receiver dropped
+fn is_disconnect(error: &io::Error) -> bool {
+    error.raw_os_error() == Some(libc::ENODEV)
+}
+
+/// Wait for every device of a closed session to reappear, returning their new
+/// paths, or `None` if stopped first
+fn wait_for_devices(
+    mouse_ids: &[DeviceIdentity],
+    trigger_id: Option<&DeviceIdentity>,
+    stop: &AtomicBool,
+) -> Option<(Vec<String>, Option<String>)> {
+    let watcher = InputDirWatcher::new()
+        .inspect_err(|e| log::warn!("Cannot watch /dev/input ({}), polling instead", e))
+        .ok();
+    while !stop.load(Ordering::Relaxed) {
+        // Matched together, so nodes of the same mouse each get their own
+        let identities: Vec<&DeviceIdentity> = mouse_ids.iter().chain(trigger_id).collect();
+        if let Some(mut paths) = find_devices(&identities) {
+            let trigger_path = trigger_id.and_then(|_| paths.pop());
+            return Some((paths, trigger_path));
+        }
+
+        match &watcher {
+            Some(watcher) => {
+                if let Err(e) = watcher.wait(RECONNECT_POLL) {
+                    log::warn!("Error watching /dev/input: {}", e);
+                    thread::sleep(RECONNECT_POLL);
+                }
+            }
+            None => thread::sleep(RECONNECT_POLL),
+        }
+    }
+    None
+}
+
+/// Wait for the devices with `find` and reopen them with `open`. Reopening can
+/// fail just after they reappear, e.g. while udev is still fixing up their
+/// permissions or something else holds a grab, so failures are retried with a
+/// doubling backoff passed to `sleep`. Returns `None` once stopped.
+fn reconnect<S>(
+    mut find: impl FnMut() -> Option<(Vec<String>, Option<String>)>,
+    mut open: impl FnMut(&[String], Option<&str>) -> Result<S, String>,
+    stop: &AtomicBool,
+    mut sleep: impl FnMut(Duration),
+) -> Option<S> {
+    let mut backoff = RECONNECT_POLL;
+    loop {
+        let (mouse_paths, trigger_path) = find()?;
+        log::info!("Device is back at {}, resuming", mouse_paths.join(", "));
+        match open(&mouse_paths, trigger_path.as_deref()) {
+            Ok(session) => return Some(session),
+            Err(e) => log::warn!("Failed to reopen the device ({}), retrying in {:?}", e, backoff),
+        }
+        sleep(backoff);
+        if stop.load(Ordering::Relaxed) {
+            return None;
+        }
+        backoff = (backoff * 2).min(REOPEN_BACKOFF_MAX);
+    }
+}
+
+/// Sleep for `duration`, waking early if `stop` is set
+fn sleep_unless_stopped(duration: Duration, stop: &AtomicBool) {
+    let until = Instant::now() + duration;
+    while !stop.load(Ordering::Relaxed) {
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        thread::sleep(left.min(IDLE_WAKEUP));
+    }
+}
+
+fn run_proxy_loop(mut config: Config, control: &Control) -> Result<(), String> {
+    // Saved event numbers change across reboots; follow the devices to where they are now
+    resolve_config_devices(&mut config)?;
+    let mouse_paths: Vec<String> = config.mouse_device_paths().into_iter().cloned().collect();
+    let trigger_path = Some(config.trigger_device_path.clone()).filter(|path| !path.is_empty());
+    let session = Session::open(&config, &mouse_paths, trigger_path.as_deref())?;
+
+    log::info!("Proxy started for device: {}", config.device_path);
+    for path in &config.extra_device_paths {
+        log::info!("Merging events from {}", path);
+    }
+    if config.smart_ads_trigger {
+        log::info!("Smart ADS trigger enabled (RMB + LMB)");
+    }
+    for binding in config.bindings() {
+        let trigger_key = binding.effective_trigger_code();
+        log::info!("Trigger: {} (code {})", binding.trigger_description(), trigger_key.0);
+    }
+
+    let clock = SystemClock;
+    let start = clock.now();
+    let mut engine = ProxyEngine::new(config, start);
+    log::info!("Humanize seed: {} (set \"seed\" in the config to reproduce this timing)", engine.seed());
+    let recording = match &control.session_path {
+        Some(path) => {
+            log::info!("Recording the session to {}", path.display());
+            Some(RefCell::new(SessionWriter::create(path, &engine, start)?))
+        }
+        None => None,
+    };
+
+    // Empty while stopped waiting for a disconnected device
+    let mut session = Some(session);
+    let result = loop {
+        let Some(active) = session.as_mut() else {
+            break Ok(());
+        };
+        let result = drive(
+            &mut engine,
+            &mut active.source,
+            &mut active.virtual_dev,
+            &mut active.virtual_keys,
+            &clock,
+            control,
+            recording.as_ref(),
+        );
+        let error = match result {
+            Ok(()) => break Ok(()),
+            Err(e) if is_disconnect(&e) => e,
+            Err(e) => break Err(format!("Error reading events: {}", e)),
+        };
+
+        // Release anything held before the virtual device goes away, then wait for the hardware
+        log::warn!("Device disconnected ({}), waiting for it to come back", error);
+        if let Some(recording) = &recording {
+            recording.borrow_mut().disconnect(clock.now());
+        }
+        engine.disconnect(&mut RecordingSink::new(&mut active.virtual_dev, recording.as_ref(), Stream::Virtual));
+        active.close();
+        engine.set_waiting_for_device(true);
+        publish_stats(&engine, &control.stats);
+
+        let (mouse_ids, trigger_id) = (std::mem::take(&mut active.mouse_ids), active.trigger_id.take());
+        session = None;
+        let reopened = reconnect(
+            || wait_for_devices(&mouse_ids, trigger_id.as_ref(), &control.stop),
+            |mouse_paths, trigger_path| Session::open(engine.config(), mouse_paths, trigger_path),
+            &control.stop,
+            |backoff| sleep_unless_stopped(backoff, &control.stop),
+        );
+        engine.set_waiting_for_device(false);
+        if let Some(mut reopened) = reopened {
+            // Keep matching the devices as first seen
+            reopened.mouse_ids = mouse_ids;
+            reopened.trigger_id = trigger_id;
+            engine.record_reconnect();
+            session = Some(reopened);
+        }
+    };
+
+    // Clean up: release button if held. Every exit once the engine runs comes through here.
+    if let Some(session) = &mut session {
+        if let Some(recording) = &recording {
+            recording.borrow_mut().end(clock.now());
+        }
+        engine.shutdown(&mut RecordingSink::new(&mut session.virtual_dev, recording.as_ref(), Stream::Virtual));
+        session.close();
+    }
+    publish_stats(&engine, &control.stats);
+    if let Some(recording) = recording {
+        recording.into_inner().finish();
+    }
     log::info!("Proxy stopped");
-    Ok(())
+    result
 }
 
-fn emit_button_down(virtual_dev: &mut evdev::uinput::VirtualDevice) {
-    let btn_down = InputEvent::new(EventType::KEY.0, KeyCode::BTN_LEFT.0, 1);
-    let sync = InputEvent::new(EventType::SYNCHRONIZATION.0, SynchronizationCode::SYN_REPORT.0, 0);
+/// Open a device in non-blocking mode so fetching never blocks; waiting is done through epoll
+fn open_nonblocking(path: &str) -> io::Result<Device> {
+    let device = open_device(path)?;
+    let fd = device.as_raw_fd();
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+    }
+    Ok(device)
+}
 
-    if let Err(e) = virtual_dev.emit(&[btn_down, sync]) {
-        log::warn!("Failed to emit button down: {}", e);
+/// Forward a physical event unchanged
+fn forward(sink: &mut impl EventSink, event: InputEvent) {
+    if let Err(e) = sink.emit(&[event]) {
+        log::warn!("Failed to emit event: {}", e);
     }
 }
 
-fn emit_button_up(virtual_dev: &mut evdev::uinput::VirtualDevice) {
-    let btn_up = InputEvent::new(EventType::KEY.0, KeyCode::BTN_LEFT.0, 0);
-    let sync = InputEvent::new(EventType::SYNCHRONIZATION.0, SynchronizationCode::SYN_REPORT.0, 0);
+/// Set every key in `keys` to `value` in a single report
+fn key_report(keys: &[KeyCode], value: i32) -> Vec<InputEvent> {
+    let mut events: Vec<InputEvent> = keys
+        .iter()
+        .map(|key| InputEvent::new(EventType::KEY.0, key.0, value))
+        .collect();
+    events.push(InputEvent::new(EventType::SYNCHRONIZATION.0, SynchronizationCode::SYN_REPORT.0, 0));
+    events
+}
 
-    if let Err(e) = virtual_dev.emit(&[btn_up, sync]) {
+fn emit_button_down(sink: &mut impl EventSink, keys: &[KeyCode]) {
+    if let Err(e) = sink.emit(&key_report(keys, 1)) {
+        log::warn!("Failed to emit button down: {}", e);
+    }
+}
+
+fn emit_button_up(sink: &mut impl EventSink, keys: &[KeyCode]) {
+    if let Err(e) = sink.emit(&key_report(keys, 0)) {
         log::warn!("Failed to emit button up: {}", e);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{ManualClock, MemorySink, ScriptedSource};
+    use crate::config::{ActivationMode, Binding, Remap, TriggerButton};
+    use crate::humanize::TimingDistribution;
+    use crate::session::{first_difference, replay, SessionRecording};
+    use evdev::RelativeAxisCode;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    fn key(code: KeyCode, value: i32) -> InputEvent {
+        InputEvent::new(EventType::KEY.0, code.0, value)
+    }
+
+    /// Fixed 50ms delay and 10ms travel so click times are exact
+    fn fixed_config() -> Config {
+        Config {
+            device_path: "/dev/input/test".to_string(),
+            trigger_button: TriggerButton::Mouse4,
+            click_delay_min_ms: 50,
+            click_delay_max_ms: 50,
+            travel_time_min_ms: 10,
+            travel_time_max_ms: 10,
+            ..Default::default()
+        }
+    }
+
+    struct Harness {
+        clock: ManualClock,
+        source: ScriptedSource,
+        sink: MemorySink,
+        /// Receives a grabbed trigger device's other keys, when set
+        key_sink: Option<MemorySink>,
+        engine: ProxyEngine,
+    }
+
+    impl Harness {
+        fn new(config: Config, script: Vec<(u64, InputEvent)>) -> Self {
+            let clock = ManualClock::new();
+            let script = script.into_iter().map(|(at, event)| (ms(at), event)).collect();
+            Self {
+                source: ScriptedSource::new(clock.clone(), script),
+                sink: MemorySink::new(clock.clone()),
+                key_sink: None,
+                engine: ProxyEngine::new(config, clock.now()),
+                clock,
+            }
+        }
+
+        /// Step the engine in 1ms increments, the way `drive` does in real time
+        fn run_for(&mut self, duration_ms: u64) {
+            let end = self.clock.elapsed() + ms(duration_ms);
+            while self.clock.elapsed() < end {
+                for (origin, event) in self.source.fetch().unwrap() {
+                    let forwarded = match origin {
+                        Origin::Mouse => self.engine.handle_event(event, &mut self.sink),
+                        Origin::TriggerDevice => {
+                            self.engine.handle_trigger_device_event(event, &mut self.sink, self.key_sink.as_mut())
+                        }
+                    };
+                    if forwarded {
+                        self.engine.record_forward(event.timestamp(), self.clock.wall_time());
+                    }
+                }
+                self.engine.tick(self.clock.now(), &mut self.sink);
+                self.clock.advance(ms(1));
+            }
+        }
+
+        /// Run the real loop body, letting the source jump the clock between wakeups
+        fn run_driven(&mut self, duration_ms: u64) -> usize {
+            let (_updates_tx, updates) = std::sync::mpsc::channel();
+            let end = self.clock.elapsed() + ms(duration_ms);
+            let mut wakeups = 0;
+            while self.clock.elapsed() < end {
+                step(&mut self.engine, &mut self.source, &mut self.sink, &mut self.key_sink, &self.clock, &updates, None)
+                    .unwrap();
+                wakeups += 1;
+            }
+            wakeups
+        }
+
+        /// (time in ms, value) of every emitted KEY event for `code`
+        fn edges(&self, code: KeyCode) -> Vec<(u64, i32)> {
+            self.sink
+                .emitted
+                .iter()
+                .filter(|(_, e)| e.event_type() == EventType::KEY && e.code() == code.0)
+                .map(|(at, e)| (at.as_millis() as u64, e.value()))
+                .collect()
+        }
+
+        fn presses(&self, code: KeyCode) -> Vec<u64> {
+            self.edges(code).into_iter().filter(|(_, v)| *v == 1).map(|(at, _)| at).collect()
+        }
+    }
+
+    #[test]
+    fn test_trigger_hold_fires_and_release_stops() {
+        let mut h = Harness::new(
+            fixed_config(),
+            vec![(100, key(KeyCode::BTN_SIDE, 1)), (300, key(KeyCode::BTN_SIDE, 0))],
+        );
+        h.run_for(500);
+
+        assert_eq!(
+            h.edges(KeyCode::BTN_LEFT),
+            vec![(100, 1), (110, 0), (160, 1), (170, 0), (220, 1), (230, 0), (280, 1), (290, 0)]
+        );
+        // The trigger itself never reaches the virtual device
+        assert!(h.edges(KeyCode::BTN_SIDE).is_empty());
+    }
+
+    #[test]
+    fn test_trigger_release_mid_click_releases_button() {
+        let mut h = Harness::new(
+            fixed_config(),
+            vec![(100, key(KeyCode::BTN_SIDE, 1)), (105, key(KeyCode::BTN_SIDE, 0))],
+        );
+        h.run_for(300);
+
+        assert_eq!(h.edges(KeyCode::BTN_LEFT), vec![(100, 1), (105, 0)]);
+    }
+
+    #[test]
+    fn test_other_events_pass_through() {
+        let motion = InputEvent::new(EventType::RELATIVE.0, RelativeAxisCode::REL_X.0, 5);
+        let mut h = Harness::new(
+            fixed_config(),
+            vec![(10, motion), (20, key(KeyCode::BTN_RIGHT, 1)), (30, key(KeyCode::BTN_RIGHT, 0))],
+        );
+        h.run_for(50);
+
+        assert_eq!(h.sink.emitted.len(), 3);
+        assert_eq!(h.sink.emitted[0].1.code(), RelativeAxisCode::REL_X.0);
+        assert_eq!(h.sink.emitted[0].1.value(), 5);
+        assert_eq!(h.edges(KeyCode::BTN_RIGHT), vec![(20, 1), (30, 0)]);
+        assert!(h.edges(KeyCode::BTN_LEFT).is_empty());
+    }
+
+    #[test]
+    fn test_smart_ads_only_fires_while_aiming() {
+        let config = Config {
+            smart_ads_trigger: true,
+            ..fixed_config()
+        };
+        let mut h = Harness::new(
+            config,
+            vec![
+                // Hip fire: plain left click passes through
+                (10, key(KeyCode::BTN_LEFT, 1)),
+                (20, key(KeyCode::BTN_LEFT, 0)),
+                // Aim, then fire
+                (50, key(KeyCode::BTN_RIGHT, 1)),
+                (100, key(KeyCode::BTN_LEFT, 1)),
+                (200, key(KeyCode::BTN_LEFT, 0)),
+                (250, key(KeyCode::BTN_RIGHT, 0)),
+            ],
+        );
+        h.run_for(400);
+
+        assert_eq!(
+            h.edges(KeyCode::BTN_LEFT),
+            vec![(10, 1), (20, 0), (100, 1), (110, 0), (160, 1), (170, 0)]
+        );
+        assert_eq!(h.edges(KeyCode::BTN_RIGHT), vec![(50, 1), (250, 0)]);
+    }
+
+    #[test]
+    fn test_smart_ads_aim_release_stops_firing() {
+        let config = Config {
+            smart_ads_trigger: true,
+            ..fixed_config()
+        };
+        let mut h = Harness::new(
+            config,
+            vec![
+                (50, key(KeyCode::BTN_RIGHT, 1)),
+                (100, key(KeyCode::BTN_LEFT, 1)),
+                (105, key(KeyCode::BTN_RIGHT, 0)),
+            ],
+        );
+        h.run_for(300);
+
+        assert_eq!(h.edges(KeyCode::BTN_LEFT), vec![(100, 1), (105, 0)]);
+    }
+
+    #[test]
+    fn test_burst_mode_pauses_between_bursts() {
+        let config = Config {
+            burst_mode: true,
+            burst_count: 3,
+            burst_pause_ms: 100,
+            ..fixed_config()
+        };
+        let mut h = Harness::new(config, vec![(100, key(KeyCode::BTN_SIDE, 1))]);
+        h.run_for(600);
+
+        let presses = h.presses(KeyCode::BTN_LEFT);
+        assert_eq!(&presses[..3], &[100, 160, 220]);
+        // Pause (80-120% of 100ms) after the third release, then the normal delay
+        let gap = presses[3] - presses[2];
+        assert!((140..=181).contains(&gap), "burst gap was {}ms", gap);
+        assert_eq!(presses[4] - presses[3], 60);
+    }
+
+    #[test]
+    fn test_fatigue_slows_clicks_down() {
+        let config = Config {
+            simulate_fatigue: true,
+            fatigue_max_percent: 50,
+            ..fixed_config()
+        };
+        let mut h = Harness::new(config, vec![(0, key(KeyCode::BTN_SIDE, 1))]);
+        h.run_for(2000);
+
+        let presses = h.presses(KeyCode::BTN_LEFT);
+        let gaps: Vec<u64> = presses.windows(2).map(|w| w[1] - w[0]).collect();
+        assert!(gaps.windows(2).all(|w| w[1] >= w[0]), "gaps should never shrink: {:?}", gaps);
+        assert_eq!(gaps[0], 60);
+        assert!(*gaps.last().unwrap() >= 70, "gaps: {:?}", gaps);
+    }
+
+    #[test]
+    fn test_live_config_update_changes_rate() {
+        let mut h = Harness::new(fixed_config(), vec![(0, key(KeyCode::BTN_SIDE, 1))]);
+        h.run_for(125);
+        // The first click waits for one interval after the proxy starts
+        assert_eq!(h.presses(KeyCode::BTN_LEFT), vec![50, 110]);
+
+        let slower = Config {
+            click_delay_min_ms: 100,
+            click_delay_max_ms: 100,
+            ..fixed_config()
+        };
+        h.engine.apply_config(slower, h.clock.now(), &mut h.sink);
+        h.run_for(300);
+
+        assert_eq!(h.presses(KeyCode::BTN_LEFT), vec![50, 110, 220, 330]);
+    }
+
+    #[test]
+    fn test_live_trigger_change_releases_held_click() {
+        let mut h = Harness::new(fixed_config(), vec![(0, key(KeyCode::BTN_SIDE, 1))]);
+        h.run_for(55);
+
+        let config = Config {
+            trigger_button: TriggerButton::Mouse5,
+            ..fixed_config()
+        };
+        h.engine.apply_config(config, h.clock.now(), &mut h.sink);
+        h.run_for(200);
+
+        assert_eq!(h.edges(KeyCode::BTN_LEFT), vec![(50, 1), (55, 0)]);
+        assert_eq!(h.engine.config.trigger_button, TriggerButton::Mouse5);
+    }
+
+    #[test]
+    fn test_same_seed_reproduces_session() {
+        let config = Config {
+            click_delay_min_ms: 40,
+            click_delay_max_ms: 90,
+            travel_time_min_ms: 5,
+            travel_time_max_ms: 30,
+            use_gaussian: true,
+            travel_jitter: true,
+            burst_mode: true,
+            seed: Some(99),
+            ..fixed_config()
+        };
+        let session = |config: Config| {
+            let mut h = Harness::new(config, vec![(0, key(KeyCode::BTN_SIDE, 1))]);
+            h.run_for(2000);
+            h.edges(KeyCode::BTN_LEFT)
+        };
+
+        let first = session(config.clone());
+        assert!(first.len() > 20);
+        assert_eq!(first, session(config.clone()));
+        assert_ne!(first, session(Config { seed: Some(100), ..config }));
+    }
+
+    #[test]
+    fn test_click_distribution_sets_intervals() {
+        let config = Config {
+            click_delay_min_ms: 50,
+            click_delay_max_ms: 100,
+            click_distribution: Some(TimingDistribution::Gaussian {
+                mean_ms: Some(70.0),
+                std_dev_ms: Some(0.0),
+            }),
+            ..fixed_config()
+        };
+        let mut h = Harness::new(config, vec![(0, key(KeyCode::BTN_SIDE, 1))]);
+        h.run_for(300);
+
+        // Each interval runs from the previous release, 10ms after its press
+        assert_eq!(h.presses(KeyCode::BTN_LEFT), vec![70, 150, 230]);
+    }
+
+    #[test]
+    fn test_deadline_wakeups_match_polling() {
+        let config = Config {
+            click_delay_min_ms: 40,
+            click_delay_max_ms: 90,
+            travel_time_min_ms: 5,
+            travel_time_max_ms: 30,
+            travel_jitter: true,
+            simulate_fatigue: true,
+            burst_mode: true,
+            seed: Some(7),
+            ..fixed_config()
+        };
+        let script = || vec![(100, key(KeyCode::BTN_SIDE, 1)), (1500, key(KeyCode::BTN_SIDE, 0))];
+
+        let mut polled = Harness::new(config.clone(), script());
+        polled.run_for(2000);
+        let mut driven = Harness::new(config, script());
+        let wakeups = driven.run_driven(2000);
+
+        assert!(polled.edges(KeyCode::BTN_LEFT).len() > 20);
+        assert_eq!(driven.edges(KeyCode::BTN_LEFT), polled.edges(KeyCode::BTN_LEFT));
+        // Only woken for input and click edges, not every millisecond
+        assert!(wakeups < 200, "woke {} times", wakeups);
+    }
+
+    #[test]
+    fn test_idle_engine_has_no_deadline() {
+        let mut h = Harness::new(fixed_config(), vec![(10, key(KeyCode::BTN_SIDE, 1)), (80, key(KeyCode::BTN_SIDE, 0))]);
+        assert_eq!(h.engine.next_deadline(), None);
+
+        h.run_for(20);
+        assert_eq!(h.engine.next_deadline(), Some(h.clock.now() - ms(20) + ms(50)));
+
+        // Released mid-click: nothing left to do
+        h.run_for(80);
+        assert_eq!(h.engine.next_deadline(), None);
+        assert_eq!(h.run_driven(1000), 20);
+    }
+
+    #[test]
+    fn test_stats_count_clicks_and_timing() {
+        let mut h = Harness::new(
+            fixed_config(),
+            vec![(100, key(KeyCode::BTN_SIDE, 1)), (300, key(KeyCode::BTN_SIDE, 0))],
+        );
+        h.run_for(500);
+
+        let stats = h.engine.stats();
+        assert_eq!(stats.clicks, 4);
+        assert_eq!(stats.travel_time.count, 4);
+        assert_eq!(stats.travel_time.mean(), Some(ms(10)));
+        // Three gaps between four presses, 60ms apart
+        assert_eq!(stats.click_interval.count, 3);
+        assert_eq!(stats.clicks_per_second(), Some(1000.0 / 60.0));
+    }
+
+    #[test]
+    fn test_stats_interval_restarts_with_each_streak() {
+        let mut h = Harness::new(
+            fixed_config(),
+            vec![
+                (100, key(KeyCode::BTN_SIDE, 1)),
+                (170, key(KeyCode::BTN_SIDE, 0)),
+                (1000, key(KeyCode::BTN_SIDE, 1)),
+                (1070, key(KeyCode::BTN_SIDE, 0)),
+            ],
+        );
+        h.run_for(1200);
+
+        // The pause between the two holds is not a click interval
+        assert_eq!(h.engine.stats().click_interval.count, 2);
+        assert_eq!(h.engine.stats().click_interval.max_us, Some(60_000));
+    }
+
+    #[test]
+    fn test_stats_forward_latency() {
+        let motion = InputEvent::new(EventType::RELATIVE.0, RelativeAxisCode::REL_X.0, 5);
+        let mut h = Harness::new(fixed_config(), vec![(10, motion), (20, key(KeyCode::BTN_SIDE, 1))]);
+        h.run_for(5);
+        // Fetched 3ms after the kernel stamped it
+        h.clock.advance(ms(8));
+        h.run_for(20);
+
+        let stats = h.engine.stats();
+        // The trigger is swallowed, only the motion event counts
+        assert_eq!(stats.forwarded_events, 1);
+        assert_eq!(stats.forward_latency.max_us, Some(3_000));
+    }
+
+    #[test]
+    fn test_custom_output_keys() {
+        let config = Config {
+            output_keys: vec![KeyCode::BTN_RIGHT.0, KeyCode::KEY_E.0],
+            ..fixed_config()
+        };
+        let mut h = Harness::new(config, vec![(100, key(KeyCode::BTN_SIDE, 1)), (150, key(KeyCode::BTN_SIDE, 0))]);
+        h.run_for(200);
+
+        assert_eq!(h.edges(KeyCode::BTN_RIGHT), vec![(100, 1), (110, 0)]);
+        assert_eq!(h.edges(KeyCode::KEY_E), vec![(100, 1), (110, 0)]);
+        assert!(h.edges(KeyCode::BTN_LEFT).is_empty());
+    }
+
+    #[test]
+    fn test_smart_ads_repeats_configured_output() {
+        let config = Config {
+            smart_ads_trigger: true,
+            output_keys: vec![KeyCode::BTN_MIDDLE.0],
+            ..fixed_config()
+        };
+        let mut h = Harness::new(
+            config,
+            vec![(50, key(KeyCode::BTN_RIGHT, 1)), (100, key(KeyCode::BTN_LEFT, 1)), (150, key(KeyCode::BTN_LEFT, 0))],
+        );
+        h.run_for(200);
+
+        assert_eq!(h.edges(KeyCode::BTN_MIDDLE), vec![(100, 1), (110, 0)]);
+        assert!(h.edges(KeyCode::BTN_LEFT).is_empty());
+    }
+
+    #[test]
+    fn test_live_output_change_releases_old_output() {
+        let mut h = Harness::new(fixed_config(), vec![(0, key(KeyCode::BTN_SIDE, 1))]);
+        h.run_for(55);
+
+        let config = Config {
+            output_keys: vec![KeyCode::BTN_RIGHT.0],
+            ..fixed_config()
+        };
+        h.engine.apply_config(config, h.clock.now(), &mut h.sink);
+        h.run_for(100);
+
+        // Still held, so firing carries on with the new output
+        assert_eq!(h.edges(KeyCode::BTN_LEFT), vec![(50, 1), (55, 0)]);
+        assert_eq!(h.presses(KeyCode::BTN_RIGHT), vec![55, 115]);
+    }
+
+    #[test]
+    fn test_engine_reports_seed() {
+        let config = Config {
+            seed: Some(42),
+            ..fixed_config()
+        };
+        let h = Harness::new(config, vec![]);
+        assert_eq!(h.engine.seed(), 42);
+    }
+
+    #[test]
+    fn test_live_update_keeps_device() {
+        let mut h = Harness::new(fixed_config(), vec![]);
+        let config = Config {
+            device_path: "/dev/input/other".to_string(),
+            ..fixed_config()
+        };
+        h.engine.apply_config(config, h.clock.now(), &mut h.sink);
+        assert_eq!(h.engine.config.device_path, "/dev/input/test");
+    }
+
+    /// Mouse5 repeating right click every 120ms alongside the fixed primary binding
+    fn right_click_binding() -> Binding {
+        Binding {
+            trigger_button: TriggerButton::Mouse5,
+            output_keys: vec![KeyCode::BTN_RIGHT.0],
+            click_delay_min_ms: 120,
+            click_delay_max_ms: 120,
+            travel_time_min_ms: 10,
+            travel_time_max_ms: 10,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_bindings_fire_independently() {
+        let config = Config {
+            extra_bindings: vec![right_click_binding()],
+            ..fixed_config()
+        };
+        let mut h = Harness::new(
+            config,
+            vec![
+                (0, key(KeyCode::BTN_SIDE, 1)),
+                (0, key(KeyCode::BTN_EXTRA, 1)),
+                (200, key(KeyCode::BTN_SIDE, 0)),
+                (300, key(KeyCode::BTN_EXTRA, 0)),
+            ],
+        );
+        h.run_for(400);
+
+        assert_eq!(h.presses(KeyCode::BTN_LEFT), vec![50, 110, 170]);
+        assert_eq!(h.presses(KeyCode::BTN_RIGHT), vec![120, 250]);
+        assert!(h.edges(KeyCode::BTN_EXTRA).is_empty());
+    }
+
+    #[test]
+    fn test_live_binding_add_and_remove() {
+        let mut h = Harness::new(
+            fixed_config(),
+            vec![(0, key(KeyCode::BTN_EXTRA, 1)), (200, key(KeyCode::BTN_EXTRA, 1))],
+        );
+        h.run_for(100);
+        // Without a binding the button is an ordinary one
+        assert_eq!(h.edges(KeyCode::BTN_EXTRA), vec![(0, 1)]);
+
+        let config = Config {
+            extra_bindings: vec![right_click_binding()],
+            ..fixed_config()
+        };
+        h.engine.apply_config(config, h.clock.now(), &mut h.sink);
+        h.run_for(125);
+        assert_eq!(h.presses(KeyCode::BTN_RIGHT), vec![220]);
+
+        // Removing the binding mid-click releases its output
+        h.engine.apply_config(fixed_config(), h.clock.now(), &mut h.sink);
+        assert_eq!(h.edges(KeyCode::BTN_RIGHT), vec![(220, 1), (225, 0)]);
+        h.run_for(200);
+        assert_eq!(h.presses(KeyCode::BTN_RIGHT), vec![220]);
+    }
+
+    #[test]
+    fn test_toggle_latches_until_pressed_again() {
+        let config = Config {
+            activation: ActivationMode::Toggle,
+            ..fixed_config()
+        };
+        let mut h = Harness::new(
+            config,
+            vec![
+                (100, key(KeyCode::BTN_SIDE, 1)),
+                (120, key(KeyCode::BTN_SIDE, 0)),
+                (225, key(KeyCode::BTN_SIDE, 1)),
+                (240, key(KeyCode::BTN_SIDE, 0)),
+            ],
+        );
+        h.run_for(200);
+        assert_eq!(h.engine.stats().firing, vec![true]);
+
+        h.run_for(200);
+        // Toggling off mid-click releases the held output
+        assert_eq!(
+            h.edges(KeyCode::BTN_LEFT),
+            vec![(100, 1), (110, 0), (160, 1), (170, 0), (220, 1), (225, 0)]
+        );
+        assert_eq!(h.engine.stats().firing, vec![false]);
+    }
+
+    #[test]
+    fn test_remapped_buttons_pass_through_as_their_target() {
+        let config = Config {
+            remap: vec![
+                Remap {
+                    from: KeyCode::BTN_EXTRA.0,
+                    to: KeyCode::KEY_E.0,
+                },
+                // Triggers see the physical button, so this never applies
+                Remap {
+                    from: KeyCode::BTN_SIDE.0,
+                    to: KeyCode::BTN_MIDDLE.0,
+                },
+            ],
+            ..fixed_config()
+        };
+        let mut h = Harness::new(
+            config,
+            vec![
+                (100, key(KeyCode::BTN_EXTRA, 1)),
+                (120, key(KeyCode::BTN_EXTRA, 0)),
+                (200, key(KeyCode::BTN_SIDE, 1)),
+                (205, key(KeyCode::BTN_SIDE, 0)),
+            ],
+        );
+        h.run_for(300);
+
+        assert_eq!(h.edges(KeyCode::KEY_E), vec![(100, 1), (120, 0)]);
+        assert!(h.edges(KeyCode::BTN_EXTRA).is_empty());
+        assert!(h.edges(KeyCode::BTN_MIDDLE).is_empty());
+        assert_eq!(h.presses(KeyCode::BTN_LEFT), vec![200]);
+    }
+
+    #[test]
+    fn test_live_remap_change_keeps_held_button_on_old_target() {
+        let remap_to = |to: KeyCode| Config {
+            remap: vec![Remap {
+                from: KeyCode::BTN_EXTRA.0,
+                to: to.0,
+            }],
+            ..fixed_config()
+        };
+        let mut h = Harness::new(
+            remap_to(KeyCode::KEY_E),
+            vec![(100, key(KeyCode::BTN_EXTRA, 1)), (200, key(KeyCode::BTN_EXTRA, 0))],
+        );
+        h.run_for(150);
+        h.engine.apply_config(remap_to(KeyCode::KEY_R), h.clock.now(), &mut h.sink);
+        h.run_for(100);
+
+        assert_eq!(h.edges(KeyCode::KEY_E), vec![(100, 1), (200, 0)]);
+        assert!(h.edges(KeyCode::KEY_R).is_empty());
+    }
+
+    /// Mouse5 plays `script` with the fixed timing
+    fn macro_config(script: &str, activation: ActivationMode, repeat: bool) -> Config {
+        let binding = Binding {
+            trigger_button: TriggerButton::Mouse5,
+            activation,
+            activation_clicks: 2,
+            macro_steps: crate::macros::parse_script(script).unwrap(),
+            macro_repeat: repeat,
+            ..fixed_config().primary_binding()
+        };
+        Config {
+            extra_bindings: vec![binding],
+            ..fixed_config()
+        }
+    }
+
+    #[test]
+    fn test_macro_plays_once_per_press() {
+        let config = macro_config("press r, wait 30, release r, click left 2", ActivationMode::Hold, false);
+        let mut h = Harness::new(config, vec![(100, key(KeyCode::BTN_EXTRA, 1)), (400, key(KeyCode::BTN_EXTRA, 0))]);
+        h.run_for(500);
+
+        assert_eq!(h.edges(KeyCode::KEY_R), vec![(100, 1), (130, 0)]);
+        // Clicks hold for the travel time and are spaced by the click delay
+        assert_eq!(h.edges(KeyCode::BTN_LEFT), vec![(130, 1), (140, 0), (190, 1), (200, 0)]);
+        assert!(h.edges(KeyCode::BTN_EXTRA).is_empty());
+    }
+
+    #[test]
+    fn test_macro_release_cancels_and_releases_held_keys() {
+        let config = macro_config("press r, wait 30, release r, click left", ActivationMode::Hold, false);
+        let mut h = Harness::new(config, vec![(100, key(KeyCode::BTN_EXTRA, 1)), (115, key(KeyCode::BTN_EXTRA, 0))]);
+        h.run_for(300);
+
+        assert_eq!(h.edges(KeyCode::KEY_R), vec![(100, 1), (115, 0)]);
+        assert!(h.edges(KeyCode::BTN_LEFT).is_empty());
+    }
+
+    #[test]
+    fn test_repeating_macro_waits_a_click_delay_between_runs() {
+        let config = macro_config("click left, wait 20", ActivationMode::Hold, true);
+        let mut h = Harness::new(config, vec![(100, key(KeyCode::BTN_EXTRA, 1)), (300, key(KeyCode::BTN_EXTRA, 0))]);
+        h.run_driven(400);
+
+        assert_eq!(h.presses(KeyCode::BTN_LEFT), vec![100, 180, 260]);
+        assert_eq!(h.edges(KeyCode::BTN_LEFT).last(), Some(&(270, 0)));
+    }
+
+    #[test]
+    fn test_macro_replays_recorded_motion() {
+        let config = macro_config("move x 5, move y -2, wait 8, move wheel 1", ActivationMode::Hold, false);
+        let mut h = Harness::new(config, vec![(100, key(KeyCode::BTN_EXTRA, 1))]);
+        h.run_for(200);
+
+        let motion: Vec<(u64, u16, i32)> = h
+            .sink
+            .emitted
+            .iter()
+            .filter(|(_, e)| e.event_type() == EventType::RELATIVE)
+            .map(|(at, e)| (at.as_millis() as u64, e.code(), e.value()))
+            .collect();
+        assert_eq!(
+            motion,
+            vec![
+                (100, RelativeAxisCode::REL_X.0, 5),
+                (100, RelativeAxisCode::REL_Y.0, -2),
+                (108, RelativeAxisCode::REL_WHEEL.0, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fixed_clicks_plays_macro_per_click() {
+        let config = macro_config("press r, release r", ActivationMode::Clicks, false);
+        let mut h = Harness::new(config, vec![(100, key(KeyCode::BTN_EXTRA, 1)), (101, key(KeyCode::BTN_EXTRA, 0))]);
+        h.run_for(400);
+
+        assert_eq!(h.presses(KeyCode::KEY_R), vec![100, 150]);
+        assert_eq!(h.engine.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_disconnect_releases_output_and_unlatches() {
+        let config = Config {
+            activation: ActivationMode::Toggle,
+            ..fixed_config()
+        };
+        let mut h = Harness::new(
+            config,
+            vec![(100, key(KeyCode::BTN_SIDE, 1)), (102, key(KeyCode::BTN_SIDE, 0))],
+        );
+        h.run_for(105);
+        h.engine.disconnect(&mut h.sink);
+        h.run_for(200);
+
+        // The click in progress is released and the toggle does not survive the reconnect
+        assert_eq!(h.edges(KeyCode::BTN_LEFT), vec![(100, 1), (105, 0)]);
+        assert_eq!(h.engine.stats().firing, vec![false]);
+    }
+
+    #[test]
+    fn test_fixed_clicks_per_press() {
+        let config = Config {
+            activation: ActivationMode::Clicks,
+            activation_clicks: 2,
+            ..fixed_config()
+        };
+        let mut h = Harness::new(
+            config,
+            vec![
+                (100, key(KeyCode::BTN_SIDE, 1)),
+                (105, key(KeyCode::BTN_SIDE, 0)),
+                (400, key(KeyCode::BTN_SIDE, 1)),
+                (600, key(KeyCode::BTN_SIDE, 0)),
+            ],
+        );
+        h.run_for(700);
+
+        // Releasing the trigger early does not cut the count short, holding it does not extend it
+        assert_eq!(h.presses(KeyCode::BTN_LEFT), vec![100, 160, 400, 460]);
+        assert_eq!(h.edges(KeyCode::BTN_LEFT).last(), Some(&(470, 0)));
+    }
+
+    #[test]
+    fn test_chord_gates_trigger_and_passes_through() {
+        let config = Config {
+            chord: vec![KeyCode::KEY_LEFTSHIFT.0],
+            ..fixed_config()
+        };
+        let mut h = Harness::new(
+            config,
+            vec![
+                // Without the chord the trigger is an ordinary button
+                (0, key(KeyCode::BTN_SIDE, 1)),
+                (20, key(KeyCode::BTN_SIDE, 0)),
+                (100, key(KeyCode::KEY_LEFTSHIFT, 1)),
+                (150, key(KeyCode::BTN_SIDE, 1)),
+                (225, key(KeyCode::KEY_LEFTSHIFT, 0)),
+                (300, key(KeyCode::BTN_SIDE, 0)),
+            ],
+        );
+        h.run_for(400);
+
+        assert_eq!(h.edges(KeyCode::BTN_SIDE), vec![(0, 1), (20, 0)]);
+        assert_eq!(h.edges(KeyCode::KEY_LEFTSHIFT), vec![(100, 1), (225, 0)]);
+        // Letting go of the chord mid-click stops firing
+        assert_eq!(
+            h.edges(KeyCode::BTN_LEFT),
+            vec![(150, 1), (160, 0), (210, 1), (220, 0)]
+        );
+    }
+
+    #[test]
+    fn test_longest_held_chord_wins() {
+        let aimed = Binding {
+            trigger_button: TriggerButton::Mouse4,
+            chord: vec![KeyCode::BTN_RIGHT.0],
+            output_keys: vec![KeyCode::BTN_MIDDLE.0],
+            ..fixed_config().primary_binding()
+        };
+        let config = Config {
+            extra_bindings: vec![aimed],
+            ..fixed_config()
+        };
+        let mut h = Harness::new(
+            config,
+            vec![
+                (100, key(KeyCode::BTN_SIDE, 1)),
+                (105, key(KeyCode::BTN_SIDE, 0)),
+                (200, key(KeyCode::BTN_RIGHT, 1)),
+                (200, key(KeyCode::BTN_SIDE, 1)),
+                (205, key(KeyCode::BTN_SIDE, 0)),
+            ],
+        );
+        h.run_for(300);
+
+        assert_eq!(h.presses(KeyCode::BTN_LEFT), vec![100]);
+        assert_eq!(h.presses(KeyCode::BTN_MIDDLE), vec![200]);
+    }
+
+    #[test]
+    fn test_trigger_device_keys_fire_the_mouse_output() {
+        let config = Config {
+            trigger_button: TriggerButton::KeyF13,
+            chord: vec![KeyCode::KEY_LEFTCTRL.0],
+            ..fixed_config()
+        };
+        let mut h = Harness::new(config, vec![]);
+        for (at, code, value) in [
+            (90, KeyCode::KEY_LEFTCTRL, 1),
+            (100, KeyCode::KEY_F13, 1),
+            (150, KeyCode::KEY_A, 1),
+            (180, KeyCode::KEY_F13, 0),
+        ] {
+            h.source.add(ms(at), Origin::TriggerDevice, key(code, value));
+        }
+        h.run_for(300);
+
+        assert_eq!(h.presses(KeyCode::BTN_LEFT), vec![100, 160]);
+        // Without a grab the keyboard's own keys are left to the system
+        assert!(h.edges(KeyCode::KEY_A).is_empty());
+        assert!(h.edges(KeyCode::KEY_LEFTCTRL).is_empty());
+    }
+
+    #[test]
+    fn test_grabbed_trigger_device_passes_other_keys_on() {
+        let config = Config {
+            trigger_button: TriggerButton::KeyF13,
+            ..fixed_config()
+        };
+        let mut h = Harness::new(config, vec![]);
+        h.key_sink = Some(MemorySink::new(h.clock.clone()));
+        for (at, code, value) in [(100, KeyCode::KEY_F13, 1), (120, KeyCode::KEY_A, 1), (130, KeyCode::KEY_F13, 0)] {
+            h.source.add(ms(at), Origin::TriggerDevice, key(code, value));
+        }
+        h.run_for(200);
+
+        let passed: Vec<u16> = h.key_sink.as_ref().unwrap().emitted.iter().map(|(_, e)| e.code()).collect();
+        assert_eq!(passed, vec![KeyCode::KEY_A.0]);
+        assert_eq!(h.presses(KeyCode::BTN_LEFT), vec![100]);
+        assert!(h.edges(KeyCode::KEY_A).is_empty());
+    }
+
+    #[test]
+    fn test_recorded_session_replays_identically() {
+        // Unseeded, so only the recorded seed can make the replay match
+        let config = Config {
+            click_delay_min_ms: 40,
+            click_delay_max_ms: 90,
+            travel_time_min_ms: 5,
+            travel_time_max_ms: 30,
+            use_gaussian: true,
+            travel_jitter: true,
+            burst_mode: true,
+            ..fixed_config()
+        };
+        let script = vec![
+            (0, key(KeyCode::BTN_SIDE, 1)),
+            (700, key(KeyCode::BTN_SIDE, 0)),
+            (900, key(KeyCode::BTN_SIDE, 1)),
+            (1500, key(KeyCode::BTN_SIDE, 0)),
+        ];
+        let mut h = Harness::new(config.clone(), script);
+        let path = std::env::temp_dir().join(format!("ferrisfire-proxy-{}.ffs", std::process::id()));
+        let session = RefCell::new(SessionWriter::create(&path, &h.engine, h.clock.now()).unwrap());
+
+        let (updates_tx, updates) = std::sync::mpsc::channel();
+        let mut updated = false;
+        while h.clock.elapsed() < ms(2000) {
+            if !updated && h.clock.elapsed() >= ms(1000) {
+                updates_tx.send(Config { click_delay_min_ms: 70, ..config.clone() }).unwrap();
+                updated = true;
+            }
+            let mut sink = RecordingSink::new(&mut h.sink, Some(&session), Stream::Virtual);
+            step(&mut h.engine, &mut h.source, &mut sink, &mut None::<MemorySink>, &h.clock, &updates, Some(&session))
+                .unwrap();
+        }
+        session.borrow_mut().end(h.clock.now());
+        h.engine.shutdown(&mut RecordingSink::new(&mut h.sink, Some(&session), Stream::Virtual));
+        session.into_inner().finish();
+        let recording = SessionRecording::load(&path);
+        std::fs::remove_file(&path).ok();
+        let recording = recording.unwrap();
+
+        let clock = ManualClock::new();
+        let mut sink = MemorySink::new(clock.clone());
+        let stats = replay(&recording, &clock, &mut sink, None::<&mut MemorySink>, false);
+
+        assert!(stats.clicks > 10, "clicks: {}", stats.clicks);
+        assert_eq!(stats.clicks, h.engine.stats().clicks);
+        assert_eq!(first_difference(&recording.outputs(Stream::Virtual), &sink.emitted), None);
+        assert_eq!(first_difference(&h.sink.emitted, &sink.emitted), None);
+    }
+
+    #[test]
+    fn test_failed_reopen_waits_for_the_device_again() {
+        let stop = AtomicBool::new(false);
+        let mut finds = 0;
+        let mut opens = Vec::new();
+        let mut backoffs = Vec::new();
+        let reopened = reconnect(
+            || {
+                finds += 1;
+                Some((vec![format!("/dev/input/event{}", finds)], None))
+            },
+            |paths, _| {
+                opens.push(paths[0].clone());
+                match opens.len() {
+                    1 => Err("Failed to grab device: Device or resource busy".to_string()),
+                    2 => Err("Failed to create virtual device: Permission denied".to_string()),
+                    _ => Ok(paths[0].clone()),
+                }
+            },
+            &stop,
+            |backoff| backoffs.push(backoff),
+        );
+
+        assert_eq!(reopened.as_deref(), Some("/dev/input/event3"));
+        assert_eq!(opens, vec!["/dev/input/event1", "/dev/input/event2", "/dev/input/event3"]);
+        assert_eq!(backoffs, vec![RECONNECT_POLL, RECONNECT_POLL * 2]);
+
+        // Stopping during the backoff gives up instead of retrying
+        let reopened = reconnect(
+            || Some((vec!["/dev/input/event1".to_string()], None)),
+            |_, _| Err::<(), _>("Failed to grab device: Device or resource busy".to_string()),
+            &stop,
+            |_| stop.store(true, Ordering::Relaxed),
+        );
+        assert_eq!(reopened, None);
+    }
+
+    #[test]
+    fn test_live_update_keeps_merged_nodes() {
+        let mut h = Harness::new(fixed_config(), vec![]);
+        let config = Config {
+            extra_device_paths: vec!["/dev/input/test-buttons".to_string()],
+            ..fixed_config()
+        };
+        h.engine.apply_config(config, h.clock.now(), &mut h.sink);
+        assert!(h.engine.config.extra_device_paths.is_empty());
+    }
+}
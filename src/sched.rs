@@ -0,0 +1,121 @@
+//! Optional real-time tuning for the proxy thread: scheduling policy,
+//! memory locking and CPU affinity. Every step is best effort; the proxy
+//! runs with normal scheduling when the process lacks the privileges.
+
+use crate::config::{Config, RealtimePolicy};
+use std::io;
+
+/// Apply the config's tuning to the calling thread.
+/// Returns a message describing whatever could not be applied.
+pub fn tune_current_thread(config: &Config) -> Option<String> {
+    let mut failures = Vec::new();
+
+    if let Some(policy) = config.realtime_policy.to_libc()
+        && let Err(e) = set_scheduler(policy, config.realtime_priority)
+    {
+        failures.push(scheduler_failure(config, &e));
+    }
+    if config.lock_memory
+        && let Err(e) = lock_memory()
+    {
+        failures.push(memory_failure(&e));
+    }
+    if !config.cpu_affinity.is_empty()
+        && let Err(e) = set_affinity(&config.cpu_affinity)
+    {
+        failures.push(format!("CPU pinning to {:?} failed ({})", config.cpu_affinity, e));
+    }
+
+    if failures.is_empty() {
+        if config.realtime_policy != RealtimePolicy::Normal || config.lock_memory || !config.cpu_affinity.is_empty() {
+            log::info!("Proxy thread tuning applied");
+        }
+        None
+    } else {
+        Some(format!("Real-time tuning not fully applied: {}", failures.join("; ")))
+    }
+}
+
+fn scheduler_failure(config: &Config, e: &io::Error) -> String {
+    let name = config.realtime_policy.display_name();
+    if e.raw_os_error() == Some(libc::EPERM) {
+        format!(
+            "{} priority {} needs CAP_SYS_NICE or RLIMIT_RTPRIO >= {}, running with normal priority",
+            name, config.realtime_priority, config.realtime_priority
+        )
+    } else {
+        format!("{} failed ({}), running with normal priority", name, e)
+    }
+}
+
+fn memory_failure(e: &io::Error) -> String {
+    match e.raw_os_error() {
+        Some(libc::EPERM) | Some(libc::ENOMEM) => {
+            "locking memory needs CAP_IPC_LOCK or a larger RLIMIT_MEMLOCK".to_string()
+        }
+        _ => format!("locking memory failed ({})", e),
+    }
+}
+
+fn set_scheduler(policy: libc::c_int, priority: u8) -> io::Result<()> {
+    let param = libc::sched_param { sched_priority: priority as libc::c_int };
+    // pthread_setschedparam reports errors through its return value, not errno
+    let ret = unsafe { libc::pthread_setschedparam(libc::pthread_self(), policy, &param) };
+    if ret != 0 {
+        return Err(io::Error::from_raw_os_error(ret));
+    }
+    Ok(())
+}
+
+fn lock_memory() -> io::Result<()> {
+    if unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn set_affinity(cpus: &[usize]) -> io::Result<()> {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for &cpu in cpus {
+        if cpu >= libc::CPU_SETSIZE as usize {
+            return Err(io::Error::from_raw_os_error(libc::EINVAL));
+        }
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+    // pid 0 is the calling thread
+    let ret = unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Number of CPUs the affinity picker offers
+pub fn cpu_count() -> usize {
+    std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run in a throwaway thread so nothing sticks to the test runner
+    fn tune_in_thread(config: Config) -> Option<String> {
+        std::thread::spawn(move || tune_current_thread(&config)).join().unwrap()
+    }
+
+    #[test]
+    fn test_default_config_changes_nothing() {
+        assert_eq!(tune_in_thread(Config::default()), None);
+    }
+
+    #[test]
+    fn test_missing_cpu_is_reported() {
+        let config = Config {
+            cpu_affinity: vec![libc::CPU_SETSIZE as usize - 1],
+            ..Default::default()
+        };
+        let warning = tune_in_thread(config).unwrap();
+        assert!(warning.contains("CPU pinning"), "{}", warning);
+    }
+}
@@ -0,0 +1,535 @@
+//! Recording of a whole proxy session for bug reports and tuning, and replaying
+//! it offline. A recording holds the config the session started with (seed
+//! included), then everything the engine was handed in order: physical events,
+//! timing wakeups, live config updates and disconnects, each at its monotonic
+//! offset from the start. The virtual events that came out are recorded too,
+//! stamped with the offset of what caused them. Handing the same input to a
+//! fresh engine at the same offsets makes it draw the same timing and emit the
+//! same events, which `replay` checks against the recording.
+//!
+//! The file starts with `MAGIC` and the config as length-prefixed JSON,
+//! followed by little-endian records: a tag byte, the offset in microseconds,
+//! and for events their type, code and value (17 bytes in all).
+
+use crate::backend::{Clock, EventSink, ManualClock, MemorySink, Origin};
+use crate::config::Config;
+use crate::device::create_replay_device;
+use crate::proxy::ProxyEngine;
+use crate::stats::ProxyStats;
+use evdev::InputEvent;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8; 8] = b"FFSESS01";
+
+const TAG_MOUSE_INPUT: u8 = 0;
+const TAG_TRIGGER_INPUT: u8 = 1;
+const TAG_TICK: u8 = 2;
+const TAG_CONFIG: u8 = 3;
+const TAG_DISCONNECT: u8 = 4;
+const TAG_VIRTUAL_OUTPUT: u8 = 5;
+const TAG_PASSTHROUGH_OUTPUT: u8 = 6;
+const TAG_END: u8 = 7;
+
+/// How long freshly created replay devices get to be picked up before events flow
+const DEVICE_SETTLE: Duration = Duration::from_millis(500);
+
+/// Where an emitted event went
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    /// The virtual mouse
+    Virtual,
+    /// The virtual clone of a grabbed trigger device
+    Passthrough,
+}
+
+/// One entry of a session recording
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    /// A physical event handed to the engine
+    Input(Origin, InputEvent),
+    /// The engine advanced its click timing
+    Tick,
+    /// A live config update was applied
+    Config(Box<Config>),
+    /// The device went away and the engine let go of everything
+    Disconnect,
+    /// An event the proxy emitted
+    Output(Stream, InputEvent),
+    /// The proxy stopped and released what it held
+    End,
+}
+
+/// Writes a session recording as the proxy runs. A write error is logged and
+/// ends the recording; the proxy carries on without it.
+pub struct SessionWriter {
+    out: Option<Box<dyn Write>>,
+    start: Instant,
+    /// Offset of the last input, tick or update, which emitted events are attributed to
+    current: Duration,
+}
+
+impl SessionWriter {
+    /// Start recording to `path` for an engine created at `start`
+    pub fn create(path: &Path, engine: &ProxyEngine, start: Instant) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        Self::new(BufWriter::new(file), engine, start)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn new(mut out: impl Write + 'static, engine: &ProxyEngine, start: Instant) -> io::Result<Self> {
+        // The seed is what makes the humanized timing come out the same
+        let config = Config {
+            seed: Some(engine.seed()),
+            ..engine.config().clone()
+        };
+        out.write_all(MAGIC)?;
+        write_config(&mut out, &config)?;
+        Ok(Self {
+            out: Some(Box::new(out)),
+            start,
+            current: Duration::ZERO,
+        })
+    }
+
+    pub fn input(&mut self, now: Instant, origin: Origin, event: InputEvent) {
+        self.record(now, &Record::Input(origin, event));
+    }
+
+    pub fn tick(&mut self, now: Instant) {
+        self.record(now, &Record::Tick);
+    }
+
+    pub fn config(&mut self, now: Instant, config: &Config) {
+        self.record(now, &Record::Config(Box::new(config.clone())));
+    }
+
+    pub fn disconnect(&mut self, now: Instant) {
+        self.record(now, &Record::Disconnect);
+    }
+
+    pub fn end(&mut self, now: Instant) {
+        self.record(now, &Record::End);
+    }
+
+    /// Record emitted events as caused by the last recorded entry
+    pub fn output(&mut self, stream: Stream, events: &[InputEvent]) {
+        for event in events {
+            self.write(self.current, &Record::Output(stream, *event));
+        }
+    }
+
+    fn record(&mut self, now: Instant, record: &Record) {
+        self.current = now.saturating_duration_since(self.start);
+        self.write(self.current, record);
+    }
+
+    fn write(&mut self, at: Duration, record: &Record) {
+        let Some(out) = &mut self.out else {
+            return;
+        };
+        if let Err(e) = write_record(out, at, record) {
+            log::warn!("Failed to write the session recording, stopping it: {}", e);
+            self.out = None;
+        }
+    }
+
+    /// Write out anything still buffered
+    pub fn finish(mut self) {
+        if let Some(out) = &mut self.out
+            && let Err(e) = out.flush()
+        {
+            log::warn!("Failed to write the session recording: {}", e);
+        }
+    }
+}
+
+fn write_config(out: &mut impl Write, config: &Config) -> io::Result<()> {
+    let json = serde_json::to_vec(config)?;
+    out.write_all(&(json.len() as u32).to_le_bytes())?;
+    out.write_all(&json)
+}
+
+fn write_record(out: &mut impl Write, at: Duration, record: &Record) -> io::Result<()> {
+    let (tag, event) = match record {
+        Record::Input(Origin::Mouse, event) => (TAG_MOUSE_INPUT, Some(event)),
+        Record::Input(Origin::TriggerDevice, event) => (TAG_TRIGGER_INPUT, Some(event)),
+        Record::Tick => (TAG_TICK, None),
+        Record::Config(_) => (TAG_CONFIG, None),
+        Record::Disconnect => (TAG_DISCONNECT, None),
+        Record::Output(Stream::Virtual, event) => (TAG_VIRTUAL_OUTPUT, Some(event)),
+        Record::Output(Stream::Passthrough, event) => (TAG_PASSTHROUGH_OUTPUT, Some(event)),
+        Record::End => (TAG_END, None),
+    };
+    out.write_all(&[tag])?;
+    out.write_all(&(at.as_micros() as u64).to_le_bytes())?;
+    if let Some(event) = event {
+        out.write_all(&event.event_type().0.to_le_bytes())?;
+        out.write_all(&event.code().to_le_bytes())?;
+        out.write_all(&event.value().to_le_bytes())?;
+    }
+    if let Record::Config(config) = record {
+        write_config(out, config)?;
+    }
+    Ok(())
+}
+
+/// A session recording read back from a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionRecording {
+    /// Config the session started with, including the seed of its timing
+    pub config: Config,
+    /// Every entry with its offset from the start, in order
+    pub records: Vec<(Duration, Record)>,
+}
+
+impl SessionRecording {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::read(BufReader::new(file)).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+    }
+
+    /// Parse a recording; one cut short, e.g. by a crash, is read up to its last whole record
+    pub fn read(mut input: impl Read) -> Result<Self, String> {
+        let magic: [u8; 8] = read_array(&mut input).map_err(|e| e.to_string())?;
+        if &magic != MAGIC {
+            return Err("not a FerrisFire session recording".to_string());
+        }
+        let config = read_config(&mut input)?;
+
+        let mut records = Vec::new();
+        loop {
+            let mut tag = [0u8];
+            if input.read(&mut tag).map_err(|e| e.to_string())? == 0 {
+                break;
+            }
+            match read_record(&mut input, tag[0]) {
+                Ok(record) => records.push(record),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    log::warn!("Session recording is cut short, replaying what is there");
+                    break;
+                }
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+        Ok(Self { config, records })
+    }
+
+    /// Events emitted to `stream`, with the offset of what caused them
+    pub fn outputs(&self, stream: Stream) -> Vec<(Duration, InputEvent)> {
+        self.records
+            .iter()
+            .filter_map(|(at, record)| match record {
+                Record::Output(to, event) if *to == stream => Some((*at, *event)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Offset of the last entry
+    pub fn duration(&self) -> Duration {
+        self.records.last().map_or(Duration::ZERO, |(at, _)| *at)
+    }
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_config(input: &mut impl Read) -> Result<Config, String> {
+    let len = u32::from_le_bytes(read_array(input).map_err(|e| e.to_string())?) as usize;
+    let mut json = vec![0u8; len];
+    input.read_exact(&mut json).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&json).map_err(|e| format!("invalid config: {}", e))
+}
+
+fn read_record(input: &mut impl Read, tag: u8) -> io::Result<(Duration, Record)> {
+    let at = Duration::from_micros(u64::from_le_bytes(read_array(input)?));
+    let mut event = || -> io::Result<InputEvent> {
+        let event_type = u16::from_le_bytes(read_array(input)?);
+        let code = u16::from_le_bytes(read_array(input)?);
+        let value = i32::from_le_bytes(read_array(input)?);
+        Ok(InputEvent::new(event_type, code, value))
+    };
+    let record = match tag {
+        TAG_MOUSE_INPUT => Record::Input(Origin::Mouse, event()?),
+        TAG_TRIGGER_INPUT => Record::Input(Origin::TriggerDevice, event()?),
+        TAG_TICK => Record::Tick,
+        TAG_CONFIG => {
+            let config = read_config(input).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            Record::Config(Box::new(config))
+        }
+        TAG_DISCONNECT => Record::Disconnect,
+        TAG_VIRTUAL_OUTPUT => Record::Output(Stream::Virtual, event()?),
+        TAG_PASSTHROUGH_OUTPUT => Record::Output(Stream::Passthrough, event()?),
+        TAG_END => Record::End,
+        other => {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown record type {}", other)));
+        }
+    };
+    Ok((at, record))
+}
+
+/// Hand a fresh engine the recorded input at the recorded offsets of `clock`,
+/// sending what it emits to `sink` and, when the trigger device was grabbed,
+/// `passthrough`. With `paced` the replay waits out the recorded gaps in real
+/// time; otherwise it runs as fast as it can. Returns the replayed run's stats.
+pub fn replay(
+    recording: &SessionRecording,
+    clock: &ManualClock,
+    sink: &mut impl EventSink,
+    mut passthrough: Option<&mut impl EventSink>,
+    paced: bool,
+) -> ProxyStats {
+    let mut engine = ProxyEngine::new(recording.config.clone(), clock.now());
+    let started = Instant::now();
+    for (at, record) in &recording.records {
+        if *at > clock.elapsed() {
+            clock.advance(*at - clock.elapsed());
+        }
+        if paced {
+            thread::sleep(at.saturating_sub(started.elapsed()));
+        }
+        // Latency of the original events is not recorded; replayed ones count as immediate
+        let forwarded = match record {
+            Record::Input(Origin::Mouse, event) => engine.handle_event(*event, sink),
+            Record::Input(Origin::TriggerDevice, event) => {
+                engine.handle_trigger_device_event(*event, sink, passthrough.as_deref_mut())
+            }
+            Record::Tick => {
+                engine.tick(clock.now(), sink);
+                false
+            }
+            Record::Config(config) => {
+                engine.apply_config((**config).clone(), clock.now(), sink);
+                false
+            }
+            Record::Disconnect => {
+                engine.disconnect(sink);
+                false
+            }
+            Record::End => {
+                engine.shutdown(sink);
+                false
+            }
+            Record::Output(..) => false,
+        };
+        if forwarded {
+            engine.record_forward(clock.wall_time(), clock.wall_time());
+        }
+    }
+    engine.stats().clone()
+}
+
+/// Describe the first difference between recorded and replayed output, or
+/// `None` if they match event for event
+pub fn first_difference(recorded: &[(Duration, InputEvent)], replayed: &[(Duration, InputEvent)]) -> Option<String> {
+    let describe = |entry: Option<&(Duration, InputEvent)>| match entry {
+        Some((at, event)) => format!(
+            "{:?} {} = {} at {:.3} ms",
+            event.event_type(),
+            event.code(),
+            event.value(),
+            at.as_secs_f64() * 1000.0
+        ),
+        None => "nothing".to_string(),
+    };
+    let same = |a: &(Duration, InputEvent), b: &(Duration, InputEvent)| {
+        a.0 == b.0 && a.1.event_type() == b.1.event_type() && a.1.code() == b.1.code() && a.1.value() == b.1.value()
+    };
+    let idx = (0..recorded.len().max(replayed.len()))
+        .find(|idx| !matches!((recorded.get(*idx), replayed.get(*idx)), (Some(a), Some(b)) if same(a, b)))?;
+    Some(format!(
+        "event {} was {} in the recording but {} in the replay",
+        idx + 1,
+        describe(recorded.get(idx)),
+        describe(replayed.get(idx))
+    ))
+}
+
+/// Replay the session recorded at `path` offline and check that it reproduces
+/// the recorded output. With `virtual_device` it is then played once more in
+/// real time through fresh virtual devices, to watch it happen.
+pub fn run_replay(path: &Path, virtual_device: bool) -> Result<(), String> {
+    let recording = SessionRecording::load(path)?;
+    // Keys on a grabbed trigger device are passed on to its own virtual clone
+    let grabbed = recording.config.grab_trigger_device;
+
+    let clock = ManualClock::new();
+    let mut sink = MemorySink::new(clock.clone());
+    let mut passthrough = MemorySink::new(clock.clone());
+    let stats = replay(&recording, &clock, &mut sink, grabbed.then_some(&mut passthrough), false);
+    println!(
+        "Replayed {:.1} s of input: {} events emitted, {} clicks",
+        recording.duration().as_secs_f64(),
+        sink.emitted.len() + passthrough.emitted.len(),
+        stats.clicks
+    );
+
+    let difference = first_difference(&recording.outputs(Stream::Virtual), &sink.emitted).or_else(|| {
+        first_difference(&recording.outputs(Stream::Passthrough), &passthrough.emitted)
+            .map(|difference| format!("passed-on {}", difference))
+    });
+    if let Some(difference) = difference {
+        return Err(format!("Replay differs from the recording: {}", difference));
+    }
+    println!("Output is identical to the recording");
+
+    if virtual_device {
+        play_on_virtual_devices(&recording, grabbed)?;
+    }
+    Ok(())
+}
+
+fn play_on_virtual_devices(recording: &SessionRecording, grabbed: bool) -> Result<(), String> {
+    let outputs = |stream| -> Vec<InputEvent> {
+        recording.outputs(stream).into_iter().map(|(_, event)| event).collect()
+    };
+    let mut mouse = create_replay_device(
+        "FerrisFire Replay",
+        &outputs(Stream::Virtual),
+        &recording.config.all_output_keys(),
+        true,
+    )
+    .map_err(|e| format!("Failed to create virtual device: {}", e))?;
+    let mut keys = match grabbed {
+        true => Some(
+            create_replay_device("FerrisFire Replay Keys", &outputs(Stream::Passthrough), &[], false)
+                .map_err(|e| format!("Failed to create virtual trigger device: {}", e))?,
+        ),
+        false => None,
+    };
+    thread::sleep(DEVICE_SETTLE);
+
+    println!("Playing the session on virtual devices...");
+    let clock = ManualClock::new();
+    replay(recording, &clock, &mut mouse, keys.as_mut(), true);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TriggerButton;
+    use evdev::{EventType, KeyCode};
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    fn key(code: KeyCode, value: i32) -> InputEvent {
+        InputEvent::new(EventType::KEY.0, code.0, value)
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("ferrisfire-{}-{}.ffs", name, std::process::id()))
+    }
+
+    fn fixed_config() -> Config {
+        Config {
+            device_path: "/dev/input/test".to_string(),
+            trigger_button: TriggerButton::Mouse4,
+            click_delay_min_ms: 50,
+            click_delay_max_ms: 50,
+            travel_time_min_ms: 10,
+            travel_time_max_ms: 10,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_records_round_trip() {
+        let path = temp_path("roundtrip");
+        let start = Instant::now();
+        let engine = ProxyEngine::new(fixed_config(), start);
+        let mut writer = SessionWriter::create(&path, &engine, start).unwrap();
+        writer.input(start + ms(5), Origin::TriggerDevice, key(KeyCode::KEY_F13, 1));
+        writer.tick(start + ms(6));
+        writer.output(Stream::Virtual, &[key(KeyCode::BTN_LEFT, 1)]);
+        writer.config(start + ms(7), &fixed_config());
+        writer.disconnect(start + ms(8));
+        writer.end(start + ms(9));
+        writer.finish();
+        let recording = SessionRecording::load(&path);
+        std::fs::remove_file(&path).ok();
+
+        let recording = recording.unwrap();
+        assert_eq!(recording.config.seed, Some(engine.seed()));
+        assert_eq!(
+            recording.records,
+            vec![
+                (ms(5), Record::Input(Origin::TriggerDevice, key(KeyCode::KEY_F13, 1))),
+                (ms(6), Record::Tick),
+                (ms(6), Record::Output(Stream::Virtual, key(KeyCode::BTN_LEFT, 1))),
+                (ms(7), Record::Config(Box::new(fixed_config()))),
+                (ms(8), Record::Disconnect),
+                (ms(9), Record::End),
+            ]
+        );
+        assert_eq!(recording.outputs(Stream::Virtual), vec![(ms(6), key(KeyCode::BTN_LEFT, 1))]);
+        assert_eq!(recording.duration(), ms(9));
+    }
+
+    #[test]
+    fn test_truncated_recording_keeps_whole_records() {
+        let mut bytes = MAGIC.to_vec();
+        write_config(&mut bytes, &fixed_config()).unwrap();
+        write_record(&mut bytes, ms(1), &Record::Tick).unwrap();
+        write_record(&mut bytes, ms(2), &Record::Input(Origin::Mouse, key(KeyCode::BTN_SIDE, 1))).unwrap();
+        bytes.truncate(bytes.len() - 3);
+
+        let recording = SessionRecording::read(bytes.as_slice()).unwrap();
+        assert_eq!(recording.records, vec![(ms(1), Record::Tick)]);
+
+        assert_eq!(
+            SessionRecording::read(&b"{\"device_path\":1}"[..]).unwrap_err(),
+            "not a FerrisFire session recording"
+        );
+    }
+
+    #[test]
+    fn test_replay_into_memory() {
+        let mut bytes = MAGIC.to_vec();
+        write_config(&mut bytes, &fixed_config()).unwrap();
+        for (at, record) in [
+            (ms(100), Record::Input(Origin::Mouse, key(KeyCode::BTN_SIDE, 1))),
+            (ms(100), Record::Tick),
+            (ms(110), Record::Tick),
+            (ms(163), Record::Tick),
+            (ms(165), Record::Input(Origin::Mouse, key(KeyCode::BTN_SIDE, 0))),
+            (ms(170), Record::End),
+        ] {
+            write_record(&mut bytes, at, &record).unwrap();
+        }
+        let recording = SessionRecording::read(bytes.as_slice()).unwrap();
+
+        let clock = ManualClock::new();
+        let mut sink = MemorySink::new(clock.clone());
+        let stats = replay(&recording, &clock, &mut sink, None::<&mut MemorySink>, false);
+
+        let left: Vec<(Duration, i32)> = sink
+            .emitted
+            .iter()
+            .filter(|(_, e)| e.code() == KeyCode::BTN_LEFT.0)
+            .map(|(at, e)| (*at, e.value()))
+            .collect();
+        // Timing follows the recorded wakeups, so the late one at 163 starts the second click
+        assert_eq!(left, vec![(ms(100), 1), (ms(110), 0), (ms(163), 1), (ms(165), 0)]);
+        assert_eq!(stats.clicks, 1);
+    }
+
+    #[test]
+    fn test_first_difference() {
+        let recorded = vec![(ms(1), key(KeyCode::BTN_LEFT, 1)), (ms(2), key(KeyCode::BTN_LEFT, 0))];
+        assert_eq!(first_difference(&recorded, &recorded), None);
+        assert_eq!(
+            first_difference(&recorded, &recorded[..1]).unwrap(),
+            "event 2 was KEY 272 = 0 at 2.000 ms in the recording but nothing in the replay"
+        );
+    }
+}
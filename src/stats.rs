@@ -0,0 +1,192 @@
+//! Counters and timing histograms collected by the running proxy. The proxy
+//! thread publishes a snapshot periodically; the GUI shows it and the daemon
+//! hands it out as JSON through `ctl stats`.
+
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Bucket upper bounds for press-to-press intervals, in microseconds
+const INTERVAL_BOUNDS_US: &[u64] = &[
+    20_000, 30_000, 40_000, 50_000, 60_000, 70_000, 80_000, 100_000, 125_000, 150_000, 200_000, 300_000,
+];
+/// Bucket upper bounds for how long each virtual click was held, in microseconds
+const TRAVEL_BOUNDS_US: &[u64] = &[
+    5_000, 10_000, 15_000, 20_000, 25_000, 30_000, 40_000, 50_000, 75_000, 100_000,
+];
+/// Bucket upper bounds for physical event to virtual emit latency, in microseconds
+const LATENCY_BOUNDS_US: &[u64] = &[25, 50, 100, 250, 500, 1_000, 2_000, 5_000, 10_000];
+
+/// Fixed-bucket histogram of durations, kept in microseconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    /// Inclusive upper bound of each bucket; values above the last go in a final overflow bucket
+    pub bounds_us: Vec<u64>,
+    /// One count per bound plus the overflow bucket
+    pub counts: Vec<u64>,
+    pub count: u64,
+    pub sum_us: u64,
+    pub min_us: Option<u64>,
+    pub max_us: Option<u64>,
+}
+
+impl Histogram {
+    pub fn new(bounds_us: &[u64]) -> Self {
+        Self {
+            bounds_us: bounds_us.to_vec(),
+            counts: vec![0; bounds_us.len() + 1],
+            count: 0,
+            sum_us: 0,
+            min_us: None,
+            max_us: None,
+        }
+    }
+
+    pub fn record(&mut self, value: Duration) {
+        let us = value.as_micros().min(u64::MAX as u128) as u64;
+        let bucket = self.bounds_us.partition_point(|bound| *bound < us);
+        self.counts[bucket] += 1;
+        self.count += 1;
+        self.sum_us = self.sum_us.saturating_add(us);
+        self.min_us = Some(self.min_us.map_or(us, |min| min.min(us)));
+        self.max_us = Some(self.max_us.map_or(us, |max| max.max(us)));
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        (self.count > 0).then(|| Duration::from_micros(self.sum_us / self.count))
+    }
+
+    /// Upper bound of the bucket holding the `quantile` (0.0-1.0) sample,
+    /// capped at the largest value seen
+    pub fn percentile(&self, quantile: f64) -> Option<Duration> {
+        let max = self.max_us?;
+        let rank = ((self.count as f64 * quantile.clamp(0.0, 1.0)).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (idx, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let bound = self.bounds_us.get(idx).copied().unwrap_or(max);
+                return Some(Duration::from_micros(bound.min(max)));
+            }
+        }
+        Some(Duration::from_micros(max))
+    }
+}
+
+/// What the proxy did during one run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProxyStats {
+    /// Completed virtual clicks (press and release)
+    pub clicks: u64,
+    /// Physical events passed through to the virtual device
+    pub forwarded_events: u64,
+    /// Time between consecutive presses while rapid-fire is held
+    pub click_interval: Histogram,
+    /// How long each virtual click was held down
+    pub travel_time: Histogram,
+    /// Delay between the kernel timestamp of a physical event and its re-emission
+    pub forward_latency: Histogram,
+    /// Whether each binding is firing right now (held, latched or counting clicks), primary first
+    #[serde(default)]
+    pub firing: Vec<bool>,
+    /// Times the device was unplugged and came back
+    #[serde(default)]
+    pub reconnects: u64,
+    /// Whether the device is unplugged and the proxy is waiting for it
+    #[serde(default)]
+    pub waiting_for_device: bool,
+}
+
+impl Default for ProxyStats {
+    fn default() -> Self {
+        Self {
+            clicks: 0,
+            forwarded_events: 0,
+            click_interval: Histogram::new(INTERVAL_BOUNDS_US),
+            travel_time: Histogram::new(TRAVEL_BOUNDS_US),
+            forward_latency: Histogram::new(LATENCY_BOUNDS_US),
+            firing: Vec::new(),
+            reconnects: 0,
+            waiting_for_device: false,
+        }
+    }
+}
+
+impl ProxyStats {
+    /// Clicks per second actually achieved while firing, from the mean press interval
+    pub fn clicks_per_second(&self) -> Option<f64> {
+        self.click_interval
+            .mean()
+            .filter(|mean| !mean.is_zero())
+            .map(|mean| 1.0 / mean.as_secs_f64())
+    }
+}
+
+/// Latest stats snapshot, written by the proxy thread and read by its owner
+pub type SharedStats = Arc<Mutex<ProxyStats>>;
+
+/// Read the latest published snapshot
+pub fn snapshot(shared: &SharedStats) -> ProxyStats {
+    shared.lock().map(|stats| stats.clone()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn test_empty_histogram() {
+        let histogram = Histogram::new(TRAVEL_BOUNDS_US);
+        assert_eq!(histogram.mean(), None);
+        assert_eq!(histogram.percentile(0.5), None);
+        assert_eq!(histogram.counts.len(), TRAVEL_BOUNDS_US.len() + 1);
+    }
+
+    #[test]
+    fn test_histogram_buckets_and_summary() {
+        let mut histogram = Histogram::new(&[10_000, 20_000]);
+        for value in [ms(5), ms(10), ms(15), ms(40)] {
+            histogram.record(value);
+        }
+
+        // Bounds are inclusive; 40ms lands in the overflow bucket
+        assert_eq!(histogram.counts, vec![2, 1, 1]);
+        assert_eq!(histogram.mean(), Some(Duration::from_micros(17_500)));
+        assert_eq!(histogram.min_us, Some(5_000));
+        assert_eq!(histogram.max_us, Some(40_000));
+        assert_eq!(histogram.percentile(0.5), Some(ms(10)));
+        assert_eq!(histogram.percentile(0.75), Some(ms(20)));
+        assert_eq!(histogram.percentile(1.0), Some(ms(40)));
+    }
+
+    #[test]
+    fn test_percentile_capped_at_max() {
+        let mut histogram = Histogram::new(&[10_000]);
+        histogram.record(ms(2));
+        assert_eq!(histogram.percentile(0.99), Some(ms(2)));
+    }
+
+    #[test]
+    fn test_clicks_per_second() {
+        let mut stats = ProxyStats::default();
+        assert_eq!(stats.clicks_per_second(), None);
+        stats.click_interval.record(ms(50));
+        stats.click_interval.record(ms(150));
+        assert_eq!(stats.clicks_per_second(), Some(10.0));
+    }
+
+    #[test]
+    fn test_stats_serialization_roundtrip() {
+        let mut stats = ProxyStats {
+            clicks: 3,
+            ..Default::default()
+        };
+        stats.forward_latency.record(Duration::from_micros(80));
+        let json = serde_json::to_string(&stats).unwrap();
+        assert_eq!(serde_json::from_str::<ProxyStats>(&json).unwrap(), stats);
+    }
+}